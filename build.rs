@@ -0,0 +1,8 @@
+fn main() {
+    // 使用内置的protoc,编译时不需要另外安装
+    std::env::set_var(
+        "PROTOC",
+        protoc_bin_vendored::protoc_bin_path().expect("failed to find protoc"),
+    );
+    tonic_build::compile_protos("proto/bitcask.proto").expect("failed to compile protos");
+}
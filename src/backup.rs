@@ -0,0 +1,766 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use log::error;
+
+use crate::{
+    data::{
+        data_file::{
+            DataFile, BLOB_NEXT_FILE_ID_FILE_NAME, HIT_FILE_NAME, MERGE_FINISHED_FILE_NAME,
+        },
+        log_record::{LogRecord, LogRecordPos, LogRecordType},
+    },
+    db::Engine,
+    errors::{Errors, Result},
+    options::Options,
+};
+
+pub const BACKUP_MANIFEST_FILE_NAME: &str = "backup-manifest";
+const MANIFEST_MERGE_GENERATION: &[u8] = b"merge-generation";
+const MANIFEST_DATA_FILE: &[u8] = b"data";
+const MANIFEST_BLOB_FILE: &[u8] = b"blob";
+// 文件标识只校验开头和结尾的这么多字节,不需要读取整个文件
+const FILE_IDENTITY_BYTES: u64 = 4096;
+
+// 备份的类型
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackupKind {
+    Full,
+    Incremental,
+}
+
+// 备份目录中已经有的文件,用于下一次增量备份
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct BackupManifest {
+    // 备份时数据目录中没有参与merge的第一个文件id,merge之后会变化
+    pub(crate) merge_generation: u32,
+    // 文件id -> 已经拷贝的部分
+    pub(crate) data_files: BTreeMap<u32, BackupFile>,
+    pub(crate) blob_files: BTreeMap<u32, BackupFile>,
+}
+
+// 备份中的一个文件
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct BackupFile {
+    // 已经拷贝的大小
+    pub(crate) size: u64,
+    // 前size个字节的标识,同一个id的文件被删除后重新创建时标识不同,不能在它后面追加
+    pub(crate) identity: u32,
+}
+
+impl Engine {
+    // 在线备份到target_dir,备份期间可以继续写入
+    // 先固定活跃文件的边界,然后硬链接(或者拷贝)不会再变化的文件,活跃文件只拷贝到固定的位置
+    // 备份目录可以直接作为dir_path打开,也可以通过Engine::restore恢复
+    pub fn backup(&self, target_dir: PathBuf) -> Result<()> {
+        create_empty_dir(&target_dir)?;
+        let _guard = self.blob_files.remove_lock.read();
+        let manifest = self.freeze_backup_manifest()?;
+        let dir_path = self.options.dir_path.clone();
+        let active_file_id = manifest.data_files.keys().max().copied();
+        for (file_id, file) in manifest.data_files.iter() {
+            let src = DataFile::get_file_name(dir_path.clone(), *file_id);
+            let dst = DataFile::get_file_name(target_dir.clone(), *file_id);
+            match Some(*file_id) == active_file_id {
+                true => copy_prefix(&src, &dst, file.size)?,
+                false => link_or_copy(&src, &dst)?,
+            }
+        }
+        for (file_id, file) in manifest.blob_files.iter() {
+            copy_prefix(
+                &DataFile::get_blob_file_name(dir_path.clone(), *file_id),
+                &DataFile::get_blob_file_name(target_dir.clone(), *file_id),
+                file.size,
+            )?;
+        }
+        self.backup_merge_files(&target_dir)?;
+        save_manifest(&target_dir, &manifest)
+    }
+
+    // 增量备份到target_dir,只拷贝上次备份之后新增的文件和文件新增的部分
+    // target_dir中没有之前的备份,之后发生过merge,或者备份过的文件被重新创建时,退化为全量备份
+    pub fn incremental_backup(&self, target_dir: PathBuf) -> Result<BackupKind> {
+        // 没有manifest的非空目录不是之前的备份,和backup一样拒绝覆盖
+        let base = match load_manifest(&target_dir)? {
+            Some(base) => base,
+            None => {
+                self.backup(target_dir)?;
+                return Ok(BackupKind::Full);
+            }
+        };
+        let _guard = self.blob_files.remove_lock.read();
+        let manifest = self.freeze_backup_manifest()?;
+        if !is_incremental_base(&base, &manifest, &self.options.dir_path, &target_dir)? {
+            drop(_guard);
+            self.replace_with_full_backup(&target_dir)?;
+            return Ok(BackupKind::Full);
+        }
+        let dir_path = self.options.dir_path.clone();
+        let active_file_id = manifest.data_files.keys().max().copied();
+        for (file_id, file) in manifest.data_files.iter() {
+            let src = DataFile::get_file_name(dir_path.clone(), *file_id);
+            let dst = DataFile::get_file_name(target_dir.clone(), *file_id);
+            match base.data_files.get(file_id) {
+                Some(base_file) => copy_tail(&src, &dst, base_file.size, file.size)?,
+                None if Some(*file_id) == active_file_id => copy_prefix(&src, &dst, file.size)?,
+                None => link_or_copy(&src, &dst)?,
+            }
+        }
+        for (file_id, file) in manifest.blob_files.iter() {
+            let src = DataFile::get_blob_file_name(dir_path.clone(), *file_id);
+            let dst = DataFile::get_blob_file_name(target_dir.clone(), *file_id);
+            match base.blob_files.get(file_id) {
+                Some(base_file) => copy_tail(&src, &dst, base_file.size, file.size)?,
+                None => copy_prefix(&src, &dst, file.size)?,
+            }
+        }
+        // 先同步文件再更新manifest,中途失败时下次会从老的manifest重新开始
+        sync_dir(&target_dir)?;
+        save_manifest(&target_dir, &manifest)?;
+        Ok(BackupKind::Incremental)
+    }
+
+    // 先在target_dir旁边的临时目录中做全量备份,成功之后再替换掉target_dir,
+    // 全量备份中途失败时原来的备份仍然可用
+    fn replace_with_full_backup(&self, target_dir: &Path) -> Result<()> {
+        let tmp_dir = sibling_dir(target_dir, "tmp")?;
+        let old_dir = sibling_dir(target_dir, "old")?;
+        // 清理上次中途失败留下的临时目录
+        clear_dir(&tmp_dir)?;
+        clear_dir(&old_dir)?;
+        self.backup(tmp_dir.clone())?;
+        let res = fs::rename(target_dir, &old_dir).and_then(|_| fs::rename(&tmp_dir, target_dir));
+        if let Err(e) = res {
+            error!("failed to replace backup dir: {}", e);
+            return Err(Errors::FailWriteDataToFile);
+        }
+        if let Some(parent) = target_dir.parent().filter(|p| !p.as_os_str().is_empty()) {
+            sync_dir(parent)?;
+        }
+        clear_dir(&old_dir)
+    }
+
+    // 拿写锁持久化活跃文件,此时的文件列表和文件大小就是备份的边界
+    // 调用方需要持有blob的remove_lock
+    fn freeze_backup_manifest(&self) -> Result<BackupManifest> {
+        let mut manifest = BackupManifest {
+            merge_generation: Engine::no_merged_file_id(self.options.dir_path.clone())?,
+            ..Default::default()
+        };
+        {
+            let active_file_write_guard = self.data_file.write();
+            self.blob_files.sync()?;
+            self.sync_state.sync(&active_file_write_guard)?;
+            for (file_id, old_file) in self.old_files.read().iter() {
+                manifest.data_files.insert(
+                    *file_id,
+                    BackupFile {
+                        size: old_file.get_wtite_offset(),
+                        identity: 0,
+                    },
+                );
+            }
+            manifest.data_files.insert(
+                active_file_write_guard.get_file_id(),
+                BackupFile {
+                    size: active_file_write_guard.get_wtite_offset(),
+                    identity: 0,
+                },
+            );
+        }
+        // blob先于指向它的日志写入,边界之后读取的blob大小一定包含了所有被引用的blob
+        for (file_id, size) in self.blob_files.file_sizes() {
+            manifest
+                .blob_files
+                .insert(file_id, BackupFile { size, identity: 0 });
+        }
+        // 边界之前的内容不会再变化,释放锁之后再计算标识
+        let dir_path = self.options.dir_path.clone();
+        for (file_id, file) in manifest.data_files.iter_mut() {
+            let src = DataFile::get_file_name(dir_path.clone(), *file_id);
+            file.identity = file_identity(&src, file.size)?;
+        }
+        for (file_id, file) in manifest.blob_files.iter_mut() {
+            let src = DataFile::get_blob_file_name(dir_path.clone(), *file_id);
+            file.identity = file_identity(&src, file.size)?;
+        }
+        Ok(manifest)
+    }
+
+    // merge之后的hint文件和blob文件id的上界只会被整体替换,不会被修改
+    fn backup_merge_files(&self, target_dir: &Path) -> Result<()> {
+        for file_name in [
+            HIT_FILE_NAME,
+            MERGE_FINISHED_FILE_NAME,
+            BLOB_NEXT_FILE_ID_FILE_NAME,
+        ] {
+            let src = self.options.dir_path.join(file_name);
+            if src.is_file() {
+                link_or_copy(&src, &target_dir.join(file_name))?;
+            }
+        }
+        Ok(())
+    }
+
+    // 在dir中创建一个checkpoint,所有文件都是硬链接,dir需要和数据目录在同一个文件系统
+    // 先像写满一样切换活跃文件,这样所有数据都在不会再变化的文件中
+    pub fn checkpoint(&self, dir: PathBuf) -> Result<()> {
+        // 切换活跃文件会让副本的文件和leader不一致
+        self.check_writable()?;
+        create_empty_dir(&dir)?;
+        let dir_path = self.options.dir_path.clone();
+        let _guard = self.blob_files.remove_lock.read();
+        let (old_file_ids, active_file_id, blob_file_ids) = {
+            let mut active_file_write_guard = self.data_file.write();
+            if active_file_write_guard.get_wtite_offset() > 0 {
+                self.rotate_active_file(&mut active_file_write_guard)?;
+            }
+            self.blob_files.seal()?;
+            let mut old_file_ids: Vec<u32> = self.old_files.read().keys().copied().collect();
+            old_file_ids.sort();
+            (
+                old_file_ids,
+                active_file_write_guard.get_file_id(),
+                self.blob_files.old_file_ids(),
+            )
+        };
+
+        for file_id in old_file_ids {
+            hard_link(
+                &DataFile::get_file_name(dir_path.clone(), file_id),
+                &DataFile::get_file_name(dir.clone(), file_id),
+            )?;
+        }
+        for file_id in blob_file_ids {
+            hard_link(
+                &DataFile::get_blob_file_name(dir_path.clone(), file_id),
+                &DataFile::get_blob_file_name(dir.clone(), file_id),
+            )?;
+        }
+        for file_name in [
+            HIT_FILE_NAME,
+            MERGE_FINISHED_FILE_NAME,
+            BLOB_NEXT_FILE_ID_FILE_NAME,
+        ] {
+            let src = dir_path.join(file_name);
+            if src.is_file() {
+                hard_link(&src, &dir.join(file_name))?;
+            }
+        }
+        // checkpoint中单独创建一个空的活跃文件,打开之后的写入不会修改硬链接的文件
+        DataFile::new(dir.clone(), active_file_id)?;
+        sync_dir(&dir)
+    }
+
+    // 把备份恢复到options.dir_path并打开,目标目录需要是空的
+    pub fn restore(backup_dir: PathBuf, options: Options) -> Result<Engine> {
+        create_empty_dir(&options.dir_path)?;
+        let read_dir = match fs::read_dir(&backup_dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) => {
+                error!("failed to read backup dir: {}", e);
+                return Err(Errors::DirPathReadFailed);
+            }
+        };
+        for entry in read_dir.flatten() {
+            if entry.path().is_file() && entry.file_name() != BACKUP_MANIFEST_FILE_NAME {
+                copy_file(&entry.path(), &options.dir_path.join(entry.file_name()))?;
+            }
+        }
+        sync_dir(&options.dir_path)?;
+        Engine::open(options)
+    }
+}
+
+// 上次备份之后数据目录没有merge过,并且备份中的文件都还在,
+// 数据目录中对应的文件也还是原来的文件,才能在它的基础上做增量备份
+fn is_incremental_base(
+    base: &BackupManifest,
+    manifest: &BackupManifest,
+    dir_path: &Path,
+    target_dir: &Path,
+) -> Result<bool> {
+    if base.merge_generation != manifest.merge_generation {
+        return Ok(false);
+    }
+    for (file_id, base_file) in base.data_files.iter() {
+        let file = match manifest.data_files.get(file_id) {
+            Some(file) if file.size >= base_file.size => file,
+            _ => return Ok(false),
+        };
+        let src = DataFile::get_file_name(dir_path.to_path_buf(), *file_id);
+        if !DataFile::get_file_name(target_dir.to_path_buf(), *file_id).is_file()
+            || !same_identity(&src, base_file, file)?
+        {
+            return Ok(false);
+        }
+    }
+    // blob文件可能被压缩删除,新的数据会写到新的blob文件中
+    for (file_id, base_file) in base.blob_files.iter() {
+        if !DataFile::get_blob_file_name(target_dir.to_path_buf(), *file_id).is_file() {
+            return Ok(false);
+        }
+        let file = match manifest.blob_files.get(file_id) {
+            Some(file) if file.size >= base_file.size => file,
+            Some(_) => return Ok(false),
+            None => continue,
+        };
+        let src = DataFile::get_blob_file_name(dir_path.to_path_buf(), *file_id);
+        if !same_identity(&src, base_file, file)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// 数据目录中的文件的前base_file.size个字节是否还是上次备份的内容
+fn same_identity(src: &Path, base_file: &BackupFile, file: &BackupFile) -> Result<bool> {
+    let identity = match file.size == base_file.size {
+        true => file.identity,
+        false => file_identity(src, base_file.size)?,
+    };
+    Ok(identity == base_file.identity)
+}
+
+// 文件前size个字节中开头和结尾各FILE_IDENTITY_BYTES个字节的crc
+fn file_identity(path: &Path, size: u64) -> Result<u32> {
+    let res = File::open(path).and_then(|mut file| {
+        let mut hasher = crc32fast::Hasher::new();
+        let head = size.min(FILE_IDENTITY_BYTES);
+        let tail_start = size.saturating_sub(FILE_IDENTITY_BYTES).max(head);
+        for (start, end) in [(0, head), (tail_start, size)] {
+            let mut buf = vec![0; (end - start) as usize];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut buf)?;
+            hasher.update(&buf);
+        }
+        Ok(hasher.finalize())
+    });
+    match res {
+        Ok(identity) => Ok(identity),
+        Err(e) => {
+            error!("failed to read file {:?}: {}", path, e);
+            Err(Errors::FailReadFromFile)
+        }
+    }
+}
+
+pub(crate) fn load_manifest(target_dir: &Path) -> Result<Option<BackupManifest>> {
+    let buf = match fs::read(target_dir.join(BACKUP_MANIFEST_FILE_NAME)) {
+        Ok(buf) => buf,
+        Err(_) => return Ok(None),
+    };
+    let mut manifest = BackupManifest::default();
+    let mut offset = 0;
+    while let Some(read_log_record) = DataFile::decode_log_record(&buf[offset..]) {
+        let read_log_record = read_log_record?;
+        offset += read_log_record.size as usize;
+        // 文件的大小和标识记录在offset和value_size中
+        let pos = LogRecordPos::decode(read_log_record.logrecord.value);
+        let file = BackupFile {
+            size: pos.offset,
+            identity: pos.value_size,
+        };
+        match read_log_record.logrecord.key.as_slice() {
+            MANIFEST_MERGE_GENERATION => manifest.merge_generation = pos.file_id,
+            MANIFEST_DATA_FILE => {
+                manifest.data_files.insert(pos.file_id, file);
+            }
+            MANIFEST_BLOB_FILE => {
+                manifest.blob_files.insert(pos.file_id, file);
+            }
+            _ => return Err(Errors::DataFileCorrupted),
+        }
+    }
+    Ok(Some(manifest))
+}
+
+// manifest整体替换,崩溃之后不会读到写了一半的manifest
+fn save_manifest(target_dir: &Path, manifest: &BackupManifest) -> Result<()> {
+    let mut entries = vec![(
+        MANIFEST_MERGE_GENERATION,
+        manifest.merge_generation,
+        BackupFile::default(),
+    )];
+    for (file_id, file) in manifest.data_files.iter() {
+        entries.push((MANIFEST_DATA_FILE, *file_id, *file));
+    }
+    for (file_id, file) in manifest.blob_files.iter() {
+        entries.push((MANIFEST_BLOB_FILE, *file_id, *file));
+    }
+    let mut buf = Vec::new();
+    for (key, file_id, file) in entries {
+        let log_record = LogRecord {
+            key: key.to_vec(),
+            value: LogRecordPos {
+                file_id,
+                offset: file.size,
+                value_size: file.identity,
+            }
+            .encode(),
+            log_type: LogRecordType::NORMAL,
+        };
+        buf.extend_from_slice(&log_record.encode());
+    }
+    replace_file(target_dir, BACKUP_MANIFEST_FILE_NAME, &buf)
+}
+
+// target_dir旁边用于替换备份的目录
+pub(crate) fn sibling_dir(target_dir: &Path, suffix: &str) -> Result<PathBuf> {
+    match target_dir.file_name() {
+        Some(name) => {
+            Ok(target_dir.with_file_name(format!("{}.backup-{}", name.to_string_lossy(), suffix)))
+        }
+        None => Err(Errors::DirPathCreateFailed),
+    }
+}
+
+// 删除整个目录,只用于备份和恢复自己创建的临时目录
+pub(crate) fn clear_dir(dir: &Path) -> Result<()> {
+    if dir.is_dir() && fs::remove_dir_all(dir).is_err() {
+        return Err(Errors::FailWriteDataToFile);
+    }
+    Ok(())
+}
+
+// 创建目录,目录已经存在时必须是空的
+pub(crate) fn create_empty_dir(dir: &Path) -> Result<()> {
+    if let Ok(mut read_dir) = fs::read_dir(dir) {
+        if read_dir.next().is_some() {
+            return Err(Errors::DirPathNotEmpty);
+        }
+    }
+    if let Err(e) = fs::create_dir_all(dir) {
+        error!("failed to create dir: {}", e);
+        return Err(Errors::DirPathCreateFailed);
+    }
+    Ok(())
+}
+
+fn hard_link(src: &Path, dst: &Path) -> Result<()> {
+    if let Err(e) = fs::hard_link(src, dst) {
+        error!("failed to hard link file {:?}: {}", src, e);
+        return Err(Errors::FailWriteDataToFile);
+    }
+    Ok(())
+}
+
+// 不会再变化的文件优先使用硬链接,不在同一个文件系统时拷贝
+fn link_or_copy(src: &Path, dst: &Path) -> Result<()> {
+    if fs::hard_link(src, dst).is_ok() {
+        return Ok(());
+    }
+    copy_file(src, dst)
+}
+
+pub(crate) fn copy_file(src: &Path, dst: &Path) -> Result<()> {
+    if let Err(e) = fs::copy(src, dst) {
+        error!("failed to copy file {:?}: {}", src, e);
+        return Err(Errors::FailWriteDataToFile);
+    }
+    Ok(())
+}
+
+// 只拷贝文件的前size个字节
+pub(crate) fn copy_prefix(src: &Path, dst: &Path, size: u64) -> Result<()> {
+    let res = File::open(src).and_then(|src_file| {
+        let mut dst_file = File::create(dst)?;
+        io::copy(&mut src_file.take(size), &mut dst_file)?;
+        dst_file.sync_all()
+    });
+    if let Err(e) = res {
+        error!("failed to copy file {:?}: {}", src, e);
+        return Err(Errors::FailWriteDataToFile);
+    }
+    Ok(())
+}
+
+// 文件已经拷贝了前base_size个字节,把[base_size, size)这一段追加上去
+fn copy_tail(src: &Path, dst: &Path, base_size: u64, size: u64) -> Result<()> {
+    if base_size == size {
+        return Ok(());
+    }
+    let res = File::open(src).and_then(|mut src_file| {
+        let mut dst_file = OpenOptions::new().write(true).open(dst)?;
+        // 上次中途失败时可能多写了一部分,先截掉
+        dst_file.set_len(base_size)?;
+        dst_file.seek(SeekFrom::Start(base_size))?;
+        src_file.seek(SeekFrom::Start(base_size))?;
+        io::copy(&mut src_file.take(size - base_size), &mut dst_file)?;
+        dst_file.sync_all()
+    });
+    if let Err(e) = res {
+        error!("failed to copy file {:?}: {}", src, e);
+        return Err(Errors::FailWriteDataToFile);
+    }
+    Ok(())
+}
+
+pub(crate) fn sync_dir(dir: &Path) -> Result<()> {
+    if File::open(dir).and_then(|dir| dir.sync_all()).is_err() {
+        return Err(Errors::FailSyncDataToFile);
+    }
+    Ok(())
+}
+
+// 先写临时文件并落盘,再rename替换原来的文件,最后同步目录
+// 崩溃之后读到的要么是完整的老文件,要么是完整的新文件
+pub(crate) fn replace_file(dir: &Path, file_name: &str, buf: &[u8]) -> Result<()> {
+    let tmp_path = dir.join(format!("{}.tmp", file_name));
+    let res = File::create(&tmp_path).and_then(|mut file| {
+        file.write_all(buf)?;
+        file.sync_all()
+    });
+    if let Err(e) = res.and_then(|_| fs::rename(&tmp_path, dir.join(file_name))) {
+        error!("failed to write file {}: {}", file_name, e);
+        return Err(Errors::FailWriteDataToFile);
+    }
+    sync_dir(dir)
+}
+
+#[cfg(test)]
+mod test_backup {
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use super::{load_manifest, BackupKind};
+    use crate::{
+        data::data_file::DataFile,
+        db::Engine,
+        errors::Errors,
+        options::Options,
+        util::rand_kv::{get_test_key, get_test_value},
+    };
+
+    #[test]
+    fn test_backup_and_restore() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-backup");
+        opts.file_size_threshlod = 32 * 1024;
+        opts.blob_value_threshold = Some(1024);
+        let backup_dir = PathBuf::from("/tmp/bitcask-rs-backup-target");
+        let restore_dir = PathBuf::from("/tmp/bitcask-rs-backup-restore");
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine
+            .put(Bytes::from("blob"), Bytes::from(vec![b'x'; 4096]))
+            .unwrap();
+        engine.delete(get_test_key(0)).unwrap();
+
+        // 备份期间继续写入
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let engine = engine.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut i = 1000;
+                while !stop.load(Ordering::SeqCst) {
+                    engine.put(get_test_key(i), get_test_value(i)).unwrap();
+                    i += 1;
+                }
+            })
+        };
+        engine.backup(backup_dir.clone()).unwrap();
+        stop.store(true, Ordering::SeqCst);
+        writer.join().unwrap();
+        assert_eq!(
+            engine.backup(backup_dir.clone()).err().unwrap(),
+            Errors::DirPathNotEmpty
+        );
+
+        let mut restore_opts = opts.clone();
+        restore_opts.dir_path = restore_dir.clone();
+        let restored = Engine::restore(backup_dir.clone(), restore_opts).unwrap();
+        assert!(restored.get(get_test_key(0)).is_err());
+        for i in 1..1000 {
+            assert_eq!(restored.get(get_test_key(i)).unwrap(), get_test_value(i));
+        }
+        assert_eq!(
+            restored.get(Bytes::from("blob")).unwrap(),
+            Bytes::from(vec![b'x'; 4096])
+        );
+        // 备份时已经写入的数据都可以读到
+        for key in restored.list_keys().unwrap() {
+            assert_eq!(restored.get(key.clone()).unwrap(), engine.get(key).unwrap());
+        }
+        // 恢复出来的engine可以继续写入
+        restored.put(get_test_key(0), get_test_value(0)).unwrap();
+        assert_eq!(restored.get(get_test_key(0)).unwrap(), get_test_value(0));
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(backup_dir).expect("failed to remove path");
+        std::fs::remove_dir_all(restore_dir).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-checkpoint");
+        opts.file_size_threshlod = 32 * 1024;
+        opts.blob_value_threshold = Some(1024);
+        let checkpoint_dir = PathBuf::from("/tmp/bitcask-rs-checkpoint-target");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine
+            .put(Bytes::from("blob"), Bytes::from(vec![b'x'; 4096]))
+            .unwrap();
+        let active_file_id = engine.data_file.read().get_file_id();
+        engine.checkpoint(checkpoint_dir.clone()).unwrap();
+        // 活跃文件已经切换
+        assert_eq!(engine.data_file.read().get_file_id(), active_file_id + 1);
+        assert_eq!(engine.data_file.read().get_wtite_offset(), 0);
+
+        // checkpoint之后的修改不会影响checkpoint
+        engine
+            .put(get_test_key(0), Bytes::from("new value"))
+            .unwrap();
+        engine
+            .put(Bytes::from("blob"), Bytes::from(vec![b'y'; 4096]))
+            .unwrap();
+        engine.delete(get_test_key(1)).unwrap();
+
+        let mut checkpoint_opts = opts.clone();
+        checkpoint_opts.dir_path = checkpoint_dir.clone();
+        let checkpoint = Engine::open(checkpoint_opts).expect("failed to open engine");
+        for i in 0..1000 {
+            assert_eq!(checkpoint.get(get_test_key(i)).unwrap(), get_test_value(i));
+        }
+        assert_eq!(
+            checkpoint.get(Bytes::from("blob")).unwrap(),
+            Bytes::from(vec![b'x'; 4096])
+        );
+        // 在checkpoint中写入也不会影响原来的数据
+        checkpoint
+            .put(get_test_key(2), Bytes::from("checkpoint value"))
+            .unwrap();
+        checkpoint
+            .put(Bytes::from("blob2"), Bytes::from(vec![b'z'; 4096]))
+            .unwrap();
+        drop(engine);
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine2.get(get_test_key(0)).unwrap(), "new value");
+        assert!(engine2.get(get_test_key(1)).is_err());
+        assert_eq!(engine2.get(get_test_key(2)).unwrap(), get_test_value(2));
+        assert_eq!(
+            engine2.get(Bytes::from("blob")).unwrap(),
+            Bytes::from(vec![b'y'; 4096])
+        );
+        assert!(engine2.get(Bytes::from("blob2")).is_err());
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(checkpoint_dir).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_incremental_backup() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-incremental-backup");
+        opts.file_size_threshlod = 32 * 1024;
+        opts.blob_value_threshold = Some(1024);
+        let backup_dir = PathBuf::from("/tmp/bitcask-rs-incremental-backup-target");
+        let restore_dir = PathBuf::from("/tmp/bitcask-rs-incremental-backup-restore");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine
+            .put(Bytes::from("blob"), Bytes::from(vec![b'x'; 4096]))
+            .unwrap();
+        // 不是备份的非空目录不会被覆盖
+        let other_dir = PathBuf::from("/tmp/bitcask-rs-incremental-backup-other");
+        std::fs::create_dir_all(&other_dir).unwrap();
+        std::fs::write(other_dir.join("file"), b"data").unwrap();
+        assert_eq!(
+            engine.incremental_backup(other_dir.clone()),
+            Err(Errors::DirPathNotEmpty)
+        );
+        assert!(other_dir.join("file").is_file());
+        std::fs::remove_dir_all(other_dir).expect("failed to remove path");
+        // 目录中没有之前的备份,做全量备份
+        assert_eq!(
+            engine.incremental_backup(backup_dir.clone()).unwrap(),
+            BackupKind::Full
+        );
+        let base = load_manifest(&backup_dir).unwrap().unwrap();
+
+        for i in 1000..2000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine
+            .put(Bytes::from("blob2"), Bytes::from(vec![b'y'; 4096]))
+            .unwrap();
+        engine.delete(get_test_key(0)).unwrap();
+        assert_eq!(
+            engine.incremental_backup(backup_dir.clone()).unwrap(),
+            BackupKind::Incremental
+        );
+        // 之前备份过的不再变化的文件没有重新拷贝
+        let manifest = load_manifest(&backup_dir).unwrap().unwrap();
+        assert!(manifest.data_files.len() > base.data_files.len());
+        let sealed_file_id = *base.data_files.keys().next().unwrap();
+        assert_eq!(
+            manifest.data_files.get(&sealed_file_id),
+            base.data_files.get(&sealed_file_id)
+        );
+
+        let check = |restore_opts: Options| {
+            let restored = Engine::restore(backup_dir.clone(), restore_opts).unwrap();
+            assert!(restored.get(get_test_key(0)).is_err());
+            for i in 1..2000 {
+                assert_eq!(restored.get(get_test_key(i)).unwrap(), get_test_value(i));
+            }
+            assert_eq!(
+                restored.get(Bytes::from("blob2")).unwrap(),
+                Bytes::from(vec![b'y'; 4096])
+            );
+            std::fs::remove_dir_all(restore_dir.clone()).expect("failed to remove path");
+        };
+        let mut restore_opts = opts.clone();
+        restore_opts.dir_path = restore_dir.clone();
+        check(restore_opts.clone());
+
+        // 备份过的blob文件被同一个id的另一个文件替换,退化为全量备份
+        let (blob_file_id, blob_file) = manifest.blob_files.iter().next().unwrap();
+        let blob_path = DataFile::get_blob_file_name(opts.dir_path.clone(), *blob_file_id);
+        let original = std::fs::read(&blob_path).unwrap();
+        let mut replaced = original.clone();
+        replaced[..blob_file.size as usize].fill(0xab);
+        std::fs::write(&blob_path, &replaced).unwrap();
+        assert_eq!(
+            engine.incremental_backup(backup_dir.clone()).unwrap(),
+            BackupKind::Full
+        );
+        std::fs::write(&blob_path, &original).unwrap();
+        assert_eq!(
+            engine.incremental_backup(backup_dir.clone()).unwrap(),
+            BackupKind::Full
+        );
+        check(restore_opts.clone());
+
+        // merge之后原来的文件被替换,退化为全量备份
+        engine.merge().unwrap();
+        drop(engine);
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(
+            engine2.incremental_backup(backup_dir.clone()).unwrap(),
+            BackupKind::Full
+        );
+        // 新的全量备份在临时目录中完成后替换原来的备份
+        assert!(!PathBuf::from("/tmp/bitcask-rs-incremental-backup-target.backup-tmp").exists());
+        assert!(!PathBuf::from("/tmp/bitcask-rs-incremental-backup-target.backup-old").exists());
+        assert_eq!(
+            engine2.incremental_backup(backup_dir.clone()).unwrap(),
+            BackupKind::Incremental
+        );
+        check(restore_opts);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(backup_dir).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,29 @@
+// gRPC服务,接口定义在proto/bitcask.proto
+// 用法: grpc_server [--addr 127.0.0.1:50051] [--dir /tmp/bitcask-rs]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use bitcask_kv::{db::Engine, grpc::GrpcServer, options::Options};
+
+fn main() {
+    env_logger::init();
+    let mut addr = "127.0.0.1:50051".to_string();
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--addr", Some(value)) => addr = value,
+            ("--dir", Some(value)) => options.dir_path = PathBuf::from(value),
+            _ => {
+                eprintln!("usage: grpc_server [--addr host:port] [--dir path]");
+                std::process::exit(2);
+            }
+        }
+    }
+    let engine = Arc::new(Engine::open(options).expect("failed to open engine"));
+    let server = GrpcServer::start(engine, addr).expect("failed to start server");
+    println!("listening on {}", server.local_addr());
+    loop {
+        std::thread::park();
+    }
+}
@@ -0,0 +1,32 @@
+// HTTP/JSON接口的服务
+// 用法: http_server [--addr 127.0.0.1:8080] [--dir /tmp/bitcask-rs] [--backup-dir /tmp/bitcask-rs-backups]
+// 不指定--backup-dir时不能通过接口备份
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use bitcask_kv::{db::Engine, http_server::HttpServer, options::Options};
+
+fn main() {
+    env_logger::init();
+    let mut addr = "127.0.0.1:8080".to_string();
+    let mut options = Options::default();
+    let mut backup_root = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--addr", Some(value)) => addr = value,
+            ("--dir", Some(value)) => options.dir_path = PathBuf::from(value),
+            ("--backup-dir", Some(value)) => backup_root = Some(PathBuf::from(value)),
+            _ => {
+                eprintln!("usage: http_server [--addr host:port] [--dir path] [--backup-dir path]");
+                std::process::exit(2);
+            }
+        }
+    }
+    let engine = Arc::new(Engine::open(options).expect("failed to open engine"));
+    let server = HttpServer::start(engine, addr, backup_root).expect("failed to start server");
+    println!("listening on {}", server.local_addr());
+    loop {
+        std::thread::park();
+    }
+}
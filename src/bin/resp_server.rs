@@ -0,0 +1,34 @@
+// 兼容redis协议的服务
+// 用法: resp_server [--addr 127.0.0.1:6379] [--dir /tmp/bitcask-rs]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use bitcask_kv::{
+    db::Engine,
+    options::Options,
+    redis::{server::RespServer, RedisStore},
+};
+
+fn main() {
+    env_logger::init();
+    let mut addr = "127.0.0.1:6379".to_string();
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--addr", Some(value)) => addr = value,
+            ("--dir", Some(value)) => options.dir_path = PathBuf::from(value),
+            _ => {
+                eprintln!("usage: resp_server [--addr host:port] [--dir path]");
+                std::process::exit(2);
+            }
+        }
+    }
+    let engine = Arc::new(Engine::open(options).expect("failed to open engine"));
+    let server =
+        RespServer::start(Arc::new(RedisStore::new(engine)), addr).expect("failed to start server");
+    println!("listening on {}", server.local_addr());
+    loop {
+        std::thread::park();
+    }
+}
@@ -0,0 +1,527 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use bytes::Bytes;
+use parking_lot::RwLock;
+
+use crate::{
+    backup::replace_file,
+    data::{
+        data_file::{DataFile, BLOB_NEXT_FILE_ID_FILE_NAME},
+        log_record::{BlobPos, LogRecord, LogRecordType},
+    },
+    db::{Engine, NO_TXN_SEQ_NO},
+    errors::{Errors, Result},
+    options::SyncPolicy,
+    write_batch::WriteBatch,
+};
+
+// 存放大value的blob文件,main log中只记录指向blob的BlobPos
+// 这样merge的时候不需要重复拷贝不会变化的大value
+pub(crate) struct BlobFiles {
+    dir_path: PathBuf,
+    file_size_threshold: u64,
+    // 正在写入的blob文件,第一次写入大value时才会创建
+    active_file: RwLock<Option<DataFile>>,
+    old_files: RwLock<HashMap<u32, DataFile>>,
+    // 下一个blob文件的id,持久化在单独的文件中
+    // 压缩删除了id最大的文件之后也不会再次使用它的id,老的日志、副本和备份中引用这个id的记录不会读到别的数据
+    next_file_id: AtomicU32,
+    // 备份拿读锁,删除blob文件拿写锁,保证备份期间blob文件不会被删除
+    pub(crate) remove_lock: RwLock<()>,
+}
+
+// 一个blob文件的垃圾统计
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlobFileStat {
+    pub file_id: u32,
+    // 文件中所有记录的字节数
+    pub total_size: u64,
+    // 仍然被索引引用的记录的字节数
+    pub live_size: u64,
+}
+
+impl BlobFileStat {
+    pub fn garbage_ratio(&self) -> f64 {
+        if self.total_size == 0 {
+            return 0.0;
+        }
+        1.0 - self.live_size as f64 / self.total_size as f64
+    }
+}
+
+impl BlobFiles {
+    pub(crate) fn open(
+        dir_path: PathBuf,
+        file_size_threshold: u64,
+        read_only: bool,
+    ) -> Result<Self> {
+        let blob_files = match read_only {
+            true => DataFile::load_read_only_blob_files(dir_path.clone())?,
+            false => DataFile::load_blob_files(dir_path.clone())?,
+        };
+        // 已有的blob文件都不再写入,这样备份和checkpoint硬链接出去的文件不会被修改
+        let mut old_files = HashMap::new();
+        for blob_file in blob_files {
+            old_files.insert(blob_file.get_file_id(), blob_file);
+        }
+        // 没有记录时(旧版本的数据目录)从已有的最大id之后开始
+        let next_file_id = old_files
+            .keys()
+            .max()
+            .map_or(0, |file_id| file_id + 1)
+            .max(load_next_file_id(&dir_path)?);
+        Ok(BlobFiles {
+            dir_path,
+            file_size_threshold,
+            active_file: RwLock::new(None),
+            old_files: RwLock::new(old_files),
+            next_file_id: AtomicU32::new(next_file_id),
+            remove_lock: RwLock::new(()),
+        })
+    }
+
+    // 把大value写入blob文件
+    fn write(&self, key: &[u8], value: &[u8], sync: bool) -> Result<BlobPos> {
+        let log_record = LogRecord {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            log_type: LogRecordType::NORMAL,
+        };
+        let enc_log_record = log_record.encode();
+        let record_len = enc_log_record.len() as u64;
+        let mut active_file_write_guard = self.active_file.write();
+        let need_new_file = match &*active_file_write_guard {
+            Some(active_file) => {
+                active_file.get_wtite_offset() > 0
+                    && active_file.get_wtite_offset() + record_len > self.file_size_threshold
+            }
+            None => true,
+        };
+        if need_new_file {
+            self.seal_active_file(&mut active_file_write_guard)?;
+            // 先持久化新的上界再创建文件,崩溃之后不会重复使用这个id
+            let file_id = self.next_file_id.load(Ordering::SeqCst);
+            save_next_file_id(&self.dir_path, file_id + 1)?;
+            self.next_file_id.store(file_id + 1, Ordering::SeqCst);
+            *active_file_write_guard =
+                Some(DataFile::new_blob_file(self.dir_path.clone(), file_id)?);
+        }
+        let active_file = active_file_write_guard.as_ref().unwrap();
+        active_file.write(&enc_log_record)?;
+        if sync {
+            active_file.sync()?;
+        }
+        Ok(BlobPos {
+            file_id: active_file.get_file_id(),
+            offset: active_file.get_wtite_offset() - record_len,
+            value_size: value.len() as u32,
+        })
+    }
+
+    // 持久化正在写入的blob文件并把它变成不再写入的文件,下次写入时创建新的文件
+    fn seal_active_file(&self, active_file: &mut Option<DataFile>) -> Result<()> {
+        if let Some(active_file) = active_file.take() {
+            active_file.sync()?;
+            self.old_files
+                .write()
+                .insert(active_file.get_file_id(), active_file);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn seal(&self) -> Result<()> {
+        self.seal_active_file(&mut self.active_file.write())
+    }
+
+    // 读取blob中的value
+    pub(crate) fn read(&self, blob_pos: &BlobPos) -> Result<Vec<u8>> {
+        self.with_file(blob_pos.file_id, |blob_file| {
+            Ok(blob_file.read_log_record(blob_pos.offset)?.logrecord.value)
+        })
+    }
+
+    // 只读取blob中value的一段,不做crc校验
+    pub(crate) fn read_range(&self, blob_pos: &BlobPos, start: u64, len: usize) -> Result<Vec<u8>> {
+        self.with_file(blob_pos.file_id, |blob_file| {
+            let (_, key_size, _, header_size) =
+                blob_file.read_log_record_header(blob_pos.offset)?;
+            let value_offset = blob_pos.offset + (header_size + key_size) as u64;
+            blob_file.read_bytes(value_offset + start, len)
+        })
+    }
+
+    // 读取blob文件中从offset开始的原始数据,复制时使用
+    pub(crate) fn read_bytes(&self, file_id: u32, offset: u64, len: usize) -> Result<Vec<u8>> {
+        self.with_file(file_id, |blob_file| blob_file.read_bytes(offset, len))
+    }
+
+    fn with_file<F, T>(&self, file_id: u32, f: F) -> Result<T>
+    where
+        F: FnOnce(&DataFile) -> Result<T>,
+    {
+        let active_file_read_guard = self.active_file.read();
+        if let Some(active_file) = &*active_file_read_guard {
+            if active_file.get_file_id() == file_id {
+                return f(active_file);
+            }
+        }
+        let old_files_read_guard = self.old_files.read();
+        match old_files_read_guard.get(&file_id) {
+            Some(blob_file) => f(blob_file),
+            None => Err(Errors::BlobFileNotFound),
+        }
+    }
+
+    pub(crate) fn sync(&self) -> Result<()> {
+        if let Some(active_file) = &*self.active_file.read() {
+            active_file.sync()?;
+        }
+        Ok(())
+    }
+
+    // 扫描一个不再写入的blob文件,返回其中每条记录的(key, offset, size)
+    fn scan(&self, file_id: u32) -> Result<Vec<(Vec<u8>, u64, u64)>> {
+        let old_files_read_guard = self.old_files.read();
+        let blob_file = match old_files_read_guard.get(&file_id) {
+            Some(blob_file) => blob_file,
+            None => return Err(Errors::BlobFileNotFound),
+        };
+        let mut records = Vec::new();
+        let mut offset = 0;
+        loop {
+            let (_, key_size, value_size, header_size) =
+                match blob_file.read_log_record_header(offset) {
+                    Ok(header) => header,
+                    Err(Errors::DataFileReadEOF) => break,
+                    Err(e) => return Err(e),
+                };
+            let key = blob_file.read_bytes(offset + header_size as u64, key_size)?;
+            let size = (header_size + key_size + value_size + 4) as u64;
+            records.push((key, offset, size));
+            offset += size;
+        }
+        Ok(records)
+    }
+
+    // 所有blob文件的id和当前大小,在此之前写入的blob都在这个大小以内
+    pub(crate) fn file_sizes(&self) -> Vec<(u32, u64)> {
+        let active_file_read_guard = self.active_file.read();
+        let mut file_sizes: Vec<(u32, u64)> = self
+            .old_files
+            .read()
+            .values()
+            .map(|blob_file| (blob_file.get_file_id(), blob_file.get_wtite_offset()))
+            .collect();
+        if let Some(active_file) = &*active_file_read_guard {
+            file_sizes.push((active_file.get_file_id(), active_file.get_wtite_offset()));
+        }
+        file_sizes.sort();
+        file_sizes
+    }
+
+    pub(crate) fn old_file_ids(&self) -> Vec<u32> {
+        let mut file_ids: Vec<u32> = self.old_files.read().keys().copied().collect();
+        file_ids.sort();
+        file_ids
+    }
+
+    // 副本写入从leader同步过来的blob数据,offset之前已经有的部分跳过
+    pub(crate) fn append_replicated(&self, file_id: u32, offset: u64, data: &[u8]) -> Result<()> {
+        let mut old_files_write_guard = self.old_files.write();
+        let blob_file = match old_files_write_guard.entry(file_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                self.next_file_id.fetch_max(file_id + 1, Ordering::SeqCst);
+                entry.insert(DataFile::new_blob_file(self.dir_path.clone(), file_id)?)
+            }
+        };
+        let size = blob_file.get_wtite_offset();
+        if offset > size {
+            return Err(Errors::ReplicationPositionMismatch);
+        }
+        let skip = (size - offset) as usize;
+        if skip < data.len() {
+            blob_file.write(&data[skip..])?;
+            blob_file.sync()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn remove(&self, file_id: u32) -> Result<()> {
+        let _guard = self.remove_lock.write();
+        self.old_files.write().remove(&file_id);
+        let file_name = DataFile::get_blob_file_name(self.dir_path.clone(), file_id);
+        if std::fs::remove_file(file_name).is_err() {
+            return Err(Errors::BlobFileNotFound);
+        }
+        Ok(())
+    }
+}
+
+// 读取持久化的下一个blob文件id,没有记录时返回0
+fn load_next_file_id(dir_path: &Path) -> Result<u32> {
+    let file_name = dir_path.join(BLOB_NEXT_FILE_ID_FILE_NAME);
+    if !file_name.is_file() {
+        return Ok(0);
+    }
+    let next_file_id_file = DataFile::open_read_only(file_name, 0)?;
+    let read_log_record = next_file_id_file.read_log_record(0)?;
+    String::from_utf8(read_log_record.logrecord.value)
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .ok_or(Errors::DataFileCorrupted)
+}
+
+// 和merge完成的标记一样记录为一条日志,整体替换写入
+fn save_next_file_id(dir_path: &Path, next_file_id: u32) -> Result<()> {
+    let log_record = LogRecord {
+        key: BLOB_NEXT_FILE_ID_FILE_NAME.as_bytes().to_vec(),
+        value: next_file_id.to_string().into_bytes(),
+        log_type: LogRecordType::NORMAL,
+    };
+    replace_file(dir_path, BLOB_NEXT_FILE_ID_FILE_NAME, &log_record.encode())
+}
+
+impl Engine {
+    // value超过配置的大小时写入blob文件,返回main log中需要记录的value和类型
+    pub(crate) fn separate_value(
+        &self,
+        key: &[u8],
+        value: Vec<u8>,
+    ) -> Result<(Vec<u8>, LogRecordType)> {
+        match self.options.blob_value_threshold {
+            Some(threshold) if value.len() as u64 >= threshold => {
+                // blob需要先于指向它的日志落盘
+                let sync = self.options.sync_policy != SyncPolicy::Never;
+                let blob_pos = self.blob_files.write(key, &value, sync)?;
+                Ok((blob_pos.encode(), LogRecordType::BLOBINDEX))
+            }
+            _ => Ok((value, LogRecordType::NORMAL)),
+        }
+    }
+
+    // 统计每个不再写入的blob文件中有多少数据仍然有效
+    pub fn blob_file_stats(&self) -> Result<Vec<BlobFileStat>> {
+        let mut stats = Vec::new();
+        for file_id in self.blob_files.old_file_ids() {
+            let mut stat = BlobFileStat {
+                file_id,
+                total_size: 0,
+                live_size: 0,
+            };
+            for (key, offset, size) in self.blob_files.scan(file_id)? {
+                stat.total_size += size;
+                if self.is_live_blob(&key, file_id, offset)? {
+                    stat.live_size += size;
+                }
+            }
+            stats.push(stat);
+        }
+        Ok(stats)
+    }
+
+    // 压缩垃圾比例不小于garbage_ratio的blob文件:
+    // 有效的value重新写入新的blob文件,然后删除老的blob文件
+    // 有cdc reader时,老的日志可能还会引用这些blob,不做压缩
+    pub fn compact_blob_files(&self, garbage_ratio: f64) -> Result<()> {
+        self.check_writable()?;
+        if self.has_cdc_readers() {
+            return Ok(());
+        }
+        for stat in self.blob_file_stats()? {
+            if stat.total_size > 0 && stat.garbage_ratio() < garbage_ratio {
+                continue;
+            }
+            // 拿写锁,重写期间key不会被其他写入修改
+            let _guard = self.conditional_write_lock.write();
+            for (key, offset, _) in self.blob_files.scan(stat.file_id)? {
+                if !self.is_live_blob(&key, stat.file_id, offset)? {
+                    continue;
+                }
+                let value = self.blob_files.read(&BlobPos {
+                    file_id: stat.file_id,
+                    offset,
+                    value_size: 0,
+                })?;
+                let sync = self.options.sync_policy != SyncPolicy::Never;
+                let blob_pos = self.blob_files.write(&key, &value, sync)?;
+                let mut log_record = LogRecord {
+                    key: WriteBatch::encode_key_seqno(Bytes::from(key.clone()), NO_TXN_SEQ_NO),
+                    value: blob_pos.encode(),
+                    log_type: LogRecordType::BLOBINDEX,
+                };
+                let pos = self.append_log(&mut log_record)?;
+                self.indexer.put(key, pos);
+            }
+            // 删除老的blob文件之前,指向新位置的日志必须已经落盘,
+            // 否则崩溃之后日志中的记录仍然指向被删除的文件
+            self.sync()?;
+            self.blob_files.remove(stat.file_id)?;
+        }
+        Ok(())
+    }
+
+    // 判断blob文件中的记录是否仍然被索引引用
+    fn is_live_blob(&self, key: &[u8], file_id: u32, offset: u64) -> Result<bool> {
+        let log_record_pos = match self.indexer.get(key.to_vec()) {
+            Some(pos) => pos,
+            None => return Ok(false),
+        };
+        let log_record = self.read_log_record_by_pos(&log_record_pos)?;
+        if log_record.log_type != LogRecordType::BLOBINDEX {
+            return Ok(false);
+        }
+        let blob_pos = BlobPos::decode(&log_record.value);
+        Ok(blob_pos.file_id == file_id && blob_pos.offset == offset)
+    }
+}
+
+#[cfg(test)]
+mod test_blob {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{
+        db::Engine,
+        options::{Options, WriteBatchOptions},
+        util::rand_kv::get_test_key,
+    };
+
+    fn big_value(i: u8) -> Bytes {
+        Bytes::from(vec![i; 4096])
+    }
+
+    #[test]
+    fn test_blob_value_separation() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-blob");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        opts.blob_value_threshold = Some(1024);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine
+            .put(Bytes::from("small"), Bytes::from("value"))
+            .unwrap();
+        engine.put(Bytes::from("big"), big_value(1)).unwrap();
+        let write_batch = engine
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        write_batch
+            .put(Bytes::from("batch-big"), big_value(2))
+            .unwrap();
+        write_batch.commit().unwrap();
+
+        // main log中只有指针
+        assert!(engine.data_file.read().get_wtite_offset() < 1024);
+        let check = |engine: &Engine| {
+            assert_eq!(engine.get(Bytes::from("small")).unwrap(), "value");
+            assert_eq!(engine.get(Bytes::from("big")).unwrap(), big_value(1));
+            assert_eq!(engine.get(Bytes::from("batch-big")).unwrap(), big_value(2));
+            assert_eq!(
+                engine.metadata(Bytes::from("big")).unwrap().value_size,
+                4096
+            );
+            assert_eq!(
+                engine.get_range(Bytes::from("big"), 10, 5).unwrap(),
+                Bytes::from(vec![1; 5])
+            );
+            let res = engine.multi_get(&[Bytes::from("big"), Bytes::from("small")]);
+            assert_eq!(res[0].clone().unwrap().unwrap(), big_value(1));
+            assert_eq!(res[1].clone().unwrap().unwrap(), "value");
+        };
+        check(&engine);
+        // 重启后依然可以读到blob中的value
+        engine.close().unwrap();
+        drop(engine);
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        check(&engine2);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_compact_blob_files() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-blob-compact");
+        opts.file_size_threshlod = 64 * 1024;
+        opts.blob_value_threshold = Some(1024);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..100 {
+            engine.put(get_test_key(i), big_value(i as u8)).unwrap();
+        }
+        // 覆盖写和删除产生垃圾
+        for i in 0..50 {
+            engine.put(get_test_key(i), Bytes::from("small")).unwrap();
+        }
+        engine.delete(get_test_key(50)).unwrap();
+
+        let stats = engine.blob_file_stats().unwrap();
+        assert!(!stats.is_empty());
+        assert!(stats[0].garbage_ratio() > 0.9);
+        let total: u64 = stats.iter().map(|stat| stat.total_size).sum();
+        let live: u64 = stats.iter().map(|stat| stat.live_size).sum();
+        assert!(live < total);
+
+        engine.compact_blob_files(0.5).unwrap();
+        let stats = engine.blob_file_stats().unwrap();
+        assert!(stats.iter().all(|stat| stat.garbage_ratio() < 0.5));
+        let check = |engine: &Engine| {
+            for i in 0..50 {
+                assert_eq!(engine.get(get_test_key(i)).unwrap(), "small");
+            }
+            assert!(engine.get(get_test_key(50)).is_err());
+            for i in 51..100 {
+                assert_eq!(engine.get(get_test_key(i)).unwrap(), big_value(i as u8));
+            }
+        };
+        check(&engine);
+        drop(engine);
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        check(&engine2);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_blob_file_id_not_reused() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-blob-file-id");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        opts.blob_value_threshold = Some(1024);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine.put(Bytes::from("a"), big_value(1)).unwrap();
+        engine.put(Bytes::from("b"), big_value(2)).unwrap();
+        engine.close().unwrap();
+        drop(engine);
+
+        // 重启之后没有正在写入的blob文件,id最大的文件全部是垃圾,压缩时直接删除
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let max_file_id = engine.blob_file_stats().unwrap().last().unwrap().file_id;
+        engine.put(Bytes::from("a"), Bytes::from("small")).unwrap();
+        engine.delete(Bytes::from("b")).unwrap();
+        engine.compact_blob_files(0.5).unwrap();
+        assert!(engine.blob_file_stats().unwrap().is_empty());
+
+        // 新的blob文件不会使用被删除的id,重启之后也一样
+        engine.put(Bytes::from("c"), big_value(3)).unwrap();
+        engine.close().unwrap();
+        drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let stats = engine.blob_file_stats().unwrap();
+        assert_eq!(stats.len(), 1);
+        assert!(stats[0].file_id > max_file_id);
+        engine.compact_blob_files(0.0).unwrap();
+        engine.put(Bytes::from("d"), big_value(4)).unwrap();
+        engine.blob_files.seal().unwrap();
+        let file_ids: Vec<u32> = engine
+            .blob_file_stats()
+            .unwrap()
+            .iter()
+            .map(|stat| stat.file_id)
+            .collect();
+        assert!(file_ids.iter().all(|file_id| *file_id > stats[0].file_id));
+        assert_eq!(engine.get(Bytes::from("c")).unwrap(), big_value(3));
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
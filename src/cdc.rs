@@ -0,0 +1,512 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use bytes::Bytes;
+
+use crate::{
+    backup::replace_file,
+    data::{
+        data_file::DataFile,
+        log_record::{BlobPos, LogRecord, LogRecordPos, LogRecordType},
+    },
+    db::{Engine, NO_TXN_SEQ_NO},
+    errors::{Errors, Result},
+    write_batch::TXN_FIN,
+};
+
+pub const CDC_READERS_FILE_NAME: &str = "cdc-readers";
+
+// 日志中的一个位置,同时也是cdc reader的断点
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CdcPosition {
+    pub file_id: u32,
+    pub offset: u64,
+}
+
+// 注册cdc reader时指定从哪里开始读取
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResumeToken {
+    // 从日志中的某个位置开始,CdcPosition::default()表示从头开始
+    Position(CdcPosition),
+    // 从某个批量提交之后开始
+    SeqNo(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CdcRecordType {
+    Put,
+    Delete,
+    // key为起始key,value为结束key,value为空表示没有上界
+    DeleteRange,
+    // 批量提交的结束标记,同一个seq_no的记录在它之后才算提交成功,value为提交时间的毫秒数
+    TxnFinished,
+}
+
+// cdc reader读出的一条已经提交的日志
+#[derive(Clone, Debug, PartialEq)]
+pub struct CdcRecord {
+    pub pos: CdcPosition,
+    // 这条记录之后的位置,处理完之后可以用它来commit
+    pub next_pos: CdcPosition,
+    // 批量提交的seq_no,单条写入为0
+    pub seq_no: usize,
+    pub record_type: CdcRecordType,
+    pub key: Bytes,
+    pub value: Bytes,
+}
+
+// 按照日志顺序读取已经提交的记录,位置commit之后会持久化,重启后可以继续读取
+pub struct CdcReader<'a> {
+    engine: &'a Engine,
+    name: String,
+    pos: CdcPosition,
+}
+
+impl CdcReader<'_> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // 当前读取到的位置
+    pub fn position(&self) -> CdcPosition {
+        self.pos
+    }
+
+    // 读取最多max_records条记录,批量提交的记录总是一起返回,所以可能会多于max_records条
+    // 还没有写完的批量提交不会返回,下次读取时再从它的开头读
+    // 活跃文件只读取到已经持久化的位置,宕机之后不会丢失已经读出的记录
+    pub fn read(&mut self, max_records: usize) -> Result<Vec<CdcRecord>> {
+        let mut records = Vec::new();
+        // 没有读到TXN_FIN的批量提交的记录
+        let mut pending: Vec<CdcRecord> = Vec::new();
+        let mut pending_start = self.pos;
+        let durable_pos = self.engine.durable_pos();
+        let active_file_read_guard = self.engine.data_file.read();
+        let old_files_read_guard = self.engine.old_files.read();
+        let mut file_ids: Vec<u32> = old_files_read_guard.keys().copied().collect();
+        file_ids.push(active_file_read_guard.get_file_id());
+        file_ids.sort();
+        let mut pos = self.pos;
+        for file_id in file_ids {
+            if file_id < pos.file_id {
+                continue;
+            }
+            if file_id > pos.file_id {
+                pos = CdcPosition { file_id, offset: 0 };
+            }
+            // 不再写入的文件在切换时已经持久化
+            let (data_file, end) = match old_files_read_guard.get(&file_id) {
+                Some(data_file) => (data_file, data_file.get_wtite_offset()),
+                None if durable_pos.file_id == file_id => {
+                    (&*active_file_read_guard, durable_pos.offset)
+                }
+                None => (&*active_file_read_guard, 0),
+            };
+            loop {
+                if pending.is_empty() && records.len() >= max_records {
+                    self.pos = pos;
+                    return Ok(records);
+                }
+                if pos.offset >= end {
+                    break;
+                }
+                let read_log_record = match data_file.read_log_record(pos.offset) {
+                    Ok(read_log_record) => read_log_record,
+                    Err(Errors::DataFileReadEOF) => break,
+                    Err(e) => return Err(e),
+                };
+                let next_pos = CdcPosition {
+                    file_id,
+                    offset: pos.offset + read_log_record.size as u64,
+                };
+                // 时间标记只用于按照时间恢复,不是数据变更
+                if read_log_record.logrecord.log_type == LogRecordType::TIMESTAMP {
+                    pos = next_pos;
+                    continue;
+                }
+                let (record, seq_no) =
+                    self.engine
+                        .to_cdc_record(read_log_record.logrecord, pos, next_pos)?;
+                pos = next_pos;
+                if seq_no == NO_TXN_SEQ_NO {
+                    // 之前的批量提交没有写完就中断了,丢弃
+                    pending.clear();
+                    records.push(record);
+                    continue;
+                }
+                if pending.first().is_some_and(|first| first.seq_no != seq_no) {
+                    pending.clear();
+                }
+                if pending.is_empty() {
+                    pending_start = record.pos;
+                }
+                let finished = record.record_type == CdcRecordType::TxnFinished;
+                pending.push(record);
+                if finished {
+                    records.append(&mut pending);
+                }
+            }
+        }
+        // 还没有写完的批量提交,下次从它的开头开始读
+        self.pos = match pending.is_empty() {
+            true => pos,
+            false => pending_start,
+        };
+        Ok(records)
+    }
+
+    // 持久化读取进度,重启后通过open_cdc_reader从这里继续读取
+    // 这个位置之前的文件才可以被merge
+    // pos只能是上次commit的位置和当前读取位置之间的某条日志的开头,比如CdcRecord的pos或者next_pos
+    pub fn commit(&self, pos: CdcPosition) -> Result<()> {
+        self.engine.check_read_only()?;
+        let mut cdc_readers = self.engine.cdc_readers.lock();
+        if pos != self.pos {
+            let committed = cdc_readers.get(&self.name).copied().unwrap_or_default();
+            if pos < committed || pos > self.pos || !self.engine.is_record_start(committed, pos)? {
+                return Err(Errors::InvalidCdcPosition);
+            }
+        }
+        cdc_readers.insert(self.name.clone(), pos);
+        save_cdc_readers(self.engine.options.dir_path.clone(), &cdc_readers)
+    }
+}
+
+impl Engine {
+    // pos是否是某条日志的开头或者文件的末尾,从已知的日志开头from往后逐条读取判断
+    fn is_record_start(&self, from: CdcPosition, pos: CdcPosition) -> Result<bool> {
+        let active_file_read_guard = self.data_file.read();
+        let old_files_read_guard = self.old_files.read();
+        let data_file = match old_files_read_guard.get(&pos.file_id) {
+            Some(data_file) => data_file,
+            None if pos.file_id == active_file_read_guard.get_file_id() => &*active_file_read_guard,
+            None => return Ok(false),
+        };
+        let mut offset = match from.file_id == pos.file_id {
+            true => from.offset,
+            false => 0,
+        };
+        while offset < pos.offset {
+            match data_file.read_log_record(offset) {
+                Ok(read_log_record) => offset += read_log_record.size as u64,
+                Err(Errors::DataFileReadEOF) => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(offset == pos.offset)
+    }
+
+    // 注册一个cdc reader,已经存在同名的reader时覆盖它的进度
+    pub fn register_cdc_reader(&self, name: &str, token: ResumeToken) -> Result<CdcReader<'_>> {
+        if name.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        let pos = match token {
+            ResumeToken::Position(pos) => {
+                // merge过的文件已经被重写,之前的位置不再有效
+                let no_merged_file_id = Engine::no_merged_file_id(self.options.dir_path.clone())?;
+                if pos.file_id < no_merged_file_id && pos != CdcPosition::default() {
+                    return Err(Errors::InvalidCdcPosition);
+                }
+                pos
+            }
+            ResumeToken::SeqNo(seq_no) => self.find_seq_no_pos(seq_no)?,
+        };
+        let reader = CdcReader {
+            engine: self,
+            name: name.to_string(),
+            pos,
+        };
+        reader.commit(pos)?;
+        Ok(reader)
+    }
+
+    // 从持久化的进度继续读取
+    pub fn open_cdc_reader(&self, name: &str) -> Result<CdcReader<'_>> {
+        match self.cdc_readers.lock().get(name) {
+            Some(pos) => Ok(CdcReader {
+                engine: self,
+                name: name.to_string(),
+                pos: *pos,
+            }),
+            None => Err(Errors::CdcReaderNotFound),
+        }
+    }
+
+    // 删除cdc reader,它的进度不再阻止merge
+    pub fn unregister_cdc_reader(&self, name: &str) -> Result<()> {
+        self.check_read_only()?;
+        let mut cdc_readers = self.cdc_readers.lock();
+        if cdc_readers.remove(name).is_none() {
+            return Err(Errors::CdcReaderNotFound);
+        }
+        save_cdc_readers(self.options.dir_path.clone(), &cdc_readers)
+    }
+
+    // 所有cdc reader中最小的文件id,这个文件以及之后的文件都不能被merge
+    pub(crate) fn cdc_retain_file_id(&self) -> Option<u32> {
+        self.cdc_readers
+            .lock()
+            .values()
+            .map(|pos| pos.file_id)
+            .min()
+    }
+
+    pub(crate) fn has_cdc_readers(&self) -> bool {
+        !self.cdc_readers.lock().is_empty()
+    }
+
+    // 找到seq_no对应的批量提交的TXN_FIN之后的位置
+    fn find_seq_no_pos(&self, seq_no: usize) -> Result<CdcPosition> {
+        let active_file_read_guard = self.data_file.read();
+        let old_files_read_guard = self.old_files.read();
+        let mut file_ids: Vec<u32> = old_files_read_guard.keys().copied().collect();
+        file_ids.push(active_file_read_guard.get_file_id());
+        file_ids.sort();
+        for file_id in file_ids {
+            let data_file = match old_files_read_guard.get(&file_id) {
+                Some(data_file) => data_file,
+                None => &*active_file_read_guard,
+            };
+            let mut offset = 0;
+            loop {
+                let read_log_record = match data_file.read_log_record(offset) {
+                    Ok(read_log_record) => read_log_record,
+                    Err(Errors::DataFileReadEOF) => break,
+                    Err(e) => return Err(e),
+                };
+                offset += read_log_record.size as u64;
+                let logrecord = read_log_record.logrecord;
+                if logrecord.log_type != LogRecordType::TXNCOMMITTED {
+                    continue;
+                }
+                let (_, record_seq_no) = self.parse_key(logrecord.key);
+                if record_seq_no == seq_no {
+                    return Ok(CdcPosition { file_id, offset });
+                }
+            }
+        }
+        Err(Errors::CdcSeqNoNotFound)
+    }
+
+    // 把日志中的记录转换成cdc记录,blob中的value会被读出来
+    fn to_cdc_record(
+        &self,
+        logrecord: LogRecord,
+        pos: CdcPosition,
+        next_pos: CdcPosition,
+    ) -> Result<(CdcRecord, usize)> {
+        let (key, seq_no) = self.parse_key(logrecord.key);
+        let (record_type, value) = match logrecord.log_type {
+            LogRecordType::NORMAL => (CdcRecordType::Put, logrecord.value),
+            LogRecordType::BLOBINDEX => (
+                CdcRecordType::Put,
+                self.blob_files.read(&BlobPos::decode(&logrecord.value))?,
+            ),
+            LogRecordType::DELETED => (CdcRecordType::Delete, logrecord.value),
+            LogRecordType::RANGEDELETED => (CdcRecordType::DeleteRange, logrecord.value),
+            LogRecordType::TXNCOMMITTED => (CdcRecordType::TxnFinished, logrecord.value),
+            LogRecordType::TIMESTAMP => unreachable!("timestamp markers are skipped"),
+        };
+        let key = match record_type {
+            CdcRecordType::TxnFinished => Bytes::from(TXN_FIN),
+            _ => Bytes::from(key),
+        };
+        Ok((
+            CdcRecord {
+                pos,
+                next_pos,
+                seq_no,
+                record_type,
+                key,
+                value: Bytes::from(value),
+            },
+            seq_no,
+        ))
+    }
+}
+
+// 加载持久化的cdc reader进度
+pub(crate) fn load_cdc_readers(dir_path: PathBuf) -> Result<HashMap<String, CdcPosition>> {
+    let mut cdc_readers = HashMap::new();
+    let buf = match std::fs::read(dir_path.join(CDC_READERS_FILE_NAME)) {
+        Ok(buf) => buf,
+        Err(_) => return Ok(cdc_readers),
+    };
+    let mut offset = 0;
+    while let Some(read_log_record) = DataFile::decode_log_record(&buf[offset..]) {
+        let read_log_record = read_log_record?;
+        offset += read_log_record.size as usize;
+        let name = String::from_utf8(read_log_record.logrecord.key).unwrap();
+        let pos = LogRecordPos::decode(read_log_record.logrecord.value);
+        cdc_readers.insert(
+            name,
+            CdcPosition {
+                file_id: pos.file_id,
+                offset: pos.offset,
+            },
+        );
+    }
+    Ok(cdc_readers)
+}
+
+// 进度文件整体替换,崩溃之后不会读到写了一半的文件
+fn save_cdc_readers(dir_path: PathBuf, cdc_readers: &HashMap<String, CdcPosition>) -> Result<()> {
+    let mut buf = Vec::new();
+    for (name, pos) in cdc_readers.iter() {
+        let log_record = LogRecord {
+            key: name.as_bytes().to_vec(),
+            value: LogRecordPos {
+                file_id: pos.file_id,
+                offset: pos.offset,
+                value_size: 0,
+            }
+            .encode(),
+            log_type: LogRecordType::NORMAL,
+        };
+        buf.extend_from_slice(&log_record.encode());
+    }
+    replace_file(&dir_path, CDC_READERS_FILE_NAME, &buf)
+}
+
+#[cfg(test)]
+mod test_cdc {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use super::{CdcPosition, CdcRecordType, ResumeToken};
+    use crate::{
+        db::Engine,
+        errors::Errors,
+        options::{Options, WriteBatchOptions},
+        util::rand_kv::{get_test_key, get_test_value},
+    };
+
+    #[test]
+    fn test_cdc_reader() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-cdc");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine.put(Bytes::from("a"), Bytes::from("1")).unwrap();
+        let write_batch = engine
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        write_batch.put(Bytes::from("b"), Bytes::from("2")).unwrap();
+        write_batch.put(Bytes::from("c"), Bytes::from("3")).unwrap();
+        write_batch.commit().unwrap();
+        engine.delete(Bytes::from("a")).unwrap();
+
+        let mut reader = engine
+            .register_cdc_reader("test", ResumeToken::Position(CdcPosition::default()))
+            .unwrap();
+        // 还没有持久化的记录不会读到
+        assert_eq!(reader.read(10).unwrap().len(), 4);
+        assert!(reader.read(10).unwrap().is_empty());
+        engine.sync().unwrap();
+        let mut reader = engine
+            .register_cdc_reader("test", ResumeToken::Position(CdcPosition::default()))
+            .unwrap();
+        // 批量提交的记录会一起返回
+        let records = reader.read(2).unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].record_type, CdcRecordType::Put);
+        assert_eq!(records[0].key, "a");
+        assert_eq!(records[0].value, "1");
+        let seq_no = records[1].seq_no;
+        assert!(seq_no > 0);
+        assert_eq!(records[2].seq_no, seq_no);
+        assert_eq!(records[3].record_type, CdcRecordType::TxnFinished);
+        let records2 = reader.read(10).unwrap();
+        assert_eq!(records2.len(), 1);
+        assert_eq!(records2[0].record_type, CdcRecordType::Delete);
+        assert!(reader.read(10).unwrap().is_empty());
+        // 不是日志开头的位置、超过读取位置的位置都不能commit
+        let bad_pos = CdcPosition {
+            offset: records[1].pos.offset + 1,
+            ..records[1].pos
+        };
+        assert_eq!(reader.commit(bad_pos), Err(Errors::InvalidCdcPosition));
+        let beyond_pos = CdcPosition {
+            offset: records2[0].next_pos.offset + 100,
+            ..records2[0].next_pos
+        };
+        assert_eq!(reader.commit(beyond_pos), Err(Errors::InvalidCdcPosition));
+        // 只commit到批量提交结束的位置
+        reader.commit(records[3].next_pos).unwrap();
+        // 不能退回到已经commit的位置之前
+        assert_eq!(
+            reader.commit(records[0].pos),
+            Err(Errors::InvalidCdcPosition)
+        );
+
+        // 从seq_no开始读取
+        let mut seq_reader = engine
+            .register_cdc_reader("seq", ResumeToken::SeqNo(seq_no))
+            .unwrap();
+        assert_eq!(seq_reader.read(10).unwrap(), records2);
+        assert_eq!(
+            engine
+                .register_cdc_reader("bad", ResumeToken::SeqNo(seq_no + 100))
+                .err()
+                .unwrap(),
+            Errors::CdcSeqNoNotFound
+        );
+        engine.unregister_cdc_reader("seq").unwrap();
+        assert_eq!(
+            engine.open_cdc_reader("seq").err().unwrap(),
+            Errors::CdcReaderNotFound
+        );
+
+        // 重启之后从commit的位置继续读取
+        engine.close().unwrap();
+        drop(engine);
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        let mut reader = engine2.open_cdc_reader("test").unwrap();
+        assert_eq!(reader.read(10).unwrap(), records2);
+        engine2.put(Bytes::from("d"), Bytes::from("4")).unwrap();
+        engine2.sync().unwrap();
+        let records3 = reader.read(10).unwrap();
+        assert_eq!(records3.len(), 1);
+        assert_eq!(records3[0].key, "d");
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_cdc_reader_retain_files_across_merge() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-cdc-merge");
+        opts.file_size_threshlod = 32 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        // 这个reader读到一半
+        let mut reader = engine
+            .register_cdc_reader("test", ResumeToken::Position(CdcPosition::default()))
+            .unwrap();
+        let records = reader.read(500).unwrap();
+        let next_pos = records.last().unwrap().next_pos;
+        reader.commit(next_pos).unwrap();
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine.merge().unwrap();
+        engine.close().unwrap();
+        drop(engine);
+
+        // merge只处理reader已经读过的文件,reader可以继续读完剩下的记录
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..1000 {
+            assert_eq!(engine2.get(get_test_key(i)).unwrap(), get_test_value(i));
+        }
+        let mut reader = engine2.open_cdc_reader("test").unwrap();
+        assert_eq!(reader.position(), next_pos);
+        let records = reader.read(usize::MAX).unwrap();
+        assert_eq!(records.len(), 1500);
+        assert_eq!(records[0].key, get_test_key(500));
+        assert_eq!(records[1499].key, get_test_key(999));
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
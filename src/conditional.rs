@@ -0,0 +1,155 @@
+use bytes::Bytes;
+
+use crate::{
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+impl Engine {
+    // 当key当前的值等于expected时,将其替换为new,返回是否替换成功
+    // expected为None表示key不存在,new为None表示删除key
+    pub fn compare_and_swap(
+        &self,
+        key: Bytes,
+        expected: Option<Bytes>,
+        new: Option<Bytes>,
+    ) -> Result<bool> {
+        if key.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        self.check_writable()?;
+        // 拿写锁,读取和追加期间不会有其他写入
+        let _guard = self.conditional_write_lock.write();
+        if self.get_current_value(&key)? != expected {
+            return Ok(false);
+        }
+        match new {
+            Some(value) => self.put_record(key, value)?,
+            None => self.delete_record(key)?,
+        }
+        Ok(true)
+    }
+
+    // key不存在时才写入,返回是否写入成功
+    pub fn put_if_absent(&self, key: Bytes, value: Bytes) -> Result<bool> {
+        self.compare_and_swap(key, None, Some(value))
+    }
+
+    // key当前的值等于expected时才删除,返回是否删除成功
+    pub fn delete_if_equals(&self, key: Bytes, expected: Bytes) -> Result<bool> {
+        self.compare_and_swap(key, Some(expected), None)
+    }
+
+    // 读取key当前的值,不存在时返回None
+    pub(crate) fn get_current_value(&self, key: &Bytes) -> Result<Option<Bytes>> {
+        let log_record_pos = match self.indexer.get(key.to_vec()) {
+            Some(pos) => pos,
+            None => return Ok(None),
+        };
+        match self.get_value_by_pos(&log_record_pos) {
+            Ok(value) => Ok(Some(value)),
+            Err(Errors::KeyNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_conditional {
+    use std::{path::PathBuf, sync::Arc};
+
+    use bytes::Bytes;
+
+    use crate::{db::Engine, errors::Errors, options::Options};
+
+    #[test]
+    fn test_compare_and_swap() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-cas");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // key不存在时才能写入
+        assert!(engine
+            .put_if_absent(Bytes::from("lock"), Bytes::from("owner1"))
+            .unwrap());
+        assert!(!engine
+            .put_if_absent(Bytes::from("lock"), Bytes::from("owner2"))
+            .unwrap());
+        assert_eq!(engine.get(Bytes::from("lock")).unwrap(), "owner1");
+
+        // 值不匹配时不会替换
+        let res = engine.compare_and_swap(
+            Bytes::from("lock"),
+            Some(Bytes::from("owner2")),
+            Some(Bytes::from("owner3")),
+        );
+        assert!(!res.unwrap());
+        let res = engine.compare_and_swap(
+            Bytes::from("lock"),
+            Some(Bytes::from("owner1")),
+            Some(Bytes::from("owner3")),
+        );
+        assert!(res.unwrap());
+        assert_eq!(engine.get(Bytes::from("lock")).unwrap(), "owner3");
+
+        // 值相等时才删除
+        assert!(!engine
+            .delete_if_equals(Bytes::from("lock"), Bytes::from("owner1"))
+            .unwrap());
+        assert!(engine
+            .delete_if_equals(Bytes::from("lock"), Bytes::from("owner3"))
+            .unwrap());
+        assert_eq!(
+            engine.get(Bytes::from("lock")).err().unwrap(),
+            Errors::KeyNotFound
+        );
+
+        // 空key
+        let res = engine.put_if_absent(Bytes::new(), Bytes::from("value"));
+        assert_eq!(res.err().unwrap(), Errors::KeyEmptyErr);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_concurrent_compare_and_swap() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-concurrent-cas");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        engine
+            .put(Bytes::from("counter"), Bytes::from("0"))
+            .unwrap();
+        let mut handles = Vec::new();
+        for _ in 0..8 {
+            let engine = engine.clone();
+            let handle = std::thread::spawn(move || {
+                let mut done = 0;
+                while done < 100 {
+                    let current = engine.get(Bytes::from("counter")).unwrap();
+                    let n: u64 = String::from_utf8(current.to_vec())
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    let next = Bytes::from((n + 1).to_string());
+                    if engine
+                        .compare_and_swap(Bytes::from("counter"), Some(current), Some(next))
+                        .unwrap()
+                    {
+                        done += 1;
+                    }
+                    // 同时有普通写入
+                    engine
+                        .put(Bytes::from("other"), Bytes::from("value"))
+                        .unwrap();
+                }
+            });
+            handles.push(handle);
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(engine.get(Bytes::from("counter")).unwrap(), "800");
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
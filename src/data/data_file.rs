@@ -11,7 +11,7 @@ use prost::length_delimiter_len;
 use crate::errors::Errors;
 use crate::errors::Result;
 use crate::fio;
-use crate::fio::new_io_manager;
+use crate::fio::{new_io_manager, new_read_only_io_manager};
 
 use super::log_record::*;
 pub struct DataFile {
@@ -22,8 +22,10 @@ pub struct DataFile {
 }
 
 pub const DATA_FILE_NAME_SUFFIX: &str = ".data";
+pub const BLOB_FILE_NAME_SUFFIX: &str = ".blob";
 pub const HIT_FILE_NAME: &str = "hint-index";
 pub const MERGE_FINISHED_FILE_NAME: &str = "merge-finished";
+pub const BLOB_NEXT_FILE_ID_FILE_NAME: &str = "blob-next-file-id";
 impl DataFile {
     pub fn new_hint_file(dir_path: PathBuf) -> Result<DataFile> {
         let file_name = dir_path.join(HIT_FILE_NAME);
@@ -55,6 +57,36 @@ impl DataFile {
         dirpath.join(file_id_str)
     }
 
+    pub fn get_blob_file_name(dirpath: PathBuf, file_id: u32) -> PathBuf {
+        let file_id_str = std::format!("{:09}", file_id) + BLOB_FILE_NAME_SUFFIX;
+        dirpath.join(file_id_str)
+    }
+
+    // 打开一个存放大value的blob文件
+    pub fn new_blob_file(dirpath: PathBuf, file_id: u32) -> Result<DataFile> {
+        let file_name = DataFile::get_blob_file_name(dirpath, file_id);
+        let io_manager = new_io_manager(&file_name)?;
+        Ok(DataFile {
+            file_id,
+            write_offset: RwLock::new(0),
+            fio: Box::new(io_manager),
+        })
+    }
+
+    // 只读打开一个已经存在的文件,写入位置就是文件的大小
+    pub fn open_read_only(file_name: PathBuf, file_id: u32) -> Result<DataFile> {
+        let io_manager = new_read_only_io_manager(&file_name)?;
+        let size = match fs::metadata(&file_name) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return Err(Errors::FailReadFromFile),
+        };
+        Ok(DataFile {
+            file_id,
+            write_offset: RwLock::new(size),
+            fio: Box::new(io_manager),
+        })
+    }
+
     // 获取新的DataFile放到old_files这一map当中来
     pub fn new(dirpath: PathBuf, file_id: u32) -> Result<DataFile> {
         let file_name = DataFile::get_file_name(dirpath, file_id);
@@ -116,6 +148,72 @@ impl DataFile {
         Ok(read_log_record)
     }
 
+    // 只读取LogRecord的header,返回(类型, key长度, value长度, header长度)
+    pub fn read_log_record_header(
+        &self,
+        offset: u64,
+    ) -> Result<(LogRecordType, usize, usize, usize)> {
+        let mut header_bytes = BytesMut::zeroed(LogRecord::max_logrecord_header());
+        self.fio.read(&mut header_bytes, offset)?;
+        let rec_typ = header_bytes.get_u8();
+        let key_size = decode_length_delimiter(&mut header_bytes).unwrap();
+        let value_size = decode_length_delimiter(&mut header_bytes).unwrap();
+        if key_size == 0 {
+            return Err(Errors::DataFileReadEOF);
+        }
+        let actual_header_size =
+            length_delimiter_len(key_size) + length_delimiter_len(value_size) + 1;
+        Ok((
+            LogRecordType::from_byte(rec_typ),
+            key_size,
+            value_size,
+            actual_header_size,
+        ))
+    }
+
+    // 读取从offset开始的一段数据,读到文件末尾时返回的数据会比len短
+    pub fn read_bytes(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0; len];
+        let n = self.fio.read(&mut buf, offset)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    // 从一段已经读出来的数据中解析LogRecord,数据不完整时返回None
+    pub fn decode_log_record(buf: &[u8]) -> Option<Result<ReadLogRecord>> {
+        if buf.is_empty() {
+            return None;
+        }
+        let rec_typ = buf[0];
+        let mut header = &buf[1..];
+        let key_size = decode_length_delimiter(&mut header).ok()?;
+        let value_size = decode_length_delimiter(&mut header).ok()?;
+        if key_size == 0 {
+            return Some(Err(Errors::DataFileReadEOF));
+        }
+        let actual_header_size =
+            length_delimiter_len(key_size) + length_delimiter_len(value_size) + 1;
+        let size = actual_header_size + key_size + value_size + 4;
+        if buf.len() < size {
+            return None;
+        }
+        let kv_buf = &buf[actual_header_size..size];
+        let logrecord = LogRecord {
+            key: kv_buf[..key_size].to_vec(),
+            value: kv_buf[key_size..key_size + value_size].to_vec(),
+            log_type: LogRecordType::from_byte(rec_typ),
+        };
+        // 做checksum检测
+        let checksum = (&kv_buf[key_size + value_size..]).get_u32();
+        if checksum != logrecord.crc32() {
+            return Some(Err(Errors::CheckSumFailed));
+        }
+        Some(Ok(ReadLogRecord {
+            logrecord,
+            size: size as i64,
+        }))
+    }
+
     // 写数据到文件当中
     pub fn write(&self, buf: &[u8]) -> Result<usize> {
         let size = self.fio.write(buf)?;
@@ -129,6 +227,67 @@ impl DataFile {
     }
     /// 加载数据文件
     pub fn load_data_files(dirpath: PathBuf) -> Result<Vec<DataFile>> {
+        DataFile::load_files(dirpath, DATA_FILE_NAME_SUFFIX, false)
+    }
+
+    /// 加载blob文件
+    pub fn load_blob_files(dirpath: PathBuf) -> Result<Vec<DataFile>> {
+        DataFile::load_files(dirpath, BLOB_FILE_NAME_SUFFIX, false)
+    }
+
+    /// 只读加载数据文件
+    pub fn load_read_only_data_files(dirpath: PathBuf) -> Result<Vec<DataFile>> {
+        let data_files = DataFile::load_files(dirpath, DATA_FILE_NAME_SUFFIX, true)?;
+        // 写入的engine可能正在写最新的文件,末尾不完整或者损坏的日志当作文件结尾
+        if let Some(active_file) = data_files.last() {
+            let valid_end = active_file.valid_log_end();
+            *active_file.write_offset.write() = valid_end;
+        }
+        Ok(data_files)
+    }
+
+    // 从头开始检查日志,返回最后一条完整日志的末尾位置
+    // 数据可能是任意内容,不能像read_log_record一样在解析失败时panic
+    fn valid_log_end(&self) -> u64 {
+        let size = self.get_wtite_offset();
+        let mut offset = 0;
+        while offset < size {
+            let header = match self.read_bytes(offset, LogRecord::max_logrecord_header()) {
+                Ok(header) if !header.is_empty() => header,
+                _ => break,
+            };
+            if LogRecordType::try_from_byte(header[0]).is_none() {
+                break;
+            }
+            let mut sizes = &header[1..];
+            let (key_size, value_size) = match (
+                decode_length_delimiter(&mut sizes),
+                decode_length_delimiter(&mut sizes),
+            ) {
+                (Ok(key_size), Ok(value_size)) => (key_size as u64, value_size as u64),
+                _ => break,
+            };
+            let record_size = (header.len() - sizes.len()) as u64 + key_size + value_size + 4;
+            if key_size == 0 || offset + record_size > size {
+                break;
+            }
+            match self
+                .read_bytes(offset, record_size as usize)
+                .map(|buf| DataFile::decode_log_record(&buf))
+            {
+                Ok(Some(Ok(_))) => offset += record_size,
+                _ => break,
+            }
+        }
+        offset
+    }
+
+    /// 只读加载blob文件
+    pub fn load_read_only_blob_files(dirpath: PathBuf) -> Result<Vec<DataFile>> {
+        DataFile::load_files(dirpath, BLOB_FILE_NAME_SUFFIX, true)
+    }
+
+    fn load_files(dirpath: PathBuf, suffix: &str, read_only: bool) -> Result<Vec<DataFile>> {
         // 1.读取数据目录
         let dir_files = fs::read_dir(dirpath.clone());
         if dir_files.is_err() {
@@ -142,7 +301,7 @@ impl DataFile {
             let file_name = entry.to_str().unwrap();
             let metadata = std::fs::metadata(dirpath.join(file_name)).unwrap();
             // 我们只需要拿到数据文件,所以我们需要看后缀名
-            if file_name.ends_with(DATA_FILE_NAME_SUFFIX) {
+            if file_name.ends_with(suffix) {
                 let splits: Vec<&str> = file_name.split(".").collect();
                 let file_id = match splits[0].parse::<u32>() {
                     Ok(file_id) => file_id,
@@ -157,7 +316,18 @@ impl DataFile {
         for file_id in file_ids {
             // 这里出现错误我们不用unwarp将其panic掉
             // 而是使用?范围Err
-            let datafile = DataFile::new(dirpath.clone(), file_id)?;
+            if read_only {
+                let file_name = match suffix {
+                    BLOB_FILE_NAME_SUFFIX => DataFile::get_blob_file_name(dirpath.clone(), file_id),
+                    _ => DataFile::get_file_name(dirpath.clone(), file_id),
+                };
+                datafiles.push(DataFile::open_read_only(file_name, file_id)?);
+                continue;
+            }
+            let datafile = match suffix {
+                BLOB_FILE_NAME_SUFFIX => DataFile::new_blob_file(dirpath.clone(), file_id)?,
+                _ => DataFile::new(dirpath.clone(), file_id)?,
+            };
             datafile.set_write_offset(*file_size.get(&file_id).unwrap() as u64);
             datafiles.push(datafile);
         }
@@ -297,4 +467,24 @@ mod data_file_test {
         assert_eq!(log_record.value, logrecord3.value);
         assert_eq!(log_record.log_type, logrecord3.log_type);
     }
+
+    #[test]
+    fn test_decode_log_record() {
+        let logrecord = LogRecord {
+            key: "key1".as_bytes().to_vec(),
+            value: "value1".as_bytes().to_vec(),
+            log_type: NORMAL,
+        };
+        let enc = logrecord.encode();
+        let read_logrecord = DataFile::decode_log_record(&enc).unwrap().unwrap();
+        assert_eq!(read_logrecord.size as usize, enc.len());
+        assert_eq!(read_logrecord.logrecord.key, logrecord.key);
+        assert_eq!(read_logrecord.logrecord.value, logrecord.value);
+        // 数据不完整
+        assert!(DataFile::decode_log_record(&enc[..enc.len() - 1]).is_none());
+        // 数据损坏
+        let mut broken = enc.clone();
+        broken[3] ^= 0xff;
+        assert!(DataFile::decode_log_record(&broken).unwrap().is_err());
+    }
 }
@@ -2,7 +2,7 @@ use std::os::unix::raw::off_t;
 
 use bytes::{BufMut, BytesMut};
 use prost::{
-    encode_length_delimiter,
+    decode_length_delimiter, encode_length_delimiter,
     encoding::{decode_varint, encode_varint},
     length_delimiter_len,
 };
@@ -19,6 +19,8 @@ pub struct LogRecordPos {
     pub(crate) file_id: u32,
     // 在当前项目包可见即可
     pub(crate) offset: u64,
+    // value的长度,不需要读盘就可以拿到
+    pub(crate) value_size: u32,
 }
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -26,13 +28,63 @@ pub enum LogRecordType {
     NORMAL = 1,
     DELETED = 2,
     TXNCOMMITTED = 3,
+    // 范围删除,key是范围的起始key,value是结束key(不包含),value为空表示没有上界
+    RANGEDELETED = 4,
+    // value存放在blob文件中,value是BlobPos的编码
+    BLOBINDEX = 5,
+    // 时间标记,value是写入时间的毫秒数,之后的记录都在这个时间之后写入
+    TIMESTAMP = 6,
+}
+
+// 大value在blob文件中的位置
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlobPos {
+    pub(crate) file_id: u32,
+    pub(crate) offset: u64,
+    pub(crate) value_size: u32,
+}
+
+impl BlobPos {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        encode_varint(self.file_id as u64, &mut buf);
+        encode_varint(self.offset, &mut buf);
+        encode_varint(self.value_size as u64, &mut buf);
+        buf.to_vec()
+    }
+
+    pub fn decode(pos: &[u8]) -> BlobPos {
+        let mut buf = pos;
+        let mut next = || match decode_varint(&mut buf) {
+            Ok(v) => v,
+            Err(e) => panic!("decode blob_pos error:{}", e),
+        };
+        BlobPos {
+            file_id: next() as u32,
+            offset: next(),
+            value_size: next() as u32,
+        }
+    }
 }
 
 impl LogRecordPos {
+    pub fn get_file_id(&self) -> u32 {
+        self.file_id
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_value_size(&self) -> u32 {
+        self.value_size
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         let mut buf = BytesMut::new();
         encode_varint(self.file_id as u64, &mut buf);
         encode_varint(self.offset, &mut buf);
+        encode_varint(self.value_size as u64, &mut buf);
         buf.to_vec()
     }
     pub fn decode(pos: Vec<u8>) -> LogRecordPos {
@@ -46,20 +98,40 @@ impl LogRecordPos {
             Ok(_offset) => _offset,
             Err(e) => panic!("decode logrecord_pos error:{}", e),
         };
+        // 老版本的hint file中没有记录value的长度
+        let value_size = match buf.is_empty() {
+            true => 0,
+            false => match decode_varint(&mut buf) {
+                Ok(_value_size) => _value_size,
+                Err(e) => panic!("decode logrecord_pos error:{}", e),
+            },
+        };
         LogRecordPos {
             file_id: fid as u32,
             offset: offset,
+            value_size: value_size as u32,
         }
     }
 }
 
 impl LogRecordType {
     pub fn from_byte(record_type: u8) -> LogRecordType {
+        match LogRecordType::try_from_byte(record_type) {
+            Some(log_type) => log_type,
+            None => panic!("unknown record type"),
+        }
+    }
+
+    // 未知的类型返回None,用于检查可能损坏的数据
+    pub fn try_from_byte(record_type: u8) -> Option<LogRecordType> {
         match record_type {
-            1 => LogRecordType::NORMAL,
-            2 => LogRecordType::DELETED,
-            3 => LogRecordType::TXNCOMMITTED,
-            _ => panic!("unknown record type"),
+            1 => Some(LogRecordType::NORMAL),
+            2 => Some(LogRecordType::DELETED),
+            3 => Some(LogRecordType::TXNCOMMITTED),
+            4 => Some(LogRecordType::RANGEDELETED),
+            5 => Some(LogRecordType::BLOBINDEX),
+            6 => Some(LogRecordType::TIMESTAMP),
+            _ => None,
         }
     }
 }
@@ -125,6 +197,26 @@ impl LogRecord {
         + 4 // crc32
     }
 
+    // 从编码后的logrecord中解析出value的长度,value在blob文件中时返回真实的长度
+    pub fn decode_value_size(enc: &[u8]) -> usize {
+        let mut header = &enc[1..];
+        let key_size = decode_length_delimiter(&mut header).unwrap();
+        let value_size = decode_length_delimiter(&mut header).unwrap();
+        if LogRecordType::from_byte(enc[0]) != LogRecordType::BLOBINDEX {
+            return value_size;
+        }
+        let value_start = enc.len() - header.len() + key_size;
+        BlobPos::decode(&enc[value_start..value_start + value_size]).value_size as usize
+    }
+
+    // value的真实长度,value在blob文件中时返回blob中的长度
+    pub fn value_size(&self) -> usize {
+        match self.log_type {
+            LogRecordType::BLOBINDEX => BlobPos::decode(&self.value).value_size as usize,
+            _ => self.value.len(),
+        }
+    }
+
     // 获取logrecord的header长度的理论最大值
     pub fn max_logrecord_header() -> usize {
         std::mem::size_of::<u8>() + length_delimiter_len(std::u32::MAX as usize) * 2
@@ -133,7 +225,7 @@ impl LogRecord {
 
 #[cfg(test)]
 mod log_record_test {
-    use super::{LogRecord, LogRecordType::*};
+    use super::{BlobPos, LogRecord, LogRecordType::*};
 
     #[test]
     fn test_encode_and_crc() {
@@ -167,4 +259,31 @@ mod log_record_test {
         assert!(enc3.len() > 5);
         assert_eq!(1816502328, log_record3.crc32());
     }
+
+    #[test]
+    fn test_blob_pos_and_value_size() {
+        let blob_pos = BlobPos {
+            file_id: 3,
+            offset: 1 << 40,
+            value_size: 10 * 1024 * 1024,
+        };
+        assert_eq!(BlobPos::decode(&blob_pos.encode()), blob_pos);
+
+        let log_record = LogRecord {
+            key: "key".as_bytes().to_vec(),
+            value: blob_pos.encode(),
+            log_type: BLOBINDEX,
+        };
+        assert_eq!(log_record.value_size(), 10 * 1024 * 1024);
+        assert_eq!(
+            LogRecord::decode_value_size(&log_record.encode()),
+            10 * 1024 * 1024
+        );
+        let log_record = LogRecord {
+            key: "key".as_bytes().to_vec(),
+            value: "value".as_bytes().to_vec(),
+            log_type: NORMAL,
+        };
+        assert_eq!(LogRecord::decode_value_size(&log_record.encode()), 5);
+    }
 }
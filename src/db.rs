@@ -1,22 +1,27 @@
 use std::collections::HashMap;
 use std::fs;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use bytes::{Bytes, BytesMut};
 use log::error;
-use parking_lot::{Mutex, RwLock};
+use parking_lot::{Mutex, RwLock, RwLockWriteGuard};
 use prost::decode_length_delimiter;
 
-use crate::data::data_file::MERGE_FINISHED_FILE_NAME;
-use crate::data::log_record::{LogRecordType, ReadLogRecord};
+use crate::blob::BlobFiles;
+use crate::cdc::{load_cdc_readers, CdcPosition};
+use crate::data::log_record::{BlobPos, LogRecordType, ReadLogRecord};
 use crate::data::{
     data_file::DataFile,
     log_record::{LogRecord, LogRecordPos},
 };
 use crate::errors::{Errors, Result};
+use crate::flusher::{Flusher, SyncState};
+use crate::group_commit::GroupCommit;
 use crate::index::{Indexer, NewIndexer};
-use crate::options::Options;
+use crate::options::{Options, SyncPolicy};
+use crate::replication::ReplicaState;
+use crate::watch::{Change, Watchers};
 use crate::write_batch::{WriteBatch, TXN_FIN};
 
 pub const NO_TXN_SEQ_NO: usize = 0;
@@ -37,6 +42,31 @@ pub struct Engine {
     pub(crate) seq_no: Arc<AtomicUsize>,
 
     pub(crate) merge_lock: Mutex<()>,
+
+    // 并发写入的组提交队列
+    pub(crate) group_commit: GroupCommit,
+
+    // 普通写入拿读锁可以并发进行,条件写入拿写锁,
+    // 保证读取当前值和追加日志之间不会有其他写入
+    pub(crate) conditional_write_lock: RwLock<()>,
+
+    // 持久化进度
+    pub(crate) sync_state: Arc<SyncState>,
+    // SyncPolicy::Interval 时的后台持久化线程,engine被drop时随之停止
+    #[allow(dead_code)]
+    pub(crate) flusher: Option<Flusher>,
+
+    // 大value分离出去的blob文件
+    pub(crate) blob_files: BlobFiles,
+
+    // watch的订阅者
+    pub(crate) watchers: Arc<Watchers>,
+
+    // 已经注册的cdc reader的进度
+    pub(crate) cdc_readers: Mutex<HashMap<String, CdcPosition>>,
+
+    // 只读副本的回放状态,不是副本时为None
+    pub(crate) replica: Option<Mutex<ReplicaState>>,
 }
 
 const INIT_FILE_ID: u32 = 0;
@@ -46,33 +76,54 @@ impl Engine {
     // 防止数据丢失
     pub fn sync(&self) -> Result<()> {
         let write_guard = self.data_file.write();
-        write_guard.sync()
+        self.blob_files.sync()?;
+        self.sync_state.sync(&write_guard)
     }
     // close
     // 资源清理
     pub fn close(&self) -> Result<()> {
         let write_guard = self.data_file.write();
-        write_guard.sync()
+        self.blob_files.sync()?;
+        self.sync_state.sync(&write_guard)
     }
 
     // 根据配置打开一个DB实例
     pub fn open(options: Options) -> Result<Self> {
+        Engine::open_engine(options, false)
+    }
+
+    // 打开一个只读副本,数据只能通过复制从leader同步过来
+    pub fn open_replica(options: Options) -> Result<Self> {
+        Engine::open_engine(options, true)
+    }
+
+    fn open_engine(options: Options, replica: bool) -> Result<Self> {
         println!("文件夹:{:?}", options.dir_path);
         // 首先需要检测options的合法性
         if let Some(e) = options.check_options() {
             return Err(e);
         }
-        // 合法性检测通过(当然不是完整的合法检测，比如目录是否是合法的没做)
-        // 后,我们就开始看目录是否存在，不存在，就自己创建一个新的目录
-        if let Err(e) = fs::create_dir_all(options.dir_path.as_path()) {
-            // 这里创建文件失败
-            error!("create database dirpath failed: {}", e);
-            return Err(Errors::DirPathCreateFailed);
+        // 副本需要写入从leader同步过来的日志
+        if replica && options.read_only {
+            return Err(Errors::ReadOnly);
+        }
+        // 只读打开时不创建目录,也不移动merge的文件,没有应用的merge结果直接忽略
+        if !options.read_only {
+            // 合法性检测通过(当然不是完整的合法检测，比如目录是否是合法的没做)
+            // 后,我们就开始看目录是否存在，不存在，就自己创建一个新的目录
+            if let Err(e) = fs::create_dir_all(options.dir_path.as_path()) {
+                // 这里创建文件失败
+                error!("create database dirpath failed: {}", e);
+                return Err(Errors::DirPathCreateFailed);
+            }
+            // 加载merge files(将merge的文件给移动过来)
+            Engine::load_merge_files(options.dir_path.clone()).unwrap();
         }
-        // 加载merge files(将merge的文件给移动过来)
-        Engine::load_merge_files(options.dir_path.clone()).unwrap();
         // 开始加载文件
-        let mut data_files = DataFile::load_data_files(options.dir_path.clone())?;
+        let mut data_files = match options.read_only {
+            true => DataFile::load_read_only_data_files(options.dir_path.clone())?,
+            false => DataFile::load_data_files(options.dir_path.clone())?,
+        };
         // 切分active_files 和 old_files
         let active_file: DataFile;
         let mut max_file_id = 0;
@@ -82,30 +133,59 @@ impl Engine {
         // 拿到active_file
         if data_files.len() > 0 {
             active_file = data_files.pop().unwrap();
+        } else if options.read_only {
+            return Err(Errors::DataFileNotFound);
         } else {
             active_file = DataFile::new(options.dir_path.clone(), INIT_FILE_ID).unwrap();
         }
 
-        // old files
+        // old files,merge之后文件id不一定连续,按照文件自己的id存放
         let mut old_files_hashmap = HashMap::new();
-        if data_files.len() >= 1 {
-            for id in 0..=data_files.len() - 1 {
-                let old_file = data_files.pop().unwrap();
-                old_files_hashmap.insert(id as u32, old_file);
-            }
+        for old_file in data_files {
+            old_files_hashmap.insert(old_file.get_file_id(), old_file);
         }
+        // 磁盘上已有的数据都是持久化过的
+        let durable_pos = LogRecordPos {
+            file_id: active_file.get_file_id(),
+            offset: active_file.get_wtite_offset(),
+            value_size: 0,
+        };
+        let watchers = Arc::new(Watchers::new(durable_pos));
+        let sync_state = Arc::new(SyncState::new(durable_pos, watchers.clone()));
+        let data_file = Arc::new(RwLock::new(active_file));
+        let flusher = match options.sync_policy {
+            SyncPolicy::Interval(interval) if !options.read_only => Some(Flusher::start(
+                interval,
+                data_file.clone(),
+                sync_state.clone(),
+            )),
+            _ => None,
+        };
+        let cdc_readers = load_cdc_readers(options.dir_path.clone())?;
+        let blob_files = BlobFiles::open(
+            options.dir_path.clone(),
+            options.file_size_threshlod,
+            options.read_only,
+        )?;
         // 构建DB实例
         let engine = Engine {
             max_file_id: max_file_id as u32,
             indexer: NewIndexer(options.index_type),
             options: options,
-            data_file: Arc::new(RwLock::new(active_file)),
+            data_file,
             old_files: Arc::new(RwLock::new(old_files_hashmap)),
             batch_commit_lock: Arc::new(Mutex::new(())),
             seq_no: Arc::new(AtomicUsize::new(0)),
             merge_lock: Mutex::new(()),
+            group_commit: GroupCommit::new(),
+            conditional_write_lock: RwLock::new(()),
+            sync_state,
+            flusher,
+            blob_files,
+            watchers,
+            cdc_readers: Mutex::new(cdc_readers),
+            replica: replica.then(|| Mutex::new(ReplicaState::new())),
         };
-        engine.load_index_from_datafiles().unwrap();
         // 加载索引
         match engine.load_index_from_datafiles() {
             Ok(_) => return Ok(engine),
@@ -113,11 +193,24 @@ impl Engine {
         }
     }
 
+    // 只读打开和副本都不能直接写入
+    pub(crate) fn check_writable(&self) -> Result<()> {
+        if self.replica.is_some() {
+            return Err(Errors::ReadOnly);
+        }
+        self.check_read_only()
+    }
+
+    // 只读打开时不能修改数据目录中的任何文件
+    pub(crate) fn check_read_only(&self) -> Result<()> {
+        if self.options.read_only {
+            return Err(Errors::ReadOnly);
+        }
+        Ok(())
+    }
+
     pub(crate) fn parse_key(&self, key: Vec<u8>) -> (Vec<u8>, usize) {
-        let mut buf = BytesMut::new();
-        buf.extend_from_slice(&key);
-        let seq_no = decode_length_delimiter(&mut buf).unwrap();
-        return (buf.to_vec(), seq_no);
+        parse_log_key(key)
     }
 
     fn load_index_from_datafiles(&self) -> Result<()> {
@@ -125,105 +218,53 @@ impl Engine {
         if self.max_file_id == 0 {
             return Ok(());
         }
-        let mut no_merged_file_id = 0;
-        let mut merge_finished = false;
-        let merge_finished_file;
-        let merge_finish_path = self.options.dir_path.join(MERGE_FINISHED_FILE_NAME);
-        if merge_finish_path.is_file() {
-            merge_finished = true;
-            merge_finished_file = DataFile::new_finished_file(merge_finish_path)?;
-            let read_logrecord = merge_finished_file.read_log_record(0)?;
-            let v = String::from_utf8(read_logrecord.logrecord.value).unwrap();
-            no_merged_file_id = v.parse::<u32>().unwrap();
+        // merge过的文件的索引直接从hint文件中加载
+        let no_merged_file_id = Engine::no_merged_file_id(self.options.dir_path.clone())?;
+        if no_merged_file_id > 0 {
+            self.load_hint_file()?;
         }
 
         let read_guard = self.old_files.read();
-        // 暂存批量提交的log_record
-        let mut logrecords = Vec::new();
-        let mut current_seq_no = NO_TXN_SEQ_NO;
-        for id in 0..=self.max_file_id - 1 {
-            let mut file: Option<&DataFile> = None;
-            // 1.拿到读锁
-            if id != self.max_file_id - 1 {
-                // old files
-                file = Some(read_guard.get(&id).unwrap());
-                // 对于old file如果是已经被merge过的不要再load index了
-                if merge_finished {
-                    if file.unwrap().get_file_id() < no_merged_file_id {
-                        continue;
-                    }
-                }
-            }
-            let mut offset = 0;
-            loop {
-                let logrecord_res: Result<ReadLogRecord>;
-                if id == self.max_file_id - 1 {
-                    logrecord_res = self.data_file.read().read_log_record(offset);
-                } else {
-                    logrecord_res = file.unwrap().read_log_record(offset);
-                }
-                let (mut logrecord, size) = match logrecord_res {
-                    Ok(res) => (res.logrecord, res.size),
-                    Err(e) => {
-                        if e == Errors::DataFileReadEOF {
-                            break;
-                        }
-                        return Err(e);
-                    }
-                };
-
-                let (new_key, seq_no) = self.parse_key(logrecord.key);
-                logrecord.key = new_key;
-                if seq_no == NO_TXN_SEQ_NO {
-                    // 接下来需要对key进行解析
-                    // 读取到logrecord 后就可以构建索引了
-                    self.update_indexer(
-                        logrecord,
-                        LogRecordPos {
-                            file_id: id,
-                            offset: offset,
-                        },
-                    );
-                } else {
-                    // 如果是批量原子提交的情况，则需要进行缓存
-                    if current_seq_no == NO_TXN_SEQ_NO {
-                        current_seq_no = seq_no;
-                    }
-
-                    if current_seq_no != seq_no {
-                        // 老的原子提交失败了
-                        logrecords.clear()
-                    }
-                    // 当前事务已经到了最后一个了
-                    // 开始加载索引
-                    if logrecord.key.eq(TXN_FIN) {
-                        while logrecords.len() > 0 {
-                            let (log_record, logrecord_pos) = logrecords.pop().unwrap();
-                            self.update_indexer(log_record, logrecord_pos)
-                        }
-                        current_seq_no = NO_TXN_SEQ_NO;
-                    } else {
-                        logrecords.push((
-                            logrecord,
-                            LogRecordPos {
-                                file_id: id,
-                                offset: offset,
-                            },
-                        ));
-                    }
-                }
-                // 更新offset
-                offset += size as u64;
-            }
+        let active_file_read_guard = self.data_file.read();
+        let mut files: Vec<&DataFile> = read_guard.values().collect();
+        files.push(&*active_file_read_guard);
+        files.sort_by_key(|file| file.get_file_id());
+        let replayer = replay_data_files(&files, no_merged_file_id, |entry| {
+            self.apply_replay_entry(entry);
+            true
+        })?;
+        // 重启后批量提交的seq_no继续递增
+        self.seq_no.store(replayer.max_seq_no(), Ordering::SeqCst);
+        // 副本需要接着回放之后同步过来的日志
+        if let Some(replica) = &self.replica {
+            replica.lock().replayer = replayer;
         }
         Ok(())
     }
 
+    pub(crate) fn apply_replay_entry(&self, entry: ReplayEntry) {
+        for (logrecord, pos) in entry.records {
+            self.update_indexer(logrecord, pos);
+        }
+    }
+
     fn update_indexer(&self, logrecord: LogRecord, pos: LogRecordPos) {
-        if logrecord.log_type == LogRecordType::NORMAL {
-            self.indexer.put(logrecord.key.to_vec(), pos);
-        } else {
-            self.indexer.delete(logrecord.key.to_vec());
+        match logrecord.log_type {
+            LogRecordType::NORMAL | LogRecordType::BLOBINDEX => {
+                self.indexer.put(logrecord.key.to_vec(), pos);
+            }
+            LogRecordType::RANGEDELETED => {
+                let end = match logrecord.value.is_empty() {
+                    true => None,
+                    false => Some(logrecord.value.as_slice()),
+                };
+                self.indexer.delete_range(&logrecord.key, end);
+            }
+            // 时间标记不对应任何key
+            LogRecordType::TIMESTAMP => (),
+            _ => {
+                self.indexer.delete(logrecord.key.to_vec());
+            }
         }
     }
 
@@ -234,13 +275,28 @@ impl Engine {
         if key.is_empty() {
             return Err(Errors::KeyEmptyErr);
         }
+        self.check_writable()?;
+        let _guard = self.conditional_write_lock.read();
+        self.put_record(key, value)
+    }
+
+    // 追加一条put日志并更新索引,调用方需要持有conditional_write_lock
+    pub(crate) fn put_record(&self, key: Bytes, value: Bytes) -> Result<()> {
+        let (enc_value, log_type) = self.separate_value(&key, value.to_vec())?;
         let mut log_recored = LogRecord {
             key: WriteBatch::encode_key_seqno(key.clone(), NO_TXN_SEQ_NO),
-            value: value.to_vec(),
-            log_type: crate::data::log_record::LogRecordType::NORMAL,
+            value: enc_value,
+            log_type,
         };
+        let mut changes = Vec::new();
+        if !self.watchers.is_empty() {
+            changes.push(Change {
+                key: key.clone(),
+                value: Some(value),
+            });
+        }
         // 追加日志信息
-        let logrecord_pos = self.append_log(&mut log_recored)?;
+        let logrecord_pos = self.append_log_with_changes(&mut log_recored, changes)?;
         // 更新内存索引信息
         let ok = self.indexer.put(key.to_vec(), logrecord_pos);
         // 当然,btreeIndex一直返回的都是true，为了逻辑完整性
@@ -265,6 +321,23 @@ impl Engine {
     }
 
     pub(crate) fn get_value_by_pos(&self, log_record_pos: &LogRecordPos) -> Result<Bytes> {
+        let log_record = self.read_log_record_by_pos(log_record_pos)?;
+        match log_record.log_type {
+            LogRecordType::DELETED => Err(Errors::KeyNotFound),
+            // value在blob文件中
+            LogRecordType::BLOBINDEX => {
+                let blob_pos = BlobPos::decode(&log_record.value);
+                Ok(self.blob_files.read(&blob_pos)?.into())
+            }
+            _ => Ok(log_record.value.into()),
+        }
+    }
+
+    // 根据LogRecordPos读取main log中的记录,不会解析blob
+    pub(crate) fn read_log_record_by_pos(
+        &self,
+        log_record_pos: &LogRecordPos,
+    ) -> Result<LogRecord> {
         let active_file_read_guard = self.data_file.read();
         let old_files_read_guard = self.old_files.read();
         // 3. 根据LogRecordPos去查询
@@ -278,12 +351,7 @@ impl Engine {
                 data_file.unwrap().read_log_record(log_record_pos.offset)?
             }
         };
-        // println!("logrecord_pos: id -> {:?},offset -> {:?}",log_record_pos.file_id,log_record_pos.offset);
-        // println!("get: {:?},{:?},{:?}",  Bytes::from(readlog_record.logrecord.key.clone()),Bytes::from(readlog_record.logrecord.value.clone()),readlog_record.logrecord.log_type);
-        if readlog_record.logrecord.log_type == LogRecordType::DELETED {
-            return Err(Errors::KeyNotFound);
-        }
-        return Ok(readlog_record.logrecord.value.into());
+        Ok(readlog_record.logrecord)
     }
 
     pub fn delete(&self, key: Bytes) -> Result<()> {
@@ -291,6 +359,13 @@ impl Engine {
         if key.is_empty() {
             return Err(Errors::KeyEmptyErr);
         }
+        self.check_writable()?;
+        let _guard = self.conditional_write_lock.read();
+        self.delete_record(key)
+    }
+
+    // 追加一条删除日志并更新索引,调用方需要持有conditional_write_lock
+    pub(crate) fn delete_record(&self, key: Bytes) -> Result<()> {
         // 2.从内存索引获取
         let logrecord_pos = self.indexer.get(key.to_vec());
         if logrecord_pos.is_none() {
@@ -301,7 +376,14 @@ impl Engine {
             value: Default::default(),
             log_type: LogRecordType::DELETED,
         };
-        match self.append_log(&mut log_record) {
+        let mut changes = Vec::new();
+        if !self.watchers.is_empty() {
+            changes.push(Change {
+                key: key.clone(),
+                value: None,
+            });
+        }
+        match self.append_log_with_changes(&mut log_record, changes) {
             Ok(_) => {
                 self.indexer.delete(key.to_vec());
                 return Ok(());
@@ -310,38 +392,196 @@ impl Engine {
         }
     }
 
-    pub fn append_log(&self, log_record: &mut LogRecord) -> Result<LogRecordPos> {
-        // 1.编码logRecord
-        let enc_log_record = log_record.encode();
+    // 持久化活跃文件并把它变成old file,然后开启新的活跃文件
+    pub(crate) fn rotate_active_file(
+        &self,
+        active_file_write_guard: &mut RwLockWriteGuard<DataFile>,
+    ) -> Result<()> {
+        self.sync_state.sync(active_file_write_guard)?;
+        let old_file_id = active_file_write_guard.get_file_id();
+        // 更新活跃文件
+        let new_data_file = DataFile::new(self.options.dir_path.clone(), old_file_id + 1)?;
+        let old_file = std::mem::replace(&mut **active_file_write_guard, new_data_file);
+        self.old_files.write().insert(old_file_id, old_file);
+        Ok(())
+    }
+
+    // 将编码后的日志写入活跃文件,超过阈值时切换新的活跃文件
+    // 调用方需要持有活跃文件的写锁,是否持久化由调用方决定
+    pub(crate) fn write_to_active_file(
+        &self,
+        active_file_write_guard: &mut RwLockWriteGuard<DataFile>,
+        enc_log_record: &[u8],
+    ) -> Result<LogRecordPos> {
         let record_len = enc_log_record.len() as u64;
-        // 2.获取活跃文件写锁锁
-        let mut active_file_write_guard = self.data_file.write();
-        // 3.超过阈值就持久化,并开启新的文件
+        // 1.超过阈值就持久化,并开启新的文件
         if active_file_write_guard.get_wtite_offset() + record_len
             > self.options.file_size_threshlod
         {
-            active_file_write_guard.sync()?;
-            let old_file_id = active_file_write_guard.get_file_id();
-            let mut old_files_write_guard = self.old_files.write();
-            old_files_write_guard.insert(
-                old_file_id,
-                DataFile::new(self.options.dir_path.clone(), old_file_id)?,
-            );
-            // 更新活跃文件
-            let new_data_file = DataFile::new(self.options.dir_path.clone(), old_file_id + 1);
-            *active_file_write_guard = new_data_file.unwrap();
-        }
-        // 4.append log
-        active_file_write_guard.write(&enc_log_record)?;
-        // 5.根据配置看是否每次都要进行持久化
-        if self.options.sync {
-            active_file_write_guard.sync()?;
+            self.rotate_active_file(active_file_write_guard)?;
         }
+        // 2.append log
+        active_file_write_guard.write(enc_log_record)?;
 
         // 写完数据后，构造内存索引信息并返回
         Ok(LogRecordPos {
             file_id: active_file_write_guard.get_file_id(),
             offset: active_file_write_guard.get_wtite_offset() - record_len,
+            value_size: LogRecord::decode_value_size(enc_log_record) as u32,
         })
     }
 }
+
+// 解析日志中的key,返回真正的key和seq_no
+pub(crate) fn parse_log_key(key: Vec<u8>) -> (Vec<u8>, usize) {
+    let mut buf = BytesMut::new();
+    buf.extend_from_slice(&key);
+    let seq_no = decode_length_delimiter(&mut buf).unwrap();
+    (buf.to_vec(), seq_no)
+}
+
+// 回放时一次提交的日志,单条写入只有一条记录,批量提交在读到TXN_FIN之后一起回放
+pub(crate) struct ReplayEntry {
+    pub(crate) seq_no: usize,
+    // key已经去掉了seq_no
+    pub(crate) records: Vec<(LogRecord, LogRecordPos)>,
+    // TXN_FIN记录的value,单条写入为空
+    pub(crate) txn_fin_value: Vec<u8>,
+    // 这次提交在日志中的起止位置
+    pub(crate) start: LogRecordPos,
+    pub(crate) end: LogRecordPos,
+}
+
+// 回放日志的状态,没有读到TXN_FIN的批量提交会暂存下来,可以接着回放后面追加的日志
+pub(crate) struct LogReplayer {
+    // 暂存批量提交的log_record
+    logrecords: Vec<(LogRecord, LogRecordPos)>,
+    current_seq_no: usize,
+    batch_start: LogRecordPos,
+    max_seq_no: usize,
+}
+
+impl LogReplayer {
+    pub(crate) fn new() -> Self {
+        LogReplayer {
+            logrecords: Vec::new(),
+            current_seq_no: NO_TXN_SEQ_NO,
+            batch_start: LogRecordPos {
+                file_id: 0,
+                offset: 0,
+                value_size: 0,
+            },
+            max_seq_no: NO_TXN_SEQ_NO,
+        }
+    }
+
+    // 读到的最大的批量提交seq_no
+    pub(crate) fn max_seq_no(&self) -> usize {
+        self.max_seq_no
+    }
+
+    // 回放一个文件中从offset开始的日志,f返回false时停止回放并返回false
+    pub(crate) fn replay_file<F>(
+        &mut self,
+        file: &DataFile,
+        mut offset: u64,
+        f: &mut F,
+    ) -> Result<bool>
+    where
+        F: FnMut(ReplayEntry) -> bool,
+    {
+        let id = file.get_file_id();
+        loop {
+            // 只读打开时文件末尾不完整的日志不会被读取
+            if offset >= file.get_wtite_offset() {
+                return Ok(true);
+            }
+            let logrecord_res: Result<ReadLogRecord> = file.read_log_record(offset);
+            let (mut logrecord, size) = match logrecord_res {
+                Ok(res) => (res.logrecord, res.size),
+                Err(e) => {
+                    if e == Errors::DataFileReadEOF {
+                        return Ok(true);
+                    }
+                    return Err(e);
+                }
+            };
+            // 接下来需要对key进行解析
+            let (new_key, seq_no) = parse_log_key(logrecord.key);
+            logrecord.key = new_key;
+            let pos = LogRecordPos {
+                file_id: id,
+                offset,
+                value_size: logrecord.value_size() as u32,
+            };
+            // 更新offset
+            offset += size as u64;
+            let end = LogRecordPos {
+                file_id: id,
+                offset,
+                value_size: 0,
+            };
+            if seq_no == NO_TXN_SEQ_NO {
+                // 读取到logrecord 后就可以构建索引了
+                let entry = ReplayEntry {
+                    seq_no,
+                    records: vec![(logrecord, pos)],
+                    txn_fin_value: Vec::new(),
+                    start: pos,
+                    end,
+                };
+                if !f(entry) {
+                    return Ok(false);
+                }
+                continue;
+            }
+            self.max_seq_no = self.max_seq_no.max(seq_no);
+            // 如果是批量原子提交的情况，则需要进行缓存
+            if self.current_seq_no != seq_no {
+                // 老的原子提交失败了
+                self.logrecords.clear();
+                self.current_seq_no = seq_no;
+                self.batch_start = pos;
+            }
+            // 当前事务已经到了最后一个了,开始回放
+            if logrecord.log_type == LogRecordType::TXNCOMMITTED && logrecord.key.eq(TXN_FIN) {
+                let entry = ReplayEntry {
+                    seq_no,
+                    records: std::mem::take(&mut self.logrecords),
+                    txn_fin_value: logrecord.value,
+                    start: self.batch_start,
+                    end,
+                };
+                self.current_seq_no = NO_TXN_SEQ_NO;
+                if !f(entry) {
+                    return Ok(false);
+                }
+            } else {
+                self.logrecords.push((logrecord, pos));
+            }
+        }
+    }
+}
+
+// 按照文件顺序回放已经提交的日志,被merge过的文件跳过,没有TXN_FIN的批量提交会被丢弃
+// f返回false时停止回放,返回回放的状态
+pub(crate) fn replay_data_files<F>(
+    files: &[&DataFile],
+    no_merged_file_id: u32,
+    mut f: F,
+) -> Result<LogReplayer>
+where
+    F: FnMut(ReplayEntry) -> bool,
+{
+    let mut replayer = LogReplayer::new();
+    for file in files {
+        // 对于已经被merge过的文件不要再load index了
+        if file.get_file_id() < no_merged_file_id {
+            continue;
+        }
+        if !replayer.replay_file(file, 0, &mut f)? {
+            break;
+        }
+    }
+    Ok(replayer)
+}
@@ -2,12 +2,13 @@ use bytes::Bytes;
 use std::{
     path::PathBuf,
     sync::{Arc, Barrier},
+    time::Duration,
 };
 
 use crate::{
     db::Engine,
     errors::Errors,
-    options::Options,
+    options::{Options, SyncPolicy},
     util::rand_kv::{get_test_key, get_test_value},
 };
 
@@ -246,3 +247,208 @@ fn test_concurrent_put_get() {
     }
     std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
 }
+
+#[test]
+fn test_group_commit() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-group-commit");
+    opts.file_size_threshlod = 64 * 1024;
+    opts.sync_policy = SyncPolicy::Always;
+    let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+    let mut handles = Vec::new();
+    for thread_id in 0..16 {
+        let engine = engine.clone();
+        let handle = std::thread::spawn(move || {
+            for i in 0..200 {
+                let key = Bytes::from(format!("key-{}-{}", thread_id, i));
+                engine.put(key.clone(), get_test_value(i)).unwrap();
+                if i % 10 == 0 {
+                    engine.delete(key).unwrap();
+                }
+            }
+        });
+        handles.push(handle);
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    for thread_id in 0..16 {
+        for i in 0..200 {
+            let key = Bytes::from(format!("key-{}-{}", thread_id, i));
+            let res = engine.get(key);
+            if i % 10 == 0 {
+                assert_eq!(Errors::KeyNotFound, res.err().unwrap());
+            } else {
+                assert_eq!(get_test_value(i), res.unwrap());
+            }
+        }
+    }
+    // 重启后数据都还在
+    engine.close().unwrap();
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    for thread_id in 0..16 {
+        for i in 1..200 {
+            if i % 10 == 0 {
+                continue;
+            }
+            let key = Bytes::from(format!("key-{}-{}", thread_id, i));
+            assert_eq!(get_test_value(i), engine2.get(key).unwrap());
+        }
+    }
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_sync_policy() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-sync-policy");
+    opts.file_size_threshlod = 64 * 1024 * 1024;
+
+    // 配置不合法
+    opts.sync_policy = SyncPolicy::EveryNBytes(0);
+    assert_eq!(
+        Errors::InvalidSyncPolicyOption,
+        Engine::open(opts.clone()).err().unwrap()
+    );
+
+    // 每次写入都持久化
+    opts.sync_policy = SyncPolicy::Always;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    engine.put(get_test_key(1), get_test_value(1)).unwrap();
+    let active_offset = engine.data_file.read().get_wtite_offset();
+    assert_eq!(active_offset, engine.durable_pos().get_offset());
+    drop(engine);
+
+    // 累计写入一定字节后持久化
+    opts.sync_policy = SyncPolicy::EveryNBytes(1024);
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let start = engine.durable_pos().get_offset();
+    engine.put(get_test_key(2), get_test_value(2)).unwrap();
+    assert_eq!(start, engine.durable_pos().get_offset());
+    for i in 0..20 {
+        engine.put(get_test_key(i), get_test_value(i)).unwrap();
+    }
+    assert!(engine.durable_pos().get_offset() > start);
+    drop(engine);
+
+    // 后台线程定时持久化
+    opts.sync_policy = SyncPolicy::Interval(Duration::from_millis(10));
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    engine.put(get_test_key(3), get_test_value(3)).unwrap();
+    let active_offset = engine.data_file.read().get_wtite_offset();
+    let mut synced = false;
+    for _ in 0..100 {
+        if engine.durable_pos().get_offset() == active_offset {
+            synced = true;
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(synced);
+    drop(engine);
+
+    // 从不主动持久化,只有调用sync才会推进
+    opts.sync_policy = SyncPolicy::Never;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let start = engine.durable_pos().get_offset();
+    engine.put(get_test_key(4), get_test_value(4)).unwrap();
+    assert_eq!(start, engine.durable_pos().get_offset());
+    engine.sync().unwrap();
+    assert!(engine.durable_pos().get_offset() > start);
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_read_only() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-read-only");
+    opts.file_size_threshlod = 32 * 1024;
+
+    // 目录不存在时不会创建
+    let mut read_only_opts = opts.clone();
+    read_only_opts.read_only = true;
+    assert!(Engine::open(read_only_opts.clone()).is_err());
+    assert!(!opts.dir_path.exists());
+
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..1000 {
+        engine.put(get_test_key(i), get_test_value(i)).unwrap();
+    }
+    engine.delete(get_test_key(0)).unwrap();
+    engine.sync().unwrap();
+    let list_dir = || {
+        let mut files: Vec<(String, u64)> = std::fs::read_dir(&opts.dir_path)
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let name = entry.file_name().into_string().unwrap();
+                (name, entry.metadata().unwrap().len())
+            })
+            .collect();
+        files.sort();
+        files
+    };
+    let files = list_dir();
+
+    // 可以和写入的engine以及其他只读的engine同时打开
+    let reader1 = Engine::open(read_only_opts.clone()).expect("failed to open engine");
+    let reader2 = Engine::open(read_only_opts.clone()).expect("failed to open engine");
+    for reader in [&reader1, &reader2] {
+        assert!(reader.get(get_test_key(0)).is_err());
+        for i in 1..1000 {
+            assert_eq!(reader.get(get_test_key(i)).unwrap(), get_test_value(i));
+        }
+        assert_eq!(
+            reader.put(get_test_key(1), get_test_value(1)).err(),
+            Some(Errors::ReadOnly)
+        );
+        assert_eq!(reader.delete(get_test_key(1)).err(), Some(Errors::ReadOnly));
+        assert_eq!(
+            reader.delete_prefix(Bytes::from("bitcask")).err(),
+            Some(Errors::ReadOnly)
+        );
+        let write_batch = reader.new_write_batch(Default::default()).unwrap();
+        write_batch.put(get_test_key(1), get_test_value(1)).unwrap();
+        assert_eq!(write_batch.commit().err(), Some(Errors::ReadOnly));
+        assert_eq!(reader.merge().err(), Some(Errors::ReadOnly));
+        assert!(reader
+            .register_cdc_reader("reader", crate::cdc::ResumeToken::SeqNo(0))
+            .is_err());
+        assert_eq!(reader.get(get_test_key(1)).unwrap(), get_test_value(1));
+    }
+    drop(reader1);
+    drop(reader2);
+    // 数据目录没有任何变化
+    assert_eq!(files, list_dir());
+
+    // 最新的文件末尾有写了一半或者损坏的日志时,只读打开把它当作文件结尾
+    let (active_file_name, _) = files
+        .iter()
+        .filter(|(name, _)| name.ends_with(".data"))
+        .max()
+        .unwrap();
+    let active_file_path = opts.dir_path.join(active_file_name);
+    let data = std::fs::read(&active_file_path).unwrap();
+    engine
+        .put(get_test_key(1000), get_test_value(1000))
+        .unwrap();
+    engine.sync().unwrap();
+    drop(engine);
+    let full = std::fs::read(&active_file_path).unwrap();
+    assert!(full.len() > data.len());
+    let mut corrupted = full[data.len()..].to_vec();
+    *corrupted.last_mut().unwrap() ^= 0xff;
+    for tail in [
+        full[data.len()..full.len() - 3].to_vec(),
+        corrupted,
+        vec![0xff; 16],
+    ] {
+        let mut content = data.clone();
+        content.extend_from_slice(&tail);
+        std::fs::write(&active_file_path, content).unwrap();
+        let reader = Engine::open(read_only_opts.clone()).expect("failed to open engine");
+        assert_eq!(reader.get(get_test_key(999)).unwrap(), get_test_value(999));
+        assert!(reader.get(get_test_key(1000)).is_err());
+    }
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
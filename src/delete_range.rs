@@ -0,0 +1,142 @@
+use bytes::Bytes;
+
+use crate::{
+    data::log_record::{LogRecord, LogRecordType},
+    db::{Engine, NO_TXN_SEQ_NO},
+    errors::{Errors, Result},
+    index::IndexIteratorOptions,
+    watch::Change,
+    write_batch::WriteBatch,
+};
+
+impl Engine {
+    // 原子地删除[start, end)范围内的所有key
+    // 只会写一条范围删除的日志,索引中的key批量删除
+    pub fn delete_range(&self, start: Bytes, end: Bytes) -> Result<()> {
+        if start >= end {
+            return Ok(());
+        }
+        self.append_range_tombstone(start, Some(end))
+    }
+
+    // 原子地删除所有以prefix开头的key
+    pub fn delete_prefix(&self, prefix: Bytes) -> Result<()> {
+        if prefix.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        let end = prefix_end(&prefix);
+        self.append_range_tombstone(prefix, end)
+    }
+
+    fn append_range_tombstone(&self, start: Bytes, end: Option<Bytes>) -> Result<()> {
+        self.check_writable()?;
+        // 拿写锁,防止并发写入的key在追加日志和删除索引之间被误删
+        let _guard = self.conditional_write_lock.write();
+        let mut log_record = LogRecord {
+            key: WriteBatch::encode_key_seqno(start.clone(), NO_TXN_SEQ_NO),
+            value: end.clone().map_or(Vec::new(), |end| end.to_vec()),
+            log_type: LogRecordType::RANGEDELETED,
+        };
+        // 有订阅者时,先找出被删除的key,作为一个事件通知
+        // 持有写锁,追加日志之前找到的key和之后删除的key是一样的
+        let mut changes = Vec::new();
+        if !self.watchers.is_empty() {
+            let mut index_iter = self.indexer.iterator(IndexIteratorOptions::default());
+            index_iter.seek(&start.to_vec());
+            while let Some((key, _)) = index_iter.next() {
+                if end
+                    .as_ref()
+                    .is_some_and(|end| key.as_slice() >= end.as_ref())
+                {
+                    break;
+                }
+                changes.push(Change {
+                    key: Bytes::from(key.clone()),
+                    value: None,
+                });
+            }
+        }
+        self.append_log_with_changes(&mut log_record, changes)?;
+        self.indexer.delete_range(&start, end.as_deref());
+        Ok(())
+    }
+}
+
+// 所有以prefix开头的key的上界(不包含),prefix全是0xff时没有上界
+pub(crate) fn prefix_end(prefix: &[u8]) -> Option<Bytes> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(Bytes::from(end));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test_delete_range {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use super::prefix_end;
+    use crate::{db::Engine, errors::Errors, options::Options};
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(b"abc"), Some(Bytes::from("abd")));
+        assert_eq!(prefix_end(b"a\xff"), Some(Bytes::from("b")));
+        assert_eq!(prefix_end(b"\xff\xff"), None);
+    }
+
+    #[test]
+    fn test_delete_range_and_prefix() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-delete-range");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for tenant in ["t1", "t2", "t3"] {
+            for i in 0..100 {
+                let key = Bytes::from(format!("{}/key{:03}", tenant, i));
+                engine.put(key, Bytes::from("value")).unwrap();
+            }
+        }
+        engine.put(Bytes::from("t2"), Bytes::from("value")).unwrap();
+
+        engine.delete_prefix(Bytes::from("t2/")).unwrap();
+        engine
+            .delete_range(Bytes::from("t3/key010"), Bytes::from("t3/key050"))
+            .unwrap();
+        // 起始key不小于结束key时什么都不做
+        engine
+            .delete_range(Bytes::from("t1/key050"), Bytes::from("t1/key000"))
+            .unwrap();
+        assert_eq!(
+            engine.delete_prefix(Bytes::new()).err().unwrap(),
+            Errors::KeyEmptyErr
+        );
+        // 删除之后再写入
+        engine
+            .put(Bytes::from("t2/key001"), Bytes::from("new value"))
+            .unwrap();
+
+        let check = |engine: &Engine| {
+            assert_eq!(engine.list_keys().unwrap().len(), 100 + 2 + 60);
+            assert!(engine.get(Bytes::from("t1/key050")).is_ok());
+            assert!(engine.get(Bytes::from("t2")).is_ok());
+            assert_eq!(engine.get(Bytes::from("t2/key001")).unwrap(), "new value");
+            assert!(engine.get(Bytes::from("t2/key002")).is_err());
+            assert!(engine.get(Bytes::from("t3/key009")).is_ok());
+            assert!(engine.get(Bytes::from("t3/key010")).is_err());
+            assert!(engine.get(Bytes::from("t3/key049")).is_err());
+            assert!(engine.get(Bytes::from("t3/key050")).is_ok());
+        };
+        check(&engine);
+        // 重启后范围删除依然生效
+        engine.close().unwrap();
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        check(&engine2);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -2,7 +2,7 @@ use thiserror::Error;
 
 #[allow(dead_code)]
 // 这个文件用来自定义我们自己的error
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug, PartialEq, Clone)]
 pub enum Errors {
     // 利用thiserror来实现display
     #[error("Fail to read data from file")]
@@ -27,6 +27,8 @@ pub enum Errors {
     DirPathEmptyError,
     #[error("FileSize option must greater than 0")]
     InvalidDataFileSizeOption,
+    #[error("SyncPolicy option is invalid, n bytes and interval must greater than 0")]
+    InvalidSyncPolicyOption,
     #[error("Create DirPath Failed")]
     DirPathCreateFailed,
     #[error("Read DirPath Error")]
@@ -39,8 +41,62 @@ pub enum Errors {
     CheckSumFailed,
     #[error("Over MaxBatchRows")]
     ExceedBatchMaxRows,
+    #[error("Over MaxBatchBytes")]
+    ExceedBatchMaxBytes,
     #[error("Merge is doing now")]
     MergeInProcess,
+    #[error("No savepoint in WriteBatch")]
+    NoSavepoint,
+    #[error("MergeOperator is not set in options")]
+    MergeOperatorNotSet,
+    #[error("Value is not an integer")]
+    ValueNotInteger,
+    #[error("Integer overflow")]
+    IntegerOverflow,
+    #[error("Blob value threshold must be greater than 0")]
+    InvalidBlobValueThreshold,
+    #[error("Blob file not found")]
+    BlobFileNotFound,
+    #[error("Cdc reader not found")]
+    CdcReaderNotFound,
+    #[error("Sequence number not found in the log")]
+    CdcSeqNoNotFound,
+    #[error("Cdc position has been merged")]
+    InvalidCdcPosition,
+    #[error("DirPath is not empty")]
+    DirPathNotEmpty,
+    #[error("Restore point not found in the log")]
+    RestorePointNotFound,
+    #[error("Data files do not match the backup")]
+    RestoreLogMismatch,
+    #[error("Seq no is ambiguous after merge, restore by timestamp instead")]
+    RestoreSeqNoAfterMerge,
+    #[error("Engine is read-only")]
+    ReadOnly,
+    #[error("No data file in DirPath")]
+    DataFileNotFound,
+    #[error("Engine is not a replica")]
+    NotReplica,
+    #[error("Replication position does not match the leader")]
+    ReplicationPositionMismatch,
+    #[error("Replication transport failed")]
+    ReplicationTransportFailed,
+    #[error("Failed to decode replication message")]
+    ReplicationDecodeFailed,
+    #[error("Replication message is too large")]
+    ReplicationFrameTooLarge,
+    #[error("Failed to bind server address")]
+    ServerBindFailed,
+    #[error("Invalid scan cursor")]
+    InvalidScanCursor,
+    #[error("Invalid redis metadata")]
+    InvalidRedisMetadata,
+    #[error("Invalid sequence record")]
+    InvalidSequenceRecord,
+    #[error("Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("Invalid memcached item")]
+    InvalidMemcachedItem,
 }
 
 pub type Result<T> = std::result::Result<T, Errors>;
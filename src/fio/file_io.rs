@@ -35,6 +35,21 @@ impl FileIO {
     }
 }
 
+impl FileIO {
+    // 只读打开已经存在的文件,文件不存在时不会创建
+    pub fn open_read_only(file_name: &PathBuf) -> Result<Self> {
+        match OpenOptions::new().read(true).open(file_name) {
+            Ok(file) => Ok(FileIO {
+                file: Arc::new(RwLock::new(file)),
+            }),
+            Err(err) => {
+                error!("fail to open a file {}", err);
+                Err(Errors::FailReadFromFile)
+            }
+        }
+    }
+}
+
 impl IOManager for FileIO {
     fn read(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
         // 获取读锁
@@ -20,3 +20,8 @@ pub trait IOManager: Sync + Send {
 pub fn new_io_manager(file_name: &PathBuf) -> Result<impl IOManager> {
     FileIO::new(file_name)
 }
+
+// 只读的IOManager,写入会失败
+pub fn new_read_only_io_manager(file_name: &PathBuf) -> Result<impl IOManager> {
+    FileIO::open_read_only(file_name)
+}
@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use log::error;
+use parking_lot::{Condvar, Mutex, RwLock};
+
+use crate::data::{data_file::DataFile, log_record::LogRecordPos};
+use crate::db::Engine;
+use crate::errors::Result;
+use crate::watch::Watchers;
+
+// 记录活跃文件的持久化进度
+pub(crate) struct SyncState {
+    // 最后一次持久化的位置,在它之前的数据都已经落盘
+    durable_pos: Mutex<LogRecordPos>,
+    // 上次持久化之后写入的字节数
+    unsynced_bytes: AtomicU64,
+    // 持久化之后通知watch的订阅者
+    watchers: Arc<Watchers>,
+}
+
+impl SyncState {
+    pub(crate) fn new(durable_pos: LogRecordPos, watchers: Arc<Watchers>) -> Self {
+        SyncState {
+            durable_pos: Mutex::new(durable_pos),
+            unsynced_bytes: AtomicU64::new(0),
+            watchers,
+        }
+    }
+
+    // 持久化活跃文件并记录进度,调用方需要持有活跃文件的锁
+    pub(crate) fn sync(&self, data_file: &DataFile) -> Result<()> {
+        data_file.sync()?;
+        let durable_pos = LogRecordPos {
+            file_id: data_file.get_file_id(),
+            offset: data_file.get_wtite_offset(),
+            value_size: 0,
+        };
+        *self.durable_pos.lock() = durable_pos;
+        self.unsynced_bytes.store(0, Ordering::SeqCst);
+        self.watchers.on_durable(durable_pos);
+        Ok(())
+    }
+
+    // 累加没有持久化的字节数,返回累加后的值
+    pub(crate) fn add_unsynced_bytes(&self, size: u64) -> u64 {
+        self.unsynced_bytes.fetch_add(size, Ordering::SeqCst) + size
+    }
+
+    fn unsynced_bytes(&self) -> u64 {
+        self.unsynced_bytes.load(Ordering::SeqCst)
+    }
+}
+
+// SyncPolicy::Interval 对应的后台持久化线程,Drop的时候停止
+pub(crate) struct Flusher {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Flusher {
+    pub(crate) fn start(
+        interval: Duration,
+        data_file: Arc<RwLock<DataFile>>,
+        sync_state: Arc<SyncState>,
+    ) -> Self {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_stop = stop.clone();
+        let handle = std::thread::spawn(move || loop {
+            {
+                let (lock, cond) = &*thread_stop;
+                let mut stopped = lock.lock();
+                if !*stopped {
+                    cond.wait_for(&mut stopped, interval);
+                }
+                if *stopped {
+                    return;
+                }
+            }
+            // 拿读锁持久化,期间不会有新的写入
+            let read_guard = data_file.read();
+            if sync_state.unsynced_bytes() == 0 {
+                continue;
+            }
+            if let Err(e) = sync_state.sync(&read_guard) {
+                error!("background flusher failed to sync data file: {}", e);
+            }
+        });
+        Flusher {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Flusher {
+    fn drop(&mut self) {
+        let (lock, cond) = &*self.stop;
+        *lock.lock() = true;
+        cond.notify_all();
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+impl Engine {
+    // 获取最后一次持久化的位置,宕机后这个位置之前的数据不会丢失
+    pub fn durable_pos(&self) -> LogRecordPos {
+        *self.sync_state.durable_pos.lock()
+    }
+}
@@ -0,0 +1,108 @@
+use bytes::Bytes;
+
+use crate::{
+    data::log_record::{BlobPos, LogRecordType},
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+impl Engine {
+    // 读取value中从offset开始的len个字节,超出value长度的部分会被截掉
+    // 只读取header和需要的那一段数据,不会做crc校验,需要校验的话使用get
+    pub fn get_range(&self, key: Bytes, offset: u64, len: u64) -> Result<Bytes> {
+        if key.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        let log_record_pos = match self.indexer.get(key.to_vec()) {
+            Some(pos) => pos,
+            None => return Err(Errors::KeyNotFound),
+        };
+        let active_file_read_guard = self.data_file.read();
+        let old_files_read_guard = self.old_files.read();
+        let data_file = match active_file_read_guard.get_file_id() == log_record_pos.file_id {
+            true => &*active_file_read_guard,
+            false => match old_files_read_guard.get(&log_record_pos.file_id) {
+                Some(data_file) => data_file,
+                None => return Err(Errors::KeyNotFoundInDataFile),
+            },
+        };
+        let (log_type, key_size, value_size, header_size) =
+            data_file.read_log_record_header(log_record_pos.offset)?;
+        if log_type == LogRecordType::DELETED {
+            return Err(Errors::KeyNotFound);
+        }
+        let value_offset = log_record_pos.offset + (header_size + key_size) as u64;
+        // main log中只有指向blob的指针,value的长度以指针中记录的为准
+        let blob_pos = match log_type {
+            LogRecordType::BLOBINDEX => Some(BlobPos::decode(
+                &data_file.read_bytes(value_offset, value_size)?,
+            )),
+            _ => None,
+        };
+        let value_size = match &blob_pos {
+            Some(blob_pos) => blob_pos.value_size as u64,
+            None => value_size as u64,
+        };
+        let start = offset.min(value_size);
+        let end = offset.saturating_add(len).min(value_size);
+        if start == end {
+            return Ok(Bytes::new());
+        }
+        let buf = match &blob_pos {
+            Some(blob_pos) => {
+                self.blob_files
+                    .read_range(blob_pos, start, (end - start) as usize)?
+            }
+            None => data_file.read_bytes(value_offset + start, (end - start) as usize)?,
+        };
+        if buf.len() as u64 != end - start {
+            return Err(Errors::DataFileCorrupted);
+        }
+        Ok(Bytes::from(buf))
+    }
+}
+
+#[cfg(test)]
+mod test_get_range {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{db::Engine, errors::Errors, options::Options};
+
+    #[test]
+    fn test_get_range() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-get-range");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let value: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        engine
+            .put(Bytes::from("blob"), Bytes::from(value.clone()))
+            .unwrap();
+        engine
+            .put(Bytes::from("small"), Bytes::from("0123456789"))
+            .unwrap();
+
+        let res = engine.get_range(Bytes::from("blob"), 1000, 4096).unwrap();
+        assert_eq!(res, Bytes::from(value[1000..5096].to_vec()));
+        assert_eq!(engine.get_range(Bytes::from("small"), 2, 3).unwrap(), "234");
+        // 超出value长度的部分被截掉
+        assert_eq!(
+            engine.get_range(Bytes::from("small"), 8, 100).unwrap(),
+            "89"
+        );
+        assert!(engine
+            .get_range(Bytes::from("small"), 100, 10)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            engine
+                .get_range(Bytes::from("not existed key"), 0, 10)
+                .err()
+                .unwrap(),
+            Errors::KeyNotFound
+        );
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
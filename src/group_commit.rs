@@ -0,0 +1,201 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::data::log_record::{LogRecord, LogRecordPos, LogRecordType};
+use crate::db::{Engine, NO_TXN_SEQ_NO};
+use crate::errors::{Errors, Result};
+use crate::options::SyncPolicy;
+use crate::watch::Change;
+use crate::write_batch::{commit_timestamp, WriteBatch};
+
+// 时间标记的key,不能为空,空key表示文件结尾
+pub(crate) const TIMESTAMP_MARKER: &[u8] = "TIMESTAMP".as_bytes();
+
+// 一次写请求,包含需要连续追加的若干条编码后的日志
+struct WriteRequest {
+    records: Vec<Vec<u8>>,
+    sync: bool,
+    // 这次写入产生的变化,由leader按照日志的顺序交给watch
+    changes: Mutex<Vec<Change>>,
+    // 由leader写完之后填入
+    result: Mutex<Option<Result<Vec<LogRecordPos>>>>,
+}
+
+struct QueueState {
+    // 等待被leader写入的请求
+    pending: Vec<Arc<WriteRequest>>,
+    // 当前是否有leader在写
+    leader_active: bool,
+}
+
+// 组提交队列:并发的写请求排队,由一个leader一起追加并只做一次sync
+pub(crate) struct GroupCommit {
+    state: Mutex<QueueState>,
+    cond: Condvar,
+    // 最近一次写入的时间标记的时间,单调递增
+    last_timestamp: AtomicU64,
+}
+
+impl GroupCommit {
+    pub(crate) fn new() -> Self {
+        GroupCommit {
+            state: Mutex::new(QueueState {
+                pending: Vec::new(),
+                leader_active: false,
+            }),
+            cond: Condvar::new(),
+            last_timestamp: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Engine {
+    // 追加一条日志,会和并发的其他写请求合并提交
+    pub fn append_log(&self, log_record: &mut LogRecord) -> Result<LogRecordPos> {
+        self.append_log_with_changes(log_record, Vec::new())
+    }
+
+    // 追加一条日志,写入成功后把changes通知给watch的订阅者
+    pub(crate) fn append_log_with_changes(
+        &self,
+        log_record: &mut LogRecord,
+        changes: Vec<Change>,
+    ) -> Result<LogRecordPos> {
+        let sync = self.options.sync_policy == SyncPolicy::Always;
+        let mut pos = self.append_logs(vec![log_record.encode()], sync, changes)?;
+        Ok(pos.pop().unwrap())
+    }
+
+    // 连续追加多条已经编码的日志,返回每条日志的位置
+    // 同一个请求中的日志在文件中一定是连续的
+    // changes在写入成功之后由leader按照日志的顺序交给watch,通知的顺序和提交的顺序一致
+    pub(crate) fn append_logs(
+        &self,
+        records: Vec<Vec<u8>>,
+        sync: bool,
+        changes: Vec<Change>,
+    ) -> Result<Vec<LogRecordPos>> {
+        let request = Arc::new(WriteRequest {
+            records,
+            sync,
+            changes: Mutex::new(changes),
+            result: Mutex::new(None),
+        });
+        let mut state = self.group_commit.state.lock();
+        state.pending.push(request.clone());
+        // 等待别的leader帮我们写完,或者自己成为leader
+        loop {
+            let res = request.result.lock().take();
+            if let Some(res) = res {
+                return res;
+            }
+            if !state.leader_active {
+                break;
+            }
+            self.group_commit.cond.wait(&mut state);
+        }
+        state.leader_active = true;
+        let group = std::mem::take(&mut state.pending);
+        drop(state);
+
+        self.write_group(&group);
+
+        let mut state = self.group_commit.state.lock();
+        state.leader_active = false;
+        self.group_commit.cond.notify_all();
+        drop(state);
+        let res = request.result.lock().take().unwrap();
+        res
+    }
+
+    // 由leader调用,把一组请求写入活跃文件,需要的话只sync一次
+    fn write_group(&self, group: &[Arc<WriteRequest>]) {
+        let mut active_file_write_guard = self.data_file.write();
+        let mut results = Vec::with_capacity(group.len());
+        let mut need_sync = false;
+        let mut written = 0;
+        let mut write_err: Option<Errors> = None;
+        // 距离上一个时间标记超过配置的间隔之后,先写入一条时间标记,按照时间恢复时从标记处截断
+        // 标记只在一组请求的开头写入,不会把批量提交分开
+        let now = commit_timestamp();
+        let last_timestamp = self.group_commit.last_timestamp.load(Ordering::SeqCst);
+        let need_marker = self
+            .options
+            .timestamp_marker_interval
+            .is_some_and(|interval| {
+                now > last_timestamp && now - last_timestamp >= interval.as_millis() as u64
+            });
+        if need_marker {
+            let marker = LogRecord {
+                key: WriteBatch::encode_key_seqno(Bytes::from(TIMESTAMP_MARKER), NO_TXN_SEQ_NO),
+                value: now.to_string().into_bytes(),
+                log_type: LogRecordType::TIMESTAMP,
+            }
+            .encode();
+            match self.write_to_active_file(&mut active_file_write_guard, &marker) {
+                Ok(_) => {
+                    written += marker.len() as u64;
+                    self.group_commit
+                        .last_timestamp
+                        .store(now, Ordering::SeqCst);
+                }
+                Err(e) => write_err = Some(e),
+            }
+        }
+        for request in group.iter() {
+            // 前面的写入失败后,后面的请求也都不再写入
+            if let Some(e) = &write_err {
+                results.push(Err(e.clone()));
+                continue;
+            }
+            let mut positions = Vec::with_capacity(request.records.len());
+            for record in request.records.iter() {
+                match self.write_to_active_file(&mut active_file_write_guard, record) {
+                    Ok(pos) => {
+                        written += record.len() as u64;
+                        positions.push(pos)
+                    }
+                    Err(e) => {
+                        write_err = Some(e);
+                        break;
+                    }
+                }
+            }
+            match &write_err {
+                Some(e) => results.push(Err(e.clone())),
+                None => {
+                    need_sync |= request.sync;
+                    results.push(Ok(positions));
+                }
+            }
+        }
+        // 按照持久化策略判断是否需要持久化
+        let unsynced_bytes = self.sync_state.add_unsynced_bytes(written);
+        if let SyncPolicy::EveryNBytes(n) = self.options.sync_policy {
+            need_sync |= unsynced_bytes >= n;
+        }
+        // 一组请求只做一次持久化
+        if need_sync {
+            if let Err(e) = self.sync_state.sync(&active_file_write_guard) {
+                for res in results.iter_mut() {
+                    *res = Err(e.clone());
+                }
+            }
+        }
+        // 还持有活跃文件的锁,按照日志的顺序登记通知,持久化之后才会发送
+        for (request, res) in group.iter().zip(results.iter()) {
+            if let Ok(positions) = res {
+                let changes = std::mem::take(&mut *request.changes.lock());
+                self.watchers.notify(changes, *positions.last().unwrap());
+            }
+        }
+        drop(active_file_write_guard);
+        for (request, res) in group.iter().zip(results) {
+            *request.result.lock() = Some(res);
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use bytes::Bytes;
+use log::error;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    db::Engine,
+    delete_range::prefix_end,
+    errors::{Errors, Result},
+    options::WriteBatchOptions,
+    scan::next_key,
+};
+
+// 由proto/bitcask.proto生成的消息、服务端和客户端
+pub mod proto {
+    #![allow(clippy::all)]
+    tonic::include_proto!("bitcask");
+}
+
+pub use proto::bitcask_client::BitcaskClient;
+use proto::{
+    batch_op::Op,
+    bitcask_server::{Bitcask, BitcaskServer},
+    BatchRequest, BatchResponse, Change, DeleteRequest, DeleteResponse, GetRequest, GetResponse,
+    KeyValue, PutRequest, PutResponse, ScanRequest, WatchEvent, WatchRequest,
+};
+
+// Scan每次从engine读取的条数
+const SCAN_BATCH_SIZE: usize = 256;
+// 流式响应的缓冲区大小
+const STREAM_BUFFER_SIZE: usize = 16;
+// Watch检查客户端是否已经断开的间隔
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn to_status(e: Errors) -> Status {
+    match e {
+        Errors::KeyNotFound => Status::not_found(e.to_string()),
+        Errors::KeyEmptyErr | Errors::ExceedBatchMaxRows | Errors::ExceedBatchMaxBytes => {
+            Status::invalid_argument(e.to_string())
+        }
+        Errors::ReadOnly => Status::failed_precondition(e.to_string()),
+        e => Status::internal(e.to_string()),
+    }
+}
+
+// engine的操作会阻塞,放到专门的线程池中执行
+async fn run_blocking<T, F>(f: F) -> std::result::Result<T, Status>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => res.map_err(to_status),
+        Err(e) => Err(Status::internal(e.to_string())),
+    }
+}
+
+pub struct BitcaskService {
+    engine: Arc<Engine>,
+}
+
+impl BitcaskService {
+    pub fn new(engine: Arc<Engine>) -> Self {
+        BitcaskService { engine }
+    }
+}
+
+#[tonic::async_trait]
+impl Bitcask for BitcaskService {
+    async fn get(
+        &self,
+        request: Request<GetRequest>,
+    ) -> std::result::Result<Response<GetResponse>, Status> {
+        let engine = self.engine.clone();
+        let key = Bytes::from(request.into_inner().key);
+        let value = run_blocking(move || engine.get(key)).await?;
+        Ok(Response::new(GetResponse {
+            value: value.to_vec(),
+        }))
+    }
+
+    async fn put(
+        &self,
+        request: Request<PutRequest>,
+    ) -> std::result::Result<Response<PutResponse>, Status> {
+        let engine = self.engine.clone();
+        let request = request.into_inner();
+        run_blocking(move || engine.put(Bytes::from(request.key), Bytes::from(request.value)))
+            .await?;
+        Ok(Response::new(PutResponse {}))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> std::result::Result<Response<DeleteResponse>, Status> {
+        let engine = self.engine.clone();
+        let key = Bytes::from(request.into_inner().key);
+        run_blocking(move || engine.delete(key)).await?;
+        Ok(Response::new(DeleteResponse {}))
+    }
+
+    type ScanStream = ReceiverStream<std::result::Result<KeyValue, Status>>;
+
+    async fn scan(
+        &self,
+        request: Request<ScanRequest>,
+    ) -> std::result::Result<Response<Self::ScanStream>, Status> {
+        let request = request.into_inner();
+        let mut start = Bytes::from(request.start);
+        let mut end = Some(Bytes::from(request.end)).filter(|end| !end.is_empty());
+        if !request.prefix.is_empty() {
+            let prefix = Bytes::from(request.prefix);
+            if start < prefix {
+                start = prefix.clone();
+            }
+            end = match (end, prefix_end(&prefix)) {
+                (Some(end), Some(prefix_end)) => Some(end.min(prefix_end)),
+                (end, prefix_end) => end.or(prefix_end),
+            };
+        }
+        let mut remaining = match request.limit {
+            0 => usize::MAX,
+            limit => limit as usize,
+        };
+        let engine = self.engine.clone();
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER_SIZE);
+        // 分批读取,每一批在线程池中很快读完,发送时在异步任务中等待,不会一直占用线程池
+        // 客户端取消之后停止
+        tokio::spawn(async move {
+            while remaining > 0 {
+                let limit = remaining.min(SCAN_BATCH_SIZE);
+                let batch_engine = engine.clone();
+                let (batch_start, batch_end) = (start.clone(), end.clone());
+                let keys_only = request.keys_only;
+                let kvs = match run_blocking(move || match keys_only {
+                    true => Ok(batch_engine
+                        .scan_keys(batch_start, batch_end, limit)
+                        .into_iter()
+                        .map(|key| (key, Bytes::new()))
+                        .collect::<Vec<_>>()),
+                    false => batch_engine.scan(batch_start, batch_end, limit),
+                })
+                .await
+                {
+                    Ok(kvs) => kvs,
+                    Err(status) => {
+                        let _ = sender.send(Err(status)).await;
+                        return;
+                    }
+                };
+                if let Some((key, _)) = kvs.last() {
+                    start = next_key(key);
+                }
+                let done = kvs.len() < limit;
+                remaining -= kvs.len();
+                for (key, value) in kvs {
+                    let kv = KeyValue {
+                        key: key.to_vec(),
+                        value: value.to_vec(),
+                    };
+                    if sender.send(Ok(kv)).await.is_err() {
+                        return;
+                    }
+                }
+                if done {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn batch(
+        &self,
+        request: Request<BatchRequest>,
+    ) -> std::result::Result<Response<BatchResponse>, Status> {
+        let engine = self.engine.clone();
+        let ops = request.into_inner().ops;
+        let applied = ops.len() as u64;
+        run_blocking(move || {
+            let write_batch = engine.new_write_batch(WriteBatchOptions::default())?;
+            for op in ops {
+                match op.op {
+                    Some(Op::Put(kv)) => {
+                        write_batch.put(Bytes::from(kv.key), Bytes::from(kv.value))?
+                    }
+                    Some(Op::Delete(key)) => write_batch.delete(Bytes::from(key))?,
+                    None => (),
+                }
+            }
+            write_batch.commit()
+        })
+        .await?;
+        Ok(Response::new(BatchResponse { applied }))
+    }
+
+    type WatchStream = ReceiverStream<std::result::Result<WatchEvent, Status>>;
+
+    async fn watch(
+        &self,
+        request: Request<WatchRequest>,
+    ) -> std::result::Result<Response<Self::WatchStream>, Status> {
+        // 返回响应之前完成订阅,之后的写入都会被推送
+        let events = self.engine.watch(Bytes::from(request.into_inner().prefix));
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER_SIZE);
+        // 订阅会一直阻塞等待事件,每个订阅使用一个专门的线程,不占用执行其他请求的线程池
+        let spawned = std::thread::Builder::new()
+            .name("grpc-watch".to_string())
+            .spawn(move || loop {
+                let event = match events.recv_timeout(WATCH_POLL_INTERVAL) {
+                    Ok(event) => event,
+                    // 客户端断开之后退出,订阅在下一次通知时被移除
+                    Err(RecvTimeoutError::Timeout) if !sender.is_closed() => continue,
+                    Err(_) => return,
+                };
+                let event = WatchEvent {
+                    seq_no: event.seq_no,
+                    changes: event
+                        .changes
+                        .into_iter()
+                        .map(|change| Change {
+                            key: change.key.to_vec(),
+                            deleted: change.value.is_none(),
+                            value: change.value.map_or(Vec::new(), |value| value.to_vec()),
+                        })
+                        .collect(),
+                };
+                if sender.blocking_send(Ok(event)).is_err() {
+                    return;
+                }
+            });
+        if let Err(e) = spawned {
+            return Err(Status::resource_exhausted(e.to_string()));
+        }
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+// 在后台线程的tokio运行时中提供gRPC服务,Drop的时候停止
+pub struct GrpcServer {
+    local_addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl GrpcServer {
+    pub fn start<A: ToSocketAddrs>(engine: Arc<Engine>, addr: A) -> Result<Self> {
+        // 先同步地绑定地址,端口为0时也能拿到实际监听的地址
+        let listener = match TcpListener::bind(addr).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        }) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("failed to bind server address: {}", e);
+                return Err(Errors::ServerBindFailed);
+            }
+        };
+        let local_addr = match listener.local_addr() {
+            Ok(local_addr) => local_addr,
+            Err(_) => return Err(Errors::ServerBindFailed),
+        };
+        let runtime = match tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                error!("failed to build tokio runtime: {}", e);
+                return Err(Errors::ServerBindFailed);
+            }
+        };
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let handle = std::thread::spawn(move || {
+            runtime.block_on(async move {
+                let listener = match tokio::net::TcpListener::from_std(listener) {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        error!("failed to register grpc listener: {}", e);
+                        return;
+                    }
+                };
+                let res = tonic::transport::Server::builder()
+                    .add_service(BitcaskServer::new(BitcaskService::new(engine)))
+                    .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                        let _ = shutdown_rx.await;
+                    })
+                    .await;
+                if let Err(e) = res {
+                    error!("grpc server failed: {}", e);
+                }
+            });
+        });
+        Ok(GrpcServer {
+            local_addr,
+            shutdown: Some(shutdown),
+            handle: Some(handle),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for GrpcServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_grpc {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use tonic::Code;
+
+    use super::{
+        proto::{batch_op::Op, BatchOp, BatchRequest, GetRequest, KeyValue, PutRequest},
+        proto::{DeleteRequest, ScanRequest, WatchRequest},
+        BitcaskClient, GrpcServer,
+    };
+    use crate::{
+        db::Engine,
+        options::{Options, SyncPolicy},
+    };
+
+    #[test]
+    fn test_grpc() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-grpc");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        // 写入持久化之后watch才会收到通知
+        opts.sync_policy = SyncPolicy::Always;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let server = GrpcServer::start(engine, "127.0.0.1:0").unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let mut client = BitcaskClient::connect(format!("http://{}", server.local_addr()))
+                .await
+                .unwrap();
+            let kv = |key: &str, value: &str| KeyValue {
+                key: key.as_bytes().to_vec(),
+                value: value.as_bytes().to_vec(),
+            };
+
+            // 先订阅,之后的写入都能收到
+            let mut watch = client
+                .watch(WatchRequest {
+                    prefix: b"user:".to_vec(),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+
+            client
+                .put(PutRequest {
+                    key: b"user:0".to_vec(),
+                    value: b"v0".to_vec(),
+                })
+                .await
+                .unwrap();
+            let value = client
+                .get(GetRequest {
+                    key: b"user:0".to_vec(),
+                })
+                .await
+                .unwrap()
+                .into_inner()
+                .value;
+            assert_eq!(value, b"v0");
+            let status = client
+                .get(GetRequest {
+                    key: b"missing".to_vec(),
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::NotFound);
+            let status = client
+                .put(PutRequest {
+                    key: Vec::new(),
+                    value: b"v".to_vec(),
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument);
+
+            let mut ops: Vec<BatchOp> = (1..600)
+                .map(|i| BatchOp {
+                    op: Some(Op::Put(kv(&format!("user:{:03}", i), &i.to_string()))),
+                })
+                .collect();
+            ops.push(BatchOp {
+                op: Some(Op::Delete(b"user:0".to_vec())),
+            });
+            ops.push(BatchOp {
+                op: Some(Op::Put(kv("other", "x"))),
+            });
+            let applied = client
+                .batch(BatchRequest { ops })
+                .await
+                .unwrap()
+                .into_inner()
+                .applied;
+            assert_eq!(applied, 601);
+
+            let event = watch.message().await.unwrap().unwrap();
+            assert_eq!(event.changes.len(), 1);
+            assert_eq!(event.changes[0].key, b"user:0");
+            assert!(!event.changes[0].deleted);
+            // 批量提交的所有变化在同一个事件中,只包含匹配前缀的key
+            let event = watch.message().await.unwrap().unwrap();
+            assert_eq!(event.changes.len(), 600);
+            assert!(event.changes.iter().any(|change| change.deleted));
+
+            // 超过一批的数据分多次读取
+            let mut scan = client
+                .scan(ScanRequest {
+                    prefix: b"user:".to_vec(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            let mut kvs = Vec::new();
+            while let Some(kv) = scan.message().await.unwrap() {
+                kvs.push(kv);
+            }
+            assert_eq!(kvs.len(), 599);
+            assert_eq!(kvs[0], kv("user:001", "1"));
+            assert_eq!(kvs[598], kv("user:599", "599"));
+
+            let mut scan = client
+                .scan(ScanRequest {
+                    start: b"user:100".to_vec(),
+                    end: b"user:200".to_vec(),
+                    limit: 3,
+                    keys_only: true,
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            let mut keys = Vec::new();
+            while let Some(kv) = scan.message().await.unwrap() {
+                keys.push(String::from_utf8(kv.key).unwrap());
+            }
+            assert_eq!(keys, vec!["user:100", "user:101", "user:102"]);
+
+            client
+                .delete(DeleteRequest {
+                    key: b"user:001".to_vec(),
+                })
+                .await
+                .unwrap();
+            let event = watch.message().await.unwrap().unwrap();
+            assert!(event.changes[0].deleted);
+        });
+        drop(server);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
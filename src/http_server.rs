@@ -0,0 +1,761 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use log::error;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    db::Engine,
+    delete_range::prefix_end,
+    errors::{Errors, Result},
+    options::WriteBatchOptions,
+    scan::next_key,
+    tcp_server::TcpServer,
+};
+
+// 请求头的总大小上限
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+// 请求体的大小上限
+const MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+// 列表接口默认和最多返回的条数
+const DEFAULT_LIST_LIMIT: usize = 100;
+const MAX_LIST_LIMIT: usize = 1000;
+
+// HTTP/JSON接口的服务,每个连接一个线程,支持keep-alive
+//   GET/PUT/DELETE /kv/{key}  读写单个key,value就是请求体和响应体,不做任何编码
+//   GET /kv?prefix=&start=&end=&limit=&cursor=&keys_only=  按照key的顺序分页列出
+//   POST /batch  {"ops":[{"op":"put","key":"6b","value":"76"},{"op":"delete","key":"6b"}]}
+//   POST /admin/merge, GET /admin/stats, POST /admin/backup {"dir":"name"}
+// JSON中的key和value,以及列表接口的prefix、start、end和cursor都是十六进制编码的,可以是任意的二进制数据
+// 备份只能写到启动时配置的backup_root之下,dir是相对于它的路径,没有配置时不能备份
+// 管理接口没有鉴权,只应该监听在可信的地址上
+pub struct HttpServer {
+    server: TcpServer,
+}
+
+impl HttpServer {
+    pub fn start<A: ToSocketAddrs>(
+        engine: Arc<Engine>,
+        addr: A,
+        backup_root: Option<PathBuf>,
+    ) -> Result<Self> {
+        // 解析出真实的路径,之后用它判断备份目录有没有通过符号链接指向外面
+        let backup_root = match backup_root {
+            Some(root) => {
+                let res = std::fs::create_dir_all(&root).and_then(|_| root.canonicalize());
+                match res {
+                    Ok(root) => Some(root),
+                    Err(e) => {
+                        error!("failed to create backup root: {}", e);
+                        return Err(Errors::DirPathCreateFailed);
+                    }
+                }
+            }
+            None => None,
+        };
+        let server = TcpServer::start(addr, move |stream| {
+            if let Err(e) = handle_connection(&engine, backup_root.as_deref(), stream) {
+                error!("failed to handle http connection: {}", e);
+            }
+        })?;
+        Ok(HttpServer { server })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    body: Vec<u8>,
+    keep_alive: bool,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: Value) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Response::json(status, json!({ "error": message }))
+    }
+
+    fn no_content() -> Self {
+        Response {
+            status: 204,
+            content_type: "application/json",
+            body: Vec::new(),
+        }
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W, keep_alive: bool) -> std::io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n",
+            self.status,
+            reason_phrase(self.status),
+            self.content_type,
+            self.body.len()
+        )?;
+        if !keep_alive {
+            writer.write_all(b"Connection: close\r\n")?;
+        }
+        writer.write_all(b"\r\n")?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        501 => "Not Implemented",
+        _ => "Internal Server Error",
+    }
+}
+
+fn error_response(e: Errors) -> Response {
+    let status = match e {
+        Errors::KeyNotFound => 404,
+        Errors::KeyEmptyErr
+        | Errors::ExceedBatchMaxRows
+        | Errors::ExceedBatchMaxBytes
+        | Errors::DirPathNotEmpty => 400,
+        Errors::ReadOnly => 403,
+        Errors::MergeInProcess => 409,
+        _ => 500,
+    };
+    Response::error(status, &e.to_string())
+}
+
+fn handle_connection(
+    engine: &Engine,
+    backup_root: Option<&Path>,
+    stream: TcpStream,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        let request = match read_request(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                let status = match e.kind() {
+                    ErrorKind::Unsupported => 501,
+                    ErrorKind::OutOfMemory => 413,
+                    _ => 400,
+                };
+                return Response::error(status, &e.to_string()).write_to(&mut writer, false);
+            }
+        };
+        let response = route(engine, backup_root, &request);
+        response.write_to(&mut writer, request.keep_alive)?;
+        if !request.keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+// 读取一个请求,连接在两个请求之间关闭时返回None
+fn read_request<R: BufRead>(reader: &mut R) -> std::io::Result<Option<Request>> {
+    let mut header_size = 0;
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        let n = reader
+            .by_ref()
+            .take((MAX_HEADER_SIZE - header_size) as u64)
+            .read_line(&mut line)?;
+        if n == 0 {
+            if lines.is_empty() {
+                return Ok(None);
+            }
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "incomplete request header",
+            ));
+        }
+        header_size += n;
+        if !line.ends_with('\n') {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "request header too large",
+            ));
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            // 忽略请求之间多余的空行
+            if lines.is_empty() {
+                continue;
+            }
+            break;
+        }
+        lines.push(line.to_string());
+    }
+
+    let mut request_line = lines[0].split(' ');
+    let (method, target, version) = match (
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+    ) {
+        (Some(method), Some(target), Some(version)) => (method, target, version),
+        _ => return Err(Error::new(ErrorKind::InvalidData, "invalid request line")),
+    };
+    // HTTP/1.1默认保持连接,HTTP/1.0默认关闭
+    let mut keep_alive = version == "HTTP/1.1";
+    let mut content_length = 0;
+    for line in &lines[1..] {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim().to_ascii_lowercase(), value.trim()),
+            None => return Err(Error::new(ErrorKind::InvalidData, "invalid header")),
+        };
+        match name.as_str() {
+            "content-length" => {
+                content_length = value
+                    .parse::<usize>()
+                    .map_err(|_| Error::new(ErrorKind::InvalidData, "invalid content-length"))?;
+            }
+            "transfer-encoding" => {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "transfer-encoding is not supported",
+                ));
+            }
+            "connection" => keep_alive = !value.eq_ignore_ascii_case("close"),
+            _ => (),
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Err(Error::new(ErrorKind::OutOfMemory, "request body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut params = HashMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        match (percent_decode(name, true), percent_decode(value, true)) {
+            (Some(name), Some(value)) => {
+                params.insert(
+                    String::from_utf8_lossy(&name).to_string(),
+                    String::from_utf8_lossy(&value).to_string(),
+                );
+            }
+            _ => return Err(Error::new(ErrorKind::InvalidData, "invalid query string")),
+        }
+    }
+    Ok(Some(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: params,
+        body,
+        keep_alive,
+    }))
+}
+
+fn percent_decode(s: &str, plus_as_space: bool) -> Option<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(idx + 1..idx + 3)?).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                idx += 3;
+            }
+            b'+' if plus_as_space => {
+                decoded.push(b' ');
+                idx += 1;
+            }
+            c => {
+                decoded.push(c);
+                idx += 1;
+            }
+        }
+    }
+    Some(decoded)
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(s.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+// 读取十六进制编码的参数,参数不存在时返回None
+fn hex_param(
+    query: &HashMap<String, String>,
+    name: &str,
+) -> std::result::Result<Option<Bytes>, ()> {
+    match query.get(name) {
+        Some(value) => hex_decode(value)
+            .map(|value| Some(Bytes::from(value)))
+            .ok_or(()),
+        None => Ok(None),
+    }
+}
+
+fn route(engine: &Engine, backup_root: Option<&Path>, request: &Request) -> Response {
+    let method = request.method.as_str();
+    let path = request.path.as_str();
+    let res = if let Some(key) = path.strip_prefix("/kv/").filter(|key| !key.is_empty()) {
+        let key = match percent_decode(key, false) {
+            Some(key) => Bytes::from(key),
+            None => return Response::error(400, "invalid key"),
+        };
+        match method {
+            "GET" => engine.get(key).map(|value| Response {
+                status: 200,
+                content_type: "application/octet-stream",
+                body: value.to_vec(),
+            }),
+            "PUT" => engine
+                .put(key, Bytes::from(request.body.clone()))
+                .map(|_| Response::no_content()),
+            "DELETE" => engine.delete(key).map(|_| Response::no_content()),
+            _ => return Response::error(405, "method not allowed"),
+        }
+    } else {
+        match (method, path) {
+            ("GET", "/kv") | ("GET", "/kv/") => list(engine, &request.query),
+            ("POST", "/batch") => batch(engine, &request.body),
+            ("POST", "/admin/merge") => engine
+                .merge()
+                .map(|_| Response::json(200, json!({ "ok": true }))),
+            ("GET", "/admin/stats") => engine.stat().map(|stat| {
+                Response::json(
+                    200,
+                    json!({
+                        "key_num": stat.key_num,
+                        "data_file_num": stat.data_file_num,
+                        "disk_size": stat.disk_size,
+                    }),
+                )
+            }),
+            ("POST", "/admin/backup") => backup(engine, backup_root, &request.body),
+            (_, "/kv")
+            | (_, "/kv/")
+            | (_, "/batch")
+            | (_, "/admin/merge")
+            | (_, "/admin/stats")
+            | (_, "/admin/backup") => return Response::error(405, "method not allowed"),
+            _ => return Response::error(404, "not found"),
+        }
+    };
+    res.unwrap_or_else(error_response)
+}
+
+// 按照key的顺序列出[start, end)范围内以prefix开头的key,cursor为上一页返回的next_cursor
+fn list(engine: &Engine, query: &HashMap<String, String>) -> Result<Response> {
+    let limit = match query.get("limit").map(|limit| limit.parse::<usize>()) {
+        Some(Ok(limit)) if limit > 0 => limit.min(MAX_LIST_LIMIT),
+        Some(_) => return Ok(Response::error(400, "invalid limit")),
+        None => DEFAULT_LIST_LIMIT,
+    };
+    let (prefix, start, cursor, mut end) = match (
+        hex_param(query, "prefix"),
+        hex_param(query, "start"),
+        hex_param(query, "cursor"),
+        hex_param(query, "end"),
+    ) {
+        (Ok(prefix), Ok(start), Ok(cursor), Ok(end)) => (prefix, start, cursor, end),
+        _ => return Ok(Response::error(400, "invalid hex parameter")),
+    };
+    let mut start = match cursor {
+        Some(last_key) => next_key(&last_key),
+        None => start.unwrap_or_default(),
+    };
+    if let Some(prefix) = prefix {
+        if start < prefix {
+            start = prefix.clone();
+        }
+        end = match (end, prefix_end(&prefix)) {
+            (Some(end), Some(prefix_end)) => Some(end.min(prefix_end)),
+            (end, prefix_end) => end.or(prefix_end),
+        };
+    }
+    let keys_only = query
+        .get("keys_only")
+        .is_some_and(|keys_only| keys_only == "true" || keys_only == "1");
+    let (items, last_key) = match keys_only {
+        true => {
+            let keys = engine.scan_keys(start, end, limit);
+            let last_key = keys.last().filter(|_| keys.len() == limit).cloned();
+            let items: Vec<Value> = keys
+                .iter()
+                .map(|key| json!({ "key": hex_encode(key) }))
+                .collect();
+            (items, last_key)
+        }
+        false => {
+            let kvs = engine.scan(start, end, limit)?;
+            let last_key = kvs
+                .last()
+                .filter(|_| kvs.len() == limit)
+                .map(|(key, _)| key.clone());
+            let items: Vec<Value> = kvs
+                .iter()
+                .map(|(key, value)| {
+                    json!({
+                        "key": hex_encode(key),
+                        "value": hex_encode(value),
+                    })
+                })
+                .collect();
+            (items, last_key)
+        }
+    };
+    Ok(Response::json(
+        200,
+        json!({
+            "items": items,
+            "next_cursor": last_key.map(|key| hex_encode(&key)),
+        }),
+    ))
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum BatchOp {
+    Put { key: String, value: String },
+    Delete { key: String },
+}
+
+#[derive(Deserialize)]
+struct BatchRequest {
+    ops: Vec<BatchOp>,
+}
+
+// 所有的操作在一个WriteBatch中原子地提交
+fn batch(engine: &Engine, body: &[u8]) -> Result<Response> {
+    let request: BatchRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return Ok(Response::error(400, &e.to_string())),
+    };
+    let write_batch = engine.new_write_batch(WriteBatchOptions::default())?;
+    for op in request.ops.iter() {
+        let decoded = match op {
+            BatchOp::Put { key, value } => hex_decode(key).zip(hex_decode(value).map(Some)),
+            BatchOp::Delete { key } => hex_decode(key).map(|key| (key, None)),
+        };
+        match decoded {
+            Some((key, Some(value))) => write_batch.put(Bytes::from(key), Bytes::from(value))?,
+            Some((key, None)) => write_batch.delete(Bytes::from(key))?,
+            None => return Ok(Response::error(400, "invalid hex in batch")),
+        }
+    }
+    write_batch.commit()?;
+    Ok(Response::json(200, json!({ "applied": request.ops.len() })))
+}
+
+#[derive(Deserialize)]
+struct BackupRequest {
+    dir: PathBuf,
+}
+
+fn backup(engine: &Engine, backup_root: Option<&Path>, body: &[u8]) -> Result<Response> {
+    let backup_root = match backup_root {
+        Some(backup_root) => backup_root,
+        None => return Ok(Response::error(403, "backup is disabled")),
+    };
+    let request: BackupRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return Ok(Response::error(400, &e.to_string())),
+    };
+    match backup_dir(backup_root, &request.dir) {
+        Some(dir) => engine.backup(dir)?,
+        None => {
+            return Ok(Response::error(
+                400,
+                "backup dir is outside of the backup root",
+            ))
+        }
+    }
+    Ok(Response::json(200, json!({ "ok": true })))
+}
+
+// 把请求中的相对路径放到backup_root之下,不能是绝对路径,也不能包含..
+// 已经存在的部分解析符号链接之后也必须还在backup_root之下
+fn backup_dir(backup_root: &Path, dir: &Path) -> Option<PathBuf> {
+    if dir.as_os_str().is_empty()
+        || !dir
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    let target = backup_root.join(dir);
+    let existing = target.ancestors().find(|path| path.exists())?;
+    match existing.canonicalize() {
+        Ok(existing) if existing.starts_with(backup_root) => Some(target),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test_http_server {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use serde_json::{json, Value};
+
+    use super::{hex_encode, HttpServer};
+    use crate::{db::Engine, options::Options};
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(server: &HttpServer) -> Client {
+            let stream = TcpStream::connect(server.local_addr()).unwrap();
+            Client {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+            }
+        }
+
+        // 在同一个连接上发送请求,返回状态码和响应体
+        fn call(&mut self, method: &str, target: &str, body: &[u8]) -> (u16, Vec<u8>) {
+            let head = format!(
+                "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n",
+                method,
+                target,
+                body.len()
+            );
+            self.writer.write_all(head.as_bytes()).unwrap();
+            self.writer.write_all(body).unwrap();
+
+            let mut status_line = String::new();
+            self.reader.read_line(&mut status_line).unwrap();
+            let status = status_line.split(' ').nth(1).unwrap().parse().unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                self.reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    content_length = value.parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            self.reader.read_exact(&mut body).unwrap();
+            (status, body)
+        }
+
+        fn call_json(&mut self, method: &str, target: &str, body: Value) -> (u16, Value) {
+            let (status, body) = self.call(method, target, body.to_string().as_bytes());
+            (status, serde_json::from_slice(&body).unwrap())
+        }
+    }
+
+    #[test]
+    fn test_http_server() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-http-server");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let backup_root = PathBuf::from("/tmp/bitcask-rs-http-server-backup");
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let server =
+            HttpServer::start(engine.clone(), "127.0.0.1:0", Some(backup_root.clone())).unwrap();
+        let mut client = Client::connect(&server);
+
+        assert_eq!(client.call("PUT", "/kv/hello", b"world"), (204, Vec::new()));
+        assert_eq!(
+            client.call("GET", "/kv/hello", b""),
+            (200, b"world".to_vec())
+        );
+        // key中的特殊字符需要编码
+        assert_eq!(client.call("PUT", "/kv/a%2Fb%20c", b"\x00\xff").0, 204);
+        assert_eq!(
+            engine.get(bytes::Bytes::from("a/b c")).unwrap(),
+            bytes::Bytes::from(&b"\x00\xff"[..])
+        );
+        assert_eq!(client.call("DELETE", "/kv/hello", b"").0, 204);
+        assert_eq!(client.call("GET", "/kv/hello", b"").0, 404);
+        assert_eq!(client.call("POST", "/kv/hello", b"").0, 405);
+        assert_eq!(client.call("GET", "/nothing", b"").0, 404);
+
+        // JSON中的key和value都是十六进制编码的
+        let hex = |s: &str| hex_encode(s.as_bytes());
+        let ops: Vec<Value> = (0..25)
+            .map(|i| {
+                json!({
+                    "op": "put",
+                    "key": hex(&format!("user:{:02}", i)),
+                    "value": hex(&i.to_string()),
+                })
+            })
+            .chain([json!({ "op": "delete", "key": hex("a/b c") })])
+            .collect();
+        assert_eq!(
+            client.call_json("POST", "/batch", json!({ "ops": ops })),
+            (200, json!({ "applied": 26 }))
+        );
+        assert_eq!(
+            client
+                .call_json("POST", "/batch", json!({ "ops": [{ "op": "bad" }] }))
+                .0,
+            400
+        );
+        assert_eq!(
+            client
+                .call_json(
+                    "POST",
+                    "/batch",
+                    json!({ "ops": [{ "op": "delete", "key": "user" }] })
+                )
+                .0,
+            400
+        );
+        // 二进制的key和value
+        let ops = json!({ "ops": [{ "op": "put", "key": "00ff", "value": "ff00" }] });
+        assert_eq!(client.call_json("POST", "/batch", ops).0, 200);
+        let (_, page) = client.call_json("GET", "/kv?prefix=00", Value::Null);
+        assert_eq!(page["items"], json!([{ "key": "00ff", "value": "ff00" }]));
+        engine.delete(bytes::Bytes::from(&b"\x00\xff"[..])).unwrap();
+
+        // 分页列出前缀下的所有key
+        let mut keys = Vec::new();
+        let prefix = hex("user:");
+        let mut target = format!("/kv?prefix={}&limit=10", prefix);
+        loop {
+            let (status, page) = client.call_json("GET", &target, Value::Null);
+            assert_eq!(status, 200);
+            for item in page["items"].as_array().unwrap() {
+                keys.push(item["key"].as_str().unwrap().to_string());
+            }
+            match page["next_cursor"].as_str() {
+                Some(cursor) => {
+                    target = format!("/kv?prefix={}&limit=10&cursor={}", prefix, cursor)
+                }
+                None => break,
+            }
+        }
+        let expected: Vec<String> = (0..25).map(|i| hex(&format!("user:{:02}", i))).collect();
+        assert_eq!(keys, expected);
+        let target = format!("/kv?start={}&end={}", hex("user:05"), hex("user:08"));
+        let (_, page) = client.call_json("GET", &target, Value::Null);
+        assert_eq!(
+            page,
+            json!({
+                "items": [
+                    { "key": hex("user:05"), "value": hex("5") },
+                    { "key": hex("user:06"), "value": hex("6") },
+                    { "key": hex("user:07"), "value": hex("7") },
+                ],
+                "next_cursor": null,
+            })
+        );
+        let target = format!("/kv?prefix={}&keys_only=true&limit=3", hex("user:1"));
+        let (_, page) = client.call_json("GET", &target, Value::Null);
+        assert_eq!(
+            page["items"],
+            json!([
+                { "key": hex("user:10") },
+                { "key": hex("user:11") },
+                { "key": hex("user:12") },
+            ])
+        );
+        assert_eq!(client.call("GET", "/kv?cursor=zz", b"").0, 400);
+        assert_eq!(client.call("GET", "/kv?prefix=user", b"").0, 400);
+
+        let (status, stats) = client.call_json("GET", "/admin/stats", Value::Null);
+        assert_eq!(status, 200);
+        assert_eq!(stats["key_num"], 25);
+        assert_eq!(
+            client.call_json("POST", "/admin/merge", Value::Null),
+            (200, json!({ "ok": true }))
+        );
+        assert_eq!(
+            client.call_json("POST", "/admin/backup", json!({ "dir": "b1" })),
+            (200, json!({ "ok": true }))
+        );
+        // 备份目录不能在backup_root之外
+        for dir in [
+            "/tmp/bitcask-rs-http-server-escape",
+            "../escape",
+            "b1/../../escape",
+            "",
+        ] {
+            assert_eq!(
+                client
+                    .call_json("POST", "/admin/backup", json!({ "dir": dir }))
+                    .0,
+                400
+            );
+        }
+        std::os::unix::fs::symlink("/tmp", backup_root.join("link")).unwrap();
+        assert_eq!(
+            client
+                .call_json("POST", "/admin/backup", json!({ "dir": "link/escape" }))
+                .0,
+            400
+        );
+        assert!(!PathBuf::from("/tmp/escape").exists());
+        let mut backup_opts = opts.clone();
+        backup_opts.dir_path = backup_root.join("b1");
+        let restored = Engine::open(backup_opts).unwrap();
+        assert_eq!(restored.get(bytes::Bytes::from("user:24")).unwrap(), "24");
+        drop(restored);
+
+        drop(client);
+        drop(server);
+
+        // 没有配置backup_root时不能备份
+        let server = HttpServer::start(engine.clone(), "127.0.0.1:0", None).unwrap();
+        let mut client = Client::connect(&server);
+        assert_eq!(
+            client
+                .call_json("POST", "/admin/backup", json!({ "dir": "b2" }))
+                .0,
+            403
+        );
+        drop(client);
+        drop(server);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(backup_root).expect("failed to remove path");
+    }
+}
@@ -1,3 +1,4 @@
+use std::ops::Bound;
 use std::{collections::BTreeMap, sync::Arc};
 
 use super::{IndexIterator, IndexIteratorOptions, Indexer};
@@ -34,7 +35,58 @@ impl Indexer for Btree {
         remove_res.is_some()
     }
 
-    fn iterator(&mut self, options: IndexIteratorOptions) -> Box<dyn IndexIterator> {
+    fn delete_range(&self, start: &[u8], end: Option<&[u8]>) -> usize {
+        let mut write_guard = self.tree.write();
+        let end_bound = match end {
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+        let keys: Vec<Vec<u8>> = write_guard
+            .range::<[u8], _>((Bound::Included(start), end_bound))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys.iter() {
+            write_guard.remove(key);
+        }
+        keys.len()
+    }
+
+    fn scan(&self, start: &[u8], end: Option<&[u8]>, limit: usize) -> Vec<(Vec<u8>, LogRecordPos)> {
+        let read_guard = self.tree.read();
+        let end_bound = match end {
+            // start大于end时range会panic
+            Some(end) if start >= end => return Vec::new(),
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+        read_guard
+            .range::<[u8], _>((Bound::Included(start), end_bound))
+            .take(limit)
+            .map(|(key, pos)| (key.clone(), *pos))
+            .collect()
+    }
+
+    fn scan_reverse(
+        &self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        limit: usize,
+    ) -> Vec<(Vec<u8>, LogRecordPos)> {
+        let read_guard = self.tree.read();
+        let end_bound = match end {
+            Some(end) if start >= end => return Vec::new(),
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+        read_guard
+            .range::<[u8], _>((Bound::Included(start), end_bound))
+            .rev()
+            .take(limit)
+            .map(|(key, pos)| (key.clone(), *pos))
+            .collect()
+    }
+
+    fn iterator(&self, options: IndexIteratorOptions) -> Box<dyn IndexIterator> {
         // 获取读锁
         let read_guard = self.tree.read();
         let mut items = Vec::with_capacity(read_guard.len());
@@ -60,6 +112,10 @@ impl Indexer for Btree {
         }
         Ok(res)
     }
+
+    fn len(&self) -> usize {
+        self.tree.read().len()
+    }
 }
 
 impl Btree {
@@ -129,6 +185,7 @@ mod test_btree {
             LogRecordPos {
                 file_id: 0,
                 offset: 10,
+                value_size: 0,
             },
         );
         assert_eq!(flag, true);
@@ -137,6 +194,7 @@ mod test_btree {
             LogRecordPos {
                 file_id: 0,
                 offset: 20,
+                value_size: 0,
             },
         );
         assert_eq!(flag, true);
@@ -151,6 +209,7 @@ mod test_btree {
             LogRecordPos {
                 file_id: 0,
                 offset: 10,
+                value_size: 0,
             },
         );
         assert_eq!(flag, true);
@@ -159,6 +218,7 @@ mod test_btree {
             LogRecordPos {
                 file_id: 0,
                 offset: 20,
+                value_size: 0,
             },
         );
         assert_eq!(flag, true);
@@ -186,6 +246,7 @@ mod test_btree {
             LogRecordPos {
                 file_id: 0,
                 offset: 10,
+                value_size: 0,
             },
         );
         assert_eq!(flag, true);
@@ -194,6 +255,7 @@ mod test_btree {
             LogRecordPos {
                 file_id: 0,
                 offset: 20,
+                value_size: 0,
             },
         );
         assert_eq!(flag, true);
@@ -222,10 +284,55 @@ mod test_btree {
         assert!(log.is_none());
     }
 
+    #[test]
+    fn test_btree_delete_range() {
+        let btree = Btree::new();
+        for key in ["a", "b", "ba", "bb", "c", "d"] {
+            btree.put(
+                key.as_bytes().to_vec(),
+                LogRecordPos {
+                    file_id: 0,
+                    offset: 0,
+                    value_size: 0,
+                },
+            );
+        }
+        // 范围扫描
+        let keys: Vec<Vec<u8>> = btree
+            .scan("b".as_bytes(), Some("c".as_bytes()), 2)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![b"b".to_vec(), b"ba".to_vec()]);
+        assert_eq!(btree.scan("c".as_bytes(), None, 10).len(), 2);
+        let keys: Vec<Vec<u8>> = btree
+            .scan_reverse("b".as_bytes(), Some("c".as_bytes()), 2)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![b"bb".to_vec(), b"ba".to_vec()]);
+        // start大于end时为空
+        assert!(btree
+            .scan("c".as_bytes(), Some("b".as_bytes()), 10)
+            .is_empty());
+        assert!(btree
+            .scan_reverse("c".as_bytes(), Some("b".as_bytes()), 10)
+            .is_empty());
+        // 不包含结束key
+        assert_eq!(btree.delete_range("b".as_bytes(), Some("c".as_bytes())), 3);
+        assert!(btree.get("a".as_bytes().to_vec()).is_some());
+        assert!(btree.get("ba".as_bytes().to_vec()).is_none());
+        assert!(btree.get("c".as_bytes().to_vec()).is_some());
+        // 没有上界
+        assert_eq!(btree.delete_range("c".as_bytes(), None), 2);
+        assert_eq!(btree.list_keys().unwrap().len(), 1);
+        assert_eq!(btree.len(), 1);
+    }
+
     #[test]
     fn test_btree_iterator_seek_next_rewind() {
         // 对应空数据的情况
-        let mut bt = Btree::new();
+        let bt = Btree::new();
         let mut iter1 = bt.iterator(IndexIteratorOptions::default());
         iter1.seek(&"key1".as_bytes().to_vec());
         assert!(iter1.next().is_none());
@@ -236,6 +343,7 @@ mod test_btree {
             LogRecordPos {
                 file_id: 0,
                 offset: 0,
+                value_size: 0,
             },
         );
         bt.put(
@@ -243,6 +351,7 @@ mod test_btree {
             LogRecordPos {
                 file_id: 0,
                 offset: 0,
+                value_size: 0,
             },
         );
         bt.put(
@@ -250,6 +359,7 @@ mod test_btree {
             LogRecordPos {
                 file_id: 0,
                 offset: 0,
+                value_size: 0,
             },
         );
 
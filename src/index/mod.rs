@@ -7,8 +7,21 @@ pub(crate) trait Indexer: Send + Sync {
     fn put(&self, key: Vec<u8>, pos: LogRecordPos) -> bool;
     fn get(&self, key: Vec<u8>) -> Option<LogRecordPos>;
     fn delete(&self, key: Vec<u8>) -> bool;
-    fn iterator(&mut self, options: IndexIteratorOptions) -> Box<dyn IndexIterator>;
+    // 删除[start, end)范围内的所有key,end为None表示没有上界,返回删除的个数
+    fn delete_range(&self, start: &[u8], end: Option<&[u8]>) -> usize;
+    // 按顺序返回[start, end)范围内最多limit个key,end为None表示没有上界
+    fn scan(&self, start: &[u8], end: Option<&[u8]>, limit: usize) -> Vec<(Vec<u8>, LogRecordPos)>;
+    // 和scan一样,但是从大到小返回[start, end)范围内最大的limit个key
+    fn scan_reverse(
+        &self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        limit: usize,
+    ) -> Vec<(Vec<u8>, LogRecordPos)>;
+    fn iterator(&self, options: IndexIteratorOptions) -> Box<dyn IndexIterator>;
     fn list_keys(&self) -> Result<Vec<Bytes>>;
+    // 索引中key的个数
+    fn len(&self) -> usize;
 }
 
 pub(crate) fn NewIndexer(index_type: IndexType) -> Box<dyn Indexer> {
@@ -29,9 +42,9 @@ pub(crate) trait IndexIterator: Sync + Send {
 // 添加配置项，用于指定迭代器的查询方案
 pub struct IndexIteratorOptions {
     // 指定是否由大到小来查
-    reverse: bool,
+    pub reverse: bool,
     // 指定查询的key的前缀
-    prefix: Vec<u8>,
+    pub prefix: Vec<u8>,
 }
 
 // 实现默认配置
@@ -45,7 +58,7 @@ impl Default for IndexIteratorOptions {
 }
 
 impl IndexIteratorOptions {
-    pub(crate) fn NewOptions(flag: bool, prefix: Vec<u8>) -> IndexIteratorOptions {
+    pub fn NewOptions(flag: bool, prefix: Vec<u8>) -> IndexIteratorOptions {
         IndexIteratorOptions {
             prefix: prefix,
             reverse: flag,
@@ -3,12 +3,34 @@ mod data;
 mod db_tests;
 mod errors;
 mod fio;
+mod flusher;
+mod group_commit;
 mod index;
+mod sequence;
+mod tcp_server;
 mod util;
 // 这里使用pub是因为我们db是整个项目的
 // 对外使用接口
+pub mod backup;
+pub mod blob;
+pub mod cdc;
+pub mod conditional;
 pub mod db;
+pub mod delete_range;
+pub mod get_range;
+pub mod grpc;
+pub mod http_server;
 pub mod iterator;
+pub mod memcached;
 pub mod merge;
+pub mod merge_operator;
+pub mod metadata;
+pub mod multi_get;
 pub mod options;
+pub mod point_in_time;
+pub mod redis;
+pub mod replication;
+pub mod scan;
+pub mod stat;
+pub mod watch;
 pub mod write_batch;
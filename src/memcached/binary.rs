@@ -0,0 +1,331 @@
+use std::io::{BufReader, BufWriter, Read, Result, Write};
+use std::net::TcpStream;
+
+use bytes::{Buf, BufMut, Bytes};
+
+use super::{MemcachedStore, StoreMode, StoreResult, MAX_KEY_LENGTH, MAX_VALUE_SIZE};
+use crate::errors::Errors;
+
+pub(crate) const REQUEST_MAGIC: u8 = 0x80;
+const RESPONSE_MAGIC: u8 = 0x81;
+const HEADER_SIZE: usize = 24;
+
+// 命令
+const OP_GET: u8 = 0x00;
+const OP_SET: u8 = 0x01;
+const OP_ADD: u8 = 0x02;
+const OP_REPLACE: u8 = 0x03;
+const OP_DELETE: u8 = 0x04;
+const OP_INCREMENT: u8 = 0x05;
+const OP_DECREMENT: u8 = 0x06;
+const OP_QUIT: u8 = 0x07;
+const OP_GETQ: u8 = 0x09;
+const OP_NOOP: u8 = 0x0a;
+const OP_VERSION: u8 = 0x0b;
+const OP_GETK: u8 = 0x0c;
+const OP_GETKQ: u8 = 0x0d;
+const OP_SETQ: u8 = 0x11;
+const OP_ADDQ: u8 = 0x12;
+const OP_REPLACEQ: u8 = 0x13;
+const OP_DELETEQ: u8 = 0x14;
+const OP_INCREMENTQ: u8 = 0x15;
+const OP_DECREMENTQ: u8 = 0x16;
+const OP_QUITQ: u8 = 0x17;
+
+// 状态码
+const STATUS_OK: u16 = 0x00;
+const STATUS_KEY_NOT_FOUND: u16 = 0x01;
+const STATUS_KEY_EXISTS: u16 = 0x02;
+const STATUS_VALUE_TOO_LARGE: u16 = 0x03;
+const STATUS_INVALID_ARGUMENTS: u16 = 0x04;
+const STATUS_NOT_STORED: u16 = 0x05;
+const STATUS_NON_NUMERIC: u16 = 0x06;
+const STATUS_UNKNOWN_COMMAND: u16 = 0x81;
+const STATUS_INTERNAL_ERROR: u16 = 0x84;
+
+// incr/decr的exptime为这个值时,key不存在不写入初始值
+const NO_INITIAL_EXPTIME: u32 = 0xffff_ffff;
+
+struct Request {
+    opcode: u8,
+    opaque: u32,
+    cas: u64,
+    extras: Bytes,
+    key: Bytes,
+    value: Bytes,
+}
+
+struct Response {
+    status: u16,
+    cas: u64,
+    extras: Vec<u8>,
+    key: Bytes,
+    value: Bytes,
+}
+
+impl Response {
+    fn ok() -> Self {
+        Response::status(STATUS_OK)
+    }
+
+    fn status(status: u16) -> Self {
+        Response {
+            status,
+            cas: 0,
+            extras: Vec::new(),
+            key: Bytes::new(),
+            value: Bytes::new(),
+        }
+    }
+
+    // 出错时value中带上错误信息
+    fn error(status: u16, message: &str) -> Self {
+        Response {
+            value: Bytes::from(message.to_string()),
+            ..Response::status(status)
+        }
+    }
+
+    fn encode(&self, opcode: u8, opaque: u32, buf: &mut Vec<u8>) {
+        let body_len = self.extras.len() + self.key.len() + self.value.len();
+        buf.put_u8(RESPONSE_MAGIC);
+        buf.put_u8(opcode);
+        buf.put_u16(self.key.len() as u16);
+        buf.put_u8(self.extras.len() as u8);
+        // data type
+        buf.put_u8(0);
+        buf.put_u16(self.status);
+        buf.put_u32(body_len as u32);
+        buf.put_u32(opaque);
+        buf.put_u64(self.cas);
+        buf.extend_from_slice(&self.extras);
+        buf.extend_from_slice(&self.key);
+        buf.extend_from_slice(&self.value);
+    }
+}
+
+// 读取一个请求,连接关闭时返回None
+fn read_request(reader: &mut BufReader<TcpStream>) -> Result<Option<Request>> {
+    let mut header = [0; HEADER_SIZE];
+    match reader.read_exact(&mut header) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut header = &header[..];
+    let magic = header.get_u8();
+    let opcode = header.get_u8();
+    let key_len = header.get_u16() as usize;
+    let extras_len = header.get_u8() as usize;
+    header.advance(3);
+    let body_len = header.get_u32() as usize;
+    let opaque = header.get_u32();
+    let cas = header.get_u64();
+    if magic != REQUEST_MAGIC
+        || extras_len + key_len > body_len
+        || body_len > MAX_VALUE_SIZE + MAX_KEY_LENGTH + u8::MAX as usize
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "invalid binary request header",
+        ));
+    }
+    let mut body = vec![0; body_len];
+    reader.read_exact(&mut body)?;
+    let mut body = Bytes::from(body);
+    let extras = body.split_to(extras_len);
+    let key = body.split_to(key_len);
+    Ok(Some(Request {
+        opcode,
+        opaque,
+        cas,
+        extras,
+        key,
+        value: body,
+    }))
+}
+
+// 处理二进制协议的连接,直到连接关闭或者收到quit
+pub(crate) fn serve(
+    store: &MemcachedStore,
+    reader: &mut BufReader<TcpStream>,
+    writer: &mut BufWriter<TcpStream>,
+) -> Result<()> {
+    let mut buf = Vec::new();
+    while let Some(req) = read_request(reader)? {
+        let quiet = matches!(
+            req.opcode,
+            OP_GETQ
+                | OP_GETKQ
+                | OP_SETQ
+                | OP_ADDQ
+                | OP_REPLACEQ
+                | OP_DELETEQ
+                | OP_INCREMENTQ
+                | OP_DECREMENTQ
+                | OP_QUITQ
+        );
+        let resp = execute(store, &req);
+        // quiet的命令只返回错误,getq和getkq不返回key不存在
+        let skip = quiet
+            && (resp.status == STATUS_OK
+                || (resp.status == STATUS_KEY_NOT_FOUND
+                    && matches!(req.opcode, OP_GETQ | OP_GETKQ)));
+        if !skip {
+            buf.clear();
+            resp.encode(req.opcode, req.opaque, &mut buf);
+            writer.write_all(&buf)?;
+        }
+        if matches!(req.opcode, OP_QUIT | OP_QUITQ) {
+            break;
+        }
+        // pipeline中的请求全部处理完之后再一起发送
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+    writer.flush()
+}
+
+fn execute(store: &MemcachedStore, req: &Request) -> Response {
+    if req.key.len() > MAX_KEY_LENGTH {
+        return Response::error(STATUS_INVALID_ARGUMENTS, "Invalid arguments");
+    }
+    let result = match req.opcode {
+        OP_GET | OP_GETQ | OP_GETK | OP_GETKQ => get(store, req),
+        OP_SET | OP_SETQ | OP_ADD | OP_ADDQ | OP_REPLACE | OP_REPLACEQ => set(store, req),
+        OP_DELETE | OP_DELETEQ => delete(store, req),
+        OP_INCREMENT | OP_INCREMENTQ | OP_DECREMENT | OP_DECREMENTQ => incr(store, req),
+        OP_QUIT | OP_QUITQ | OP_NOOP => Ok(Response::ok()),
+        OP_VERSION => Ok(Response {
+            value: Bytes::from(env!("CARGO_PKG_VERSION")),
+            ..Response::ok()
+        }),
+        _ => Ok(Response::error(STATUS_UNKNOWN_COMMAND, "Unknown command")),
+    };
+    match result {
+        Ok(resp) => resp,
+        Err(Errors::ValueNotInteger) => Response::error(
+            STATUS_NON_NUMERIC,
+            "Non-numeric server-side value for incr or decr",
+        ),
+        Err(e) => Response::error(STATUS_INTERNAL_ERROR, &e.to_string()),
+    }
+}
+
+fn not_found() -> Response {
+    Response::error(STATUS_KEY_NOT_FOUND, "Not found")
+}
+
+fn invalid_arguments(req: &Request, extras_len: usize) -> bool {
+    req.extras.len() != extras_len || req.key.is_empty()
+}
+
+fn get(store: &MemcachedStore, req: &Request) -> crate::errors::Result<Response> {
+    if invalid_arguments(req, 0) || !req.value.is_empty() {
+        return Ok(Response::error(
+            STATUS_INVALID_ARGUMENTS,
+            "Invalid arguments",
+        ));
+    }
+    let item = match store.get(&req.key)? {
+        Some(item) => item,
+        None => return Ok(not_found()),
+    };
+    let key = match req.opcode {
+        OP_GETK | OP_GETKQ => req.key.clone(),
+        _ => Bytes::new(),
+    };
+    Ok(Response {
+        status: STATUS_OK,
+        cas: item.cas,
+        extras: item.flags.to_be_bytes().to_vec(),
+        key,
+        value: item.value,
+    })
+}
+
+// extras: | flags u32 | exptime u32 |
+fn set(store: &MemcachedStore, req: &Request) -> crate::errors::Result<Response> {
+    if invalid_arguments(req, 8) {
+        return Ok(Response::error(
+            STATUS_INVALID_ARGUMENTS,
+            "Invalid arguments",
+        ));
+    }
+    if req.value.len() > MAX_VALUE_SIZE {
+        return Ok(Response::error(STATUS_VALUE_TOO_LARGE, "Too large"));
+    }
+    let mut extras = req.extras.clone();
+    let flags = extras.get_u32();
+    let exptime = extras.get_u32() as i64;
+    let mode = match req.opcode {
+        OP_ADD | OP_ADDQ => StoreMode::Add,
+        OP_REPLACE | OP_REPLACEQ => StoreMode::Replace,
+        _ => StoreMode::Set,
+    };
+    let cas = match req.cas {
+        0 => None,
+        cas => Some(cas),
+    };
+    let resp = match store.store(mode, &req.key, req.value.clone(), flags, exptime, cas)? {
+        StoreResult::Stored(cas) => Response {
+            cas,
+            ..Response::ok()
+        },
+        StoreResult::Exists => Response::error(STATUS_KEY_EXISTS, "Data exists for key"),
+        // add的key已经存在,replace的key不存在
+        StoreResult::NotStored if mode == StoreMode::Add => {
+            Response::error(STATUS_KEY_EXISTS, "Data exists for key")
+        }
+        StoreResult::NotStored | StoreResult::NotFound => not_found(),
+        _ => Response::error(STATUS_NOT_STORED, "Not stored"),
+    };
+    Ok(resp)
+}
+
+fn delete(store: &MemcachedStore, req: &Request) -> crate::errors::Result<Response> {
+    if invalid_arguments(req, 0) || !req.value.is_empty() {
+        return Ok(Response::error(
+            STATUS_INVALID_ARGUMENTS,
+            "Invalid arguments",
+        ));
+    }
+    let cas = match req.cas {
+        0 => None,
+        cas => Some(cas),
+    };
+    let resp = match store.delete(&req.key, cas)? {
+        StoreResult::Deleted => Response::ok(),
+        StoreResult::Exists => Response::error(STATUS_KEY_EXISTS, "Data exists for key"),
+        _ => not_found(),
+    };
+    Ok(resp)
+}
+
+// extras: | delta u64 | initial u64 | exptime u32 |
+fn incr(store: &MemcachedStore, req: &Request) -> crate::errors::Result<Response> {
+    if invalid_arguments(req, 20) || !req.value.is_empty() {
+        return Ok(Response::error(
+            STATUS_INVALID_ARGUMENTS,
+            "Invalid arguments",
+        ));
+    }
+    let mut extras = req.extras.clone();
+    let delta = extras.get_u64();
+    let initial = extras.get_u64();
+    let initial = match extras.get_u32() {
+        NO_INITIAL_EXPTIME => None,
+        exptime => Some((initial, exptime as i64)),
+    };
+    let decr = matches!(req.opcode, OP_DECREMENT | OP_DECREMENTQ);
+    let resp = match store.incr(&req.key, delta, decr, initial)? {
+        Some((n, cas)) => Response {
+            cas,
+            value: Bytes::copy_from_slice(&n.to_be_bytes()),
+            ..Response::ok()
+        },
+        None => not_found(),
+    };
+    Ok(resp)
+}
@@ -0,0 +1,379 @@
+// 兼容memcached协议的前端,memcached的key加上前缀之后存放在engine中
+// value前面加上flags、过期时间和cas组成的头部一起存放
+// cas是每条记录自己的版本号,merge移动记录的位置之后也不会变化
+// 和redis层一样,前缀只是区分了key空间,直接通过Engine写入的Cfoo就是memcached的key foo
+mod binary;
+pub mod server;
+mod text;
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::{
+    db::Engine,
+    errors::{Errors, Result},
+    sequence::Sequence,
+};
+
+// item的key的前缀
+const ITEM_KEY_PREFIX: &[u8] = b"C";
+// 记录cas的预留上界的key,和redis的版本号一样放在V前缀下
+const CAS_KEY: &[u8] = b"Vcas";
+// 头部: | flags u32 | 过期时间 u64 | cas u64 |
+const ITEM_HEADER_SIZE: usize = 20;
+// exptime不超过30天时表示相对时间,否则是unix时间戳
+const MAX_RELATIVE_EXPTIME: i64 = 30 * 24 * 3600;
+// key的最大长度
+pub(crate) const MAX_KEY_LENGTH: usize = 250;
+// value的最大长度
+pub(crate) const MAX_VALUE_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item {
+    pub value: Bytes,
+    pub flags: u32,
+    pub cas: u64,
+    // 过期的unix时间,单位为秒,0表示不过期
+    pub expire_at: u64,
+}
+
+impl Item {
+    fn encode(&self) -> Bytes {
+        let mut buf = Vec::with_capacity(ITEM_HEADER_SIZE + self.value.len());
+        buf.put_u32(self.flags);
+        buf.put_u64(self.expire_at);
+        buf.put_u64(self.cas);
+        buf.extend_from_slice(&self.value);
+        Bytes::from(buf)
+    }
+
+    fn decode(mut buf: Bytes) -> Result<Self> {
+        if buf.len() < ITEM_HEADER_SIZE {
+            return Err(Errors::InvalidMemcachedItem);
+        }
+        let flags = buf.get_u32();
+        let expire_at = buf.get_u64();
+        let cas = buf.get_u64();
+        Ok(Item {
+            value: buf,
+            flags,
+            cas,
+            expire_at,
+        })
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expire_at != 0 && self.expire_at <= now
+    }
+}
+
+// 写入的方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StoreMode {
+    Set,
+    // key不存在时才写入
+    Add,
+    // key存在时才写入
+    Replace,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StoreResult {
+    // 写入成功,带上新的cas
+    Stored(u64),
+    NotStored,
+    Deleted,
+    // cas不一致
+    Exists,
+    NotFound,
+}
+
+pub struct MemcachedStore {
+    engine: Arc<Engine>,
+    // 分配cas的序号,重启之后也不会和之前的cas重复
+    cas: Sequence,
+}
+
+impl MemcachedStore {
+    pub fn new(engine: Arc<Engine>) -> Self {
+        MemcachedStore {
+            engine,
+            cas: Sequence::new(CAS_KEY),
+        }
+    }
+
+    fn next_cas(&self) -> Result<u64> {
+        self.cas.next(&self.engine)
+    }
+
+    // 读取原始的value和没有过期的item
+    fn get_raw(&self, key: &[u8]) -> Result<(Option<Bytes>, Option<Item>)> {
+        let raw = match self.engine.get(item_key(key)) {
+            Ok(raw) => raw,
+            Err(Errors::KeyNotFound) => return Ok((None, None)),
+            Err(e) => return Err(e),
+        };
+        let item = Item::decode(raw.clone())?;
+        match item.is_expired(now_secs()) {
+            true => Ok((Some(raw), None)),
+            false => Ok((Some(raw), Some(item))),
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Item>> {
+        Ok(self.get_raw(key)?.1)
+    }
+
+    // 写入一个item,cas不为None时只有当前的cas一致才写入
+    // 读取和写入之间有其他写入时通过compare_and_swap重试
+    pub fn store(
+        &self,
+        mode: StoreMode,
+        key: &[u8],
+        value: Bytes,
+        flags: u32,
+        exptime: i64,
+        cas: Option<u64>,
+    ) -> Result<StoreResult> {
+        loop {
+            let (raw, current) = self.get_raw(key)?;
+            match (&current, mode, cas) {
+                (None, _, Some(_)) => return Ok(StoreResult::NotFound),
+                (Some(current), _, Some(cas)) if current.cas != cas => {
+                    return Ok(StoreResult::Exists)
+                }
+                (Some(_), StoreMode::Add, _) | (None, StoreMode::Replace, _) => {
+                    return Ok(StoreResult::NotStored)
+                }
+                _ => (),
+            }
+            let item = Item {
+                value: value.clone(),
+                flags,
+                cas: self.next_cas()?,
+                expire_at: expire_at(exptime),
+            };
+            if self
+                .engine
+                .compare_and_swap(item_key(key), raw, Some(item.encode()))?
+            {
+                return Ok(StoreResult::Stored(item.cas));
+            }
+        }
+    }
+
+    // 删除key,cas不为None时只有当前的cas一致才删除
+    pub fn delete(&self, key: &[u8], cas: Option<u64>) -> Result<StoreResult> {
+        loop {
+            let (raw, current) = self.get_raw(key)?;
+            match (current, cas) {
+                (None, _) => return Ok(StoreResult::NotFound),
+                (Some(current), Some(cas)) if current.cas != cas => return Ok(StoreResult::Exists),
+                _ => (),
+            }
+            if self.engine.compare_and_swap(item_key(key), raw, None)? {
+                return Ok(StoreResult::Deleted);
+            }
+        }
+    }
+
+    // 把value当作十进制的u64加上delta,incr溢出时回绕,decr最小减到0
+    // key不存在时initial为None返回None,否则写入initial,返回新的值和cas
+    pub fn incr(
+        &self,
+        key: &[u8],
+        delta: u64,
+        decr: bool,
+        initial: Option<(u64, i64)>,
+    ) -> Result<Option<(u64, u64)>> {
+        loop {
+            let (raw, current) = self.get_raw(key)?;
+            let (item, n) = match (current, initial) {
+                (Some(current), _) => {
+                    let n = std::str::from_utf8(&current.value)
+                        .ok()
+                        .and_then(|s| s.trim_end().parse::<u64>().ok())
+                        .ok_or(Errors::ValueNotInteger)?;
+                    let n = match decr {
+                        true => n.saturating_sub(delta),
+                        false => n.wrapping_add(delta),
+                    };
+                    let item = Item {
+                        value: Bytes::from(n.to_string()),
+                        cas: self.next_cas()?,
+                        ..current
+                    };
+                    (item, n)
+                }
+                (None, Some((initial, exptime))) => {
+                    let item = Item {
+                        value: Bytes::from(initial.to_string()),
+                        flags: 0,
+                        cas: self.next_cas()?,
+                        expire_at: expire_at(exptime),
+                    };
+                    (item, initial)
+                }
+                (None, None) => return Ok(None),
+            };
+            if self
+                .engine
+                .compare_and_swap(item_key(key), raw, Some(item.encode()))?
+            {
+                return Ok(Some((n, item.cas)));
+            }
+        }
+    }
+}
+
+fn item_key(key: &[u8]) -> Bytes {
+    let mut item_key = Vec::with_capacity(ITEM_KEY_PREFIX.len() + key.len());
+    item_key.extend_from_slice(ITEM_KEY_PREFIX);
+    item_key.extend_from_slice(key);
+    Bytes::from(item_key)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+// 把memcached的exptime转换成过期的unix时间,负数表示立即过期
+fn expire_at(exptime: i64) -> u64 {
+    match exptime {
+        0 => 0,
+        n if n < 0 => 1,
+        n if n <= MAX_RELATIVE_EXPTIME => now_secs() + n as u64,
+        n => n as u64,
+    }
+}
+
+#[cfg(test)]
+mod test_memcached {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use super::{MemcachedStore, StoreMode, StoreResult};
+    use crate::{db::Engine, errors::Errors, options::Options};
+
+    #[test]
+    fn test_memcached_store() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-memcached");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let store = MemcachedStore::new(engine.clone());
+        let v = |s: &str| Bytes::from(s.to_string());
+
+        assert_eq!(
+            store
+                .store(StoreMode::Replace, b"k", v("v0"), 0, 0, None)
+                .unwrap(),
+            StoreResult::NotStored
+        );
+        let cas1 = match store
+            .store(StoreMode::Add, b"k", v("v1"), 7, 0, None)
+            .unwrap()
+        {
+            StoreResult::Stored(cas) => cas,
+            res => panic!("unexpected result {:?}", res),
+        };
+        assert_eq!(
+            store
+                .store(StoreMode::Add, b"k", v("v2"), 0, 0, None)
+                .unwrap(),
+            StoreResult::NotStored
+        );
+        let item = store.get(b"k").unwrap().unwrap();
+        assert_eq!((item.value, item.flags, item.cas), (v("v1"), 7, cas1));
+        // key带有前缀存放在engine中
+        assert!(engine.get(Bytes::from("Ck")).is_ok());
+        assert_eq!(
+            engine.get(Bytes::from("k")).err(),
+            Some(Errors::KeyNotFound)
+        );
+
+        // cas一致才写入,写入之后cas变化
+        let cas2 = match store
+            .store(StoreMode::Set, b"k", v("v3"), 1, 0, Some(cas1))
+            .unwrap()
+        {
+            StoreResult::Stored(cas) => cas,
+            res => panic!("unexpected result {:?}", res),
+        };
+        assert!(cas2 > cas1);
+        assert_eq!(
+            store
+                .store(StoreMode::Set, b"k", v("v4"), 1, 0, Some(cas1))
+                .unwrap(),
+            StoreResult::Exists
+        );
+        assert_eq!(
+            store
+                .store(StoreMode::Set, b"missing", v("v"), 1, 0, Some(cas1))
+                .unwrap(),
+            StoreResult::NotFound
+        );
+        assert_eq!(store.delete(b"k", Some(cas1)).unwrap(), StoreResult::Exists);
+        assert_eq!(
+            store.delete(b"k", Some(cas2)).unwrap(),
+            StoreResult::Deleted
+        );
+        assert_eq!(store.delete(b"k", None).unwrap(), StoreResult::NotFound);
+
+        // 负数的exptime立即过期,过期之后可以add
+        store
+            .store(StoreMode::Set, b"temp", v("v"), 0, -1, None)
+            .unwrap();
+        assert_eq!(store.get(b"temp").unwrap(), None);
+        assert!(matches!(
+            store.store(StoreMode::Add, b"temp", v("v"), 0, 100, None),
+            Ok(StoreResult::Stored(_))
+        ));
+        assert!(store.get(b"temp").unwrap().unwrap().expire_at > 0);
+
+        // incr和decr
+        assert_eq!(store.incr(b"n", 1, false, None).unwrap(), None);
+        store
+            .store(StoreMode::Set, b"n", v("10"), 3, 0, None)
+            .unwrap();
+        assert_eq!(store.incr(b"n", 5, false, None).unwrap().unwrap().0, 15);
+        assert_eq!(store.incr(b"n", 100, true, None).unwrap().unwrap().0, 0);
+        assert_eq!(store.get(b"n").unwrap().unwrap().flags, 3);
+        assert_eq!(
+            store
+                .incr(b"m", 1, false, Some((42, 0)))
+                .unwrap()
+                .unwrap()
+                .0,
+            42
+        );
+        store
+            .store(StoreMode::Set, b"s", v("abc"), 0, 0, None)
+            .unwrap();
+        assert_eq!(
+            store.incr(b"s", 1, false, None).err(),
+            Some(Errors::ValueNotInteger)
+        );
+
+        // merge之后cas不变
+        let cas = store.get(b"n").unwrap().unwrap().cas;
+        engine.merge().unwrap();
+        drop(store);
+        drop(engine);
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let store = MemcachedStore::new(engine);
+        assert_eq!(store.get(b"n").unwrap().unwrap().cas, cas);
+        // 重启之后新的cas比之前的都大
+        assert!(matches!(
+            store.store(StoreMode::Set, b"n", v("1"), 0, 0, None),
+            Ok(StoreResult::Stored(new_cas)) if new_cas > cas
+        ));
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,276 @@
+use std::io::{BufRead, BufReader, BufWriter};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+
+use log::error;
+
+use super::{binary, text, MemcachedStore};
+use crate::{errors::Result, tcp_server::TcpServer};
+
+// 兼容memcached文本协议和二进制协议的服务
+// 根据连接上的第一个字节区分协议,二进制协议的请求以0x80开头
+pub struct MemcachedServer {
+    server: TcpServer,
+}
+
+impl MemcachedServer {
+    pub fn start<A: ToSocketAddrs>(store: Arc<MemcachedStore>, addr: A) -> Result<Self> {
+        let server = TcpServer::start(addr, move |stream| {
+            if let Err(e) = handle_connection(&store, stream) {
+                error!("failed to handle memcached connection: {}", e);
+            }
+        })?;
+        Ok(MemcachedServer { server })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+fn handle_connection(store: &MemcachedStore, stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let first = match reader.fill_buf()?.first() {
+        Some(first) => *first,
+        None => return Ok(()),
+    };
+    match first {
+        binary::REQUEST_MAGIC => binary::serve(store, &mut reader, &mut writer),
+        _ => text::serve(store, &mut reader, &mut writer),
+    }
+}
+
+#[cfg(test)]
+mod test_memcached_server {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use bytes::{Buf, BufMut};
+
+    use super::MemcachedServer;
+    use crate::{db::Engine, memcached::MemcachedStore, options::Options};
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(server: &MemcachedServer) -> Client {
+            let stream = TcpStream::connect(server.local_addr()).unwrap();
+            Client {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+            }
+        }
+
+        // 发送文本命令,读取指定行数的回复
+        fn text(&mut self, request: &str, lines: usize) -> String {
+            self.writer.write_all(request.as_bytes()).unwrap();
+            let mut reply = String::new();
+            for _ in 0..lines {
+                self.reader.read_line(&mut reply).unwrap();
+            }
+            reply
+        }
+
+        // 发送二进制请求,返回状态码、cas、extras和value
+        fn binary(
+            &mut self,
+            opcode: u8,
+            cas: u64,
+            extras: &[u8],
+            key: &str,
+            value: &str,
+        ) -> Response {
+            self.writer
+                .write_all(&request(opcode, cas, extras, key, value))
+                .unwrap();
+
+            let mut header = [0; 24];
+            self.reader.read_exact(&mut header).unwrap();
+            let mut header = &header[..];
+            assert_eq!(header.get_u8(), 0x81);
+            assert_eq!(header.get_u8(), opcode);
+            let key_len = header.get_u16() as usize;
+            let extras_len = header.get_u8() as usize;
+            header.advance(1);
+            let status = header.get_u16();
+            let body_len = header.get_u32() as usize;
+            assert_eq!(header.get_u32(), 0xdead_beef);
+            let cas = header.get_u64();
+            let mut body = vec![0; body_len];
+            self.reader.read_exact(&mut body).unwrap();
+            Response {
+                status,
+                cas,
+                extras: body[..extras_len].to_vec(),
+                value: body[extras_len + key_len..].to_vec(),
+            }
+        }
+    }
+
+    fn request(opcode: u8, cas: u64, extras: &[u8], key: &str, value: &str) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.put_u8(0x80);
+        buf.put_u8(opcode);
+        buf.put_u16(key.len() as u16);
+        buf.put_u8(extras.len() as u8);
+        buf.put_u8(0);
+        buf.put_u16(0);
+        buf.put_u32((extras.len() + key.len() + value.len()) as u32);
+        buf.put_u32(0xdead_beef);
+        buf.put_u64(cas);
+        buf.extend_from_slice(extras);
+        buf.extend_from_slice(key.as_bytes());
+        buf.extend_from_slice(value.as_bytes());
+        buf
+    }
+
+    struct Response {
+        status: u16,
+        cas: u64,
+        extras: Vec<u8>,
+        value: Vec<u8>,
+    }
+
+    fn set_extras(flags: u32, exptime: u32) -> Vec<u8> {
+        let mut extras = Vec::new();
+        extras.put_u32(flags);
+        extras.put_u32(exptime);
+        extras
+    }
+
+    #[test]
+    fn test_memcached_server() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-memcached-server");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let store = Arc::new(MemcachedStore::new(engine));
+        let server = MemcachedServer::start(store, "127.0.0.1:0").unwrap();
+
+        // 文本协议
+        let mut client = Client::connect(&server);
+        assert_eq!(client.text("set k1 5 0 2\r\nv1\r\n", 1), "STORED\r\n");
+        assert_eq!(client.text("add k1 0 0 2\r\nv2\r\n", 1), "NOT_STORED\r\n");
+        assert_eq!(
+            client.text("get k1 missing\r\n", 3),
+            "VALUE k1 5 2\r\nv1\r\nEND\r\n"
+        );
+        let reply = client.text("gets k1\r\n", 3);
+        let cas: u64 = reply
+            .lines()
+            .next()
+            .unwrap()
+            .rsplit(' ')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(
+            client.text(&format!("cas k1 0 0 2 {}\r\nv3\r\n", cas + 1), 1),
+            "EXISTS\r\n"
+        );
+        assert_eq!(
+            client.text(&format!("cas k1 0 0 2 {}\r\nv3\r\n", cas), 1),
+            "STORED\r\n"
+        );
+        assert_eq!(
+            client.text("set k2 0 0 2\r\nabcd\r\n", 1),
+            "CLIENT_ERROR bad data chunk\r\n"
+        );
+        // noreply的命令没有回复
+        assert_eq!(
+            client.text("set n 0 0 2 noreply\r\n10\r\nincr n 5\r\n", 1),
+            "15\r\n"
+        );
+        assert_eq!(client.text("decr n 100\r\n", 1), "0\r\n");
+        assert_eq!(
+            client.text("incr k1 1\r\n", 1),
+            "CLIENT_ERROR cannot increment or decrement non-numeric value\r\n"
+        );
+        assert_eq!(client.text("delete k1\r\n", 1), "DELETED\r\n");
+        assert_eq!(client.text("delete k1\r\n", 1), "NOT_FOUND\r\n");
+        assert_eq!(client.text("unknown\r\n", 1), "ERROR\r\n");
+        assert_eq!(client.text("noreply\r\n", 1), "ERROR\r\n");
+        assert_eq!(
+            client.text("get\r\n", 1),
+            "CLIENT_ERROR bad command line format\r\n"
+        );
+
+        // 数据块太大时回复错误并关闭连接
+        let mut big = Client::connect(&server);
+        assert_eq!(
+            big.text(&format!("set big 0 0 {}\r\n", usize::MAX), 1),
+            "SERVER_ERROR object too large for cache\r\n"
+        );
+        assert_eq!(big.reader.read_line(&mut String::new()).unwrap(), 0);
+
+        // 二进制协议
+        let mut client = Client::connect(&server);
+        let resp = client.binary(0x01, 0, &set_extras(7, 0), "b1", "hello");
+        assert_eq!(resp.status, 0);
+        let cas = resp.cas;
+        let resp = client.binary(0x00, 0, &[], "b1", "");
+        assert_eq!((resp.status, resp.cas), (0, cas));
+        assert_eq!(resp.extras, 7u32.to_be_bytes());
+        assert_eq!(resp.value, b"hello");
+        assert_eq!(
+            client.binary(0x02, 0, &set_extras(0, 0), "b1", "x").status,
+            2
+        );
+        assert_eq!(
+            client.binary(0x03, 0, &set_extras(0, 0), "b2", "x").status,
+            1
+        );
+        assert_eq!(
+            client
+                .binary(0x01, cas + 1, &set_extras(0, 0), "b1", "x")
+                .status,
+            2
+        );
+        assert_eq!(
+            client
+                .binary(0x01, cas, &set_extras(0, 0), "b1", "x")
+                .status,
+            0
+        );
+
+        // incr在key不存在时写入初始值
+        let mut extras = Vec::new();
+        extras.put_u64(3);
+        extras.put_u64(40);
+        extras.put_u32(0);
+        let resp = client.binary(0x05, 0, &extras, "counter", "");
+        assert_eq!((resp.status, resp.value), (0, 40u64.to_be_bytes().to_vec()));
+        let resp = client.binary(0x05, 0, &extras, "counter", "");
+        assert_eq!(resp.value, 43u64.to_be_bytes());
+        assert_eq!(client.binary(0x05, 0, &extras, "b1", "").status, 6);
+
+        // getq不返回key不存在,noop之前的请求都已经处理
+        let getq = request(0x09, 0, &[], "missing", "");
+        client.writer.write_all(&getq).unwrap();
+        assert_eq!(client.binary(0x0a, 0, &[], "", "").status, 0);
+        assert_eq!(client.binary(0x04, 0, &[], "b1", "").status, 0);
+        assert_eq!(client.binary(0x04, 0, &[], "b1", "").status, 1);
+        assert_eq!(
+            client.binary(0x0b, 0, &[], "", "").value,
+            env!("CARGO_PKG_VERSION").as_bytes()
+        );
+        assert_eq!(client.binary(0x42, 0, &[], "", "").status, 0x81);
+
+        // 在文本协议中可以读到二进制协议写入的数据
+        let mut client = Client::connect(&server);
+        assert_eq!(
+            client.text("get counter\r\n", 3),
+            "VALUE counter 0 2\r\n43\r\nEND\r\n"
+        );
+
+        drop(server);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,207 @@
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::net::TcpStream;
+
+use bytes::Bytes;
+
+use super::{MemcachedStore, StoreMode, StoreResult, MAX_KEY_LENGTH, MAX_VALUE_SIZE};
+use crate::errors::Errors;
+
+// 命令行的最大长度
+const MAX_LINE_LENGTH: u64 = 2048;
+
+// 处理文本协议的连接,直到连接关闭或者收到quit
+pub(crate) fn serve(
+    store: &MemcachedStore,
+    reader: &mut BufReader<TcpStream>,
+    writer: &mut BufWriter<TcpStream>,
+) -> Result<()> {
+    loop {
+        let mut line = Vec::new();
+        if reader
+            .by_ref()
+            .take(MAX_LINE_LENGTH)
+            .read_until(b'\n', &mut line)?
+            == 0
+        {
+            return writer.flush();
+        }
+        if !line.ends_with(b"\n") {
+            writer.write_all(b"CLIENT_ERROR line too long\r\n")?;
+            return writer.flush();
+        }
+        let line = String::from_utf8_lossy(&line).to_string();
+        let tokens: Vec<&str> = line.split_ascii_whitespace().collect();
+        let (reply, noreply) = match tokens.first() {
+            Some(&"quit") => return writer.flush(),
+            Some(_) => match execute(store, reader, &tokens) {
+                Ok(reply) => reply,
+                // 数据块太大时不再读取剩下的数据,回复错误之后关闭连接
+                Err(e) if e.kind() == ErrorKind::InvalidData => {
+                    writer.write_all(format!("SERVER_ERROR {}\r\n", e).as_bytes())?;
+                    return writer.flush();
+                }
+                Err(e) => return Err(e),
+            },
+            // 忽略空行
+            None => (Vec::new(), true),
+        };
+        if !noreply {
+            writer.write_all(&reply)?;
+        }
+        // pipeline中的请求全部处理完之后再一起发送
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+}
+
+fn line(s: &str) -> Vec<u8> {
+    format!("{}\r\n", s).into_bytes()
+}
+
+fn bad_format() -> Vec<u8> {
+    line("CLIENT_ERROR bad command line format")
+}
+
+fn server_error(e: Errors) -> Vec<u8> {
+    line(&format!("SERVER_ERROR {}", e))
+}
+
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LENGTH
+}
+
+// 执行一个命令,返回回复的内容和是否不需要回复
+fn execute(
+    store: &MemcachedStore,
+    reader: &mut BufReader<TcpStream>,
+    tokens: &[&str],
+) -> Result<(Vec<u8>, bool)> {
+    // 只有命令之后的最后一个参数才是noreply
+    let noreply = tokens.len() > 1 && tokens.last() == Some(&"noreply");
+    let args = match noreply {
+        true => &tokens[1..tokens.len() - 1],
+        false => &tokens[1..],
+    };
+    let reply = match tokens[0] {
+        "get" | "gets" if !args.is_empty() => {
+            let mut reply = Vec::new();
+            for key in args {
+                if !valid_key(key) {
+                    return Ok((bad_format(), false));
+                }
+                let item = match store.get(key.as_bytes()) {
+                    Ok(Some(item)) => item,
+                    Ok(None) => continue,
+                    Err(e) => return Ok((server_error(e), false)),
+                };
+                let header = match tokens[0] {
+                    "gets" => format!(
+                        "VALUE {} {} {} {}",
+                        key,
+                        item.flags,
+                        item.value.len(),
+                        item.cas
+                    ),
+                    _ => format!("VALUE {} {} {}", key, item.flags, item.value.len()),
+                };
+                reply.extend_from_slice(&line(&header));
+                reply.extend_from_slice(&item.value);
+                reply.extend_from_slice(b"\r\n");
+            }
+            reply.extend_from_slice(b"END\r\n");
+            return Ok((reply, false));
+        }
+        "set" | "add" | "replace" | "cas" => store_command(store, reader, tokens[0], args)?,
+        "delete" if args.len() == 1 || (args.len() == 2 && args[1] == "0") => {
+            match store.delete(args[0].as_bytes(), None) {
+                Ok(StoreResult::Deleted) => line("DELETED"),
+                Ok(_) => line("NOT_FOUND"),
+                Err(e) => server_error(e),
+            }
+        }
+        "incr" | "decr" if args.len() == 2 => {
+            let delta = match args[1].parse::<u64>() {
+                Ok(delta) => delta,
+                Err(_) => {
+                    return Ok((line("CLIENT_ERROR invalid numeric delta argument"), noreply))
+                }
+            };
+            match store.incr(args[0].as_bytes(), delta, tokens[0] == "decr", None) {
+                Ok(Some((n, _))) => line(&n.to_string()),
+                Ok(None) => line("NOT_FOUND"),
+                Err(Errors::ValueNotInteger) => {
+                    line("CLIENT_ERROR cannot increment or decrement non-numeric value")
+                }
+                Err(e) => server_error(e),
+            }
+        }
+        "version" => line(&format!("VERSION {}", env!("CARGO_PKG_VERSION"))),
+        "get" | "gets" | "delete" | "incr" | "decr" => bad_format(),
+        _ => line("ERROR"),
+    };
+    Ok((reply, noreply))
+}
+
+// <command> <key> <flags> <exptime> <bytes> [<cas unique>] [noreply]\r\n<data>\r\n
+fn store_command(
+    store: &MemcachedStore,
+    reader: &mut BufReader<TcpStream>,
+    command: &str,
+    args: &[&str],
+) -> Result<Vec<u8>> {
+    let expected_args = if command == "cas" { 5 } else { 4 };
+    if args.len() != expected_args {
+        return Ok(bad_format());
+    }
+    let (flags, exptime, len) = match (
+        args[1].parse::<u32>(),
+        args[2].parse::<i64>(),
+        args[3].parse::<usize>(),
+    ) {
+        (Ok(flags), Ok(exptime), Ok(len)) => (flags, exptime, len),
+        _ => return Ok(bad_format()),
+    };
+    let cas = match command {
+        "cas" => match args[4].parse::<u64>() {
+            Ok(cas) => Some(cas),
+            Err(_) => return Ok(bad_format()),
+        },
+        _ => None,
+    };
+    if len > MAX_VALUE_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "object too large for cache",
+        ));
+    }
+    let mut data = vec![0; len + 2];
+    reader.read_exact(&mut data)?;
+    if !data.ends_with(b"\r\n") {
+        return Ok(line("CLIENT_ERROR bad data chunk"));
+    }
+    if !valid_key(args[0]) {
+        return Ok(bad_format());
+    }
+    data.truncate(len);
+    let mode = match command {
+        "add" => StoreMode::Add,
+        "replace" => StoreMode::Replace,
+        _ => StoreMode::Set,
+    };
+    let reply = match store.store(
+        mode,
+        args[0].as_bytes(),
+        Bytes::from(data),
+        flags,
+        exptime,
+        cas,
+    ) {
+        Ok(StoreResult::Stored(_)) => line("STORED"),
+        Ok(StoreResult::Exists) => line("EXISTS"),
+        Ok(StoreResult::NotFound) => line("NOT_FOUND"),
+        Ok(_) => line("NOT_STORED"),
+        Err(e) => server_error(e),
+    };
+    Ok(reply)
+}
@@ -1,15 +1,14 @@
 use crate::{
     data::{
         data_file::MERGE_FINISHED_FILE_NAME,
-        log_record::{LogRecord, LogRecordPos},
+        log_record::{LogRecord, LogRecordPos, LogRecordType},
     },
     db::NO_TXN_SEQ_NO,
     write_batch::WriteBatch,
 };
 use bytes::Bytes;
 use log::error;
-use prost::encoding::decode_varint;
-use std::{cell::RefCell, path::PathBuf};
+use std::path::PathBuf;
 
 use crate::{
     data::{data_file::DataFile, log_record::ReadLogRecord},
@@ -21,16 +20,24 @@ use crate::{
 const MERGE_NAME: &str = "merge";
 const MERGE_FNISHED_KEY: &[u8] = "merge-finished".as_bytes();
 impl Engine {
-    pub fn merge(&mut self) -> Result<()> {
-        // 使用RefCell实现可变与不可变的同时使用
-        let db = RefCell::new(self);
-        let res = db.borrow();
-        let lock = res.merge_lock.try_lock();
+    pub fn merge(&self) -> Result<()> {
+        // 副本的文件和leader保持一致,由leader来merge
+        self.check_writable()?;
+        let lock = self.merge_lock.try_lock();
         if lock.is_none() {
             return Err(Errors::MergeInProcess);
         }
 
-        let merge_dir_path = get_merge_dirpath(db.borrow().options.dir_path.clone());
+        let mut merge_files = self.get_merge_files()?;
+        // 还有cdc reader没有读完的文件需要保留,只merge所有reader都已经读过的文件
+        if let Some(retain_file_id) = self.cdc_retain_file_id() {
+            merge_files.retain(|file| file.get_file_id() < retain_file_id);
+        }
+        if merge_files.is_empty() {
+            return Ok(());
+        }
+
+        let merge_dir_path = get_merge_dirpath(self.options.dir_path.clone());
         // 可能之前已经进行过merge,那么这里就需要将merge的老的目录删除掉(它可能是成功或者未成功的)
         if merge_dir_path.is_dir() {
             // 已经存在这个目录就需要将其删除掉
@@ -38,17 +45,18 @@ impl Engine {
         }
         // 创建merge的目录
         if let Err(e) = std::fs::create_dir_all(merge_dir_path.clone()) {
-            error!("failed to create merge dir path");
+            error!("failed to create merge dir path: {}", e);
             return Err(Errors::DirPathCreateFailed);
         }
         // 创建临时的merge-db实例
         let mut merge_options = Options::default();
         merge_options.dir_path = merge_dir_path.clone();
-        merge_options.file_size_threshlod = db.borrow().options.file_size_threshlod;
+        merge_options.file_size_threshlod = self.options.file_size_threshlod;
+        // merge之后的文件不再用于按照时间恢复,不需要时间标记
+        merge_options.timestamp_marker_interval = None;
         let merge_db = Engine::open(merge_options)?;
-        let merge_files = db.borrow_mut().get_merge_files()?;
-        // 打开hint_file文件
-        let hint_file = DataFile::new_hint_file(db.borrow().options.dir_path.clone()).unwrap();
+        // 打开hint_file文件,和merge之后的数据文件一起移动到数据目录
+        let hint_file = DataFile::new_hint_file(merge_dir_path.clone())?;
         // 接下来就开始一次处理每一个old_file进行
         for file in merge_files.iter() {
             let mut offset = 0;
@@ -63,64 +71,55 @@ impl Engine {
                         return Err(e);
                     }
                 };
+                // 范围删除覆盖的key在索引里已经不存在了,参与merge的数据不会再被它影响
+                // 时间标记不对应任何key,也不需要保留
+                if logrecord.log_type == LogRecordType::RANGEDELETED
+                    || logrecord.log_type == LogRecordType::TIMESTAMP
+                {
+                    offset += size as u64;
+                    continue;
+                }
                 // 在writeBatch之后我们的key的编码发生了改变,这里我们需要解析一下
-                let (key, _) = db.borrow().parse_key(logrecord.key.clone());
+                let (key, _) = self.parse_key(logrecord.key.clone());
                 // 看在index里面这个key的pos是否对的上
-                // 更新offset
-                if let Some(pos) = db.borrow().indexer.get(key) {
-                    // 如果确认是有效key,就写入
+                if let Some(pos) = self.indexer.get(key.clone()) {
+                    // 如果确认是有效key,就去掉事务信息后写入
                     if pos.file_id == file.get_file_id() && pos.offset == offset {
-                        let real_key = logrecord.key.clone();
                         logrecord.key =
-                            WriteBatch::encode_key_seqno(Bytes::from(real_key), NO_TXN_SEQ_NO);
+                            WriteBatch::encode_key_seqno(Bytes::from(key.clone()), NO_TXN_SEQ_NO);
+                        let merged_pos = merge_db.append_log(&mut logrecord)?;
+                        // 写hint file
+                        hint_file.write_hint_file_record(key, merged_pos)?;
                     }
-                    merge_db.append_log(&mut logrecord)?;
-                    // 写hint file
-                    let real_key = logrecord.key.clone();
-                    hint_file
-                        .write_hint_file_record(real_key, pos.clone())
-                        .unwrap();
                 }
+                // 更新offset
                 offset += size as u64;
             }
         }
-        merge_db.sync().unwrap();
-        hint_file.sync().unwrap();
+        merge_db.sync()?;
+        hint_file.sync()?;
         let no_merge_fileid = merge_files.last().unwrap().get_file_id() + 1;
-        // 然后去拿没有参与到merge的文件
-        let merge_finished_file =
-            DataFile::new_finished_file(db.borrow().options.dir_path.clone())?;
+        // 最后写入merge完成的标记,记录没有参与到merge的第一个文件
+        let merge_finished_file = DataFile::new_finished_file(merge_dir_path.clone())?;
         let log_record = LogRecord {
             key: MERGE_FNISHED_KEY.to_vec(),
             value: no_merge_fileid.to_string().into_bytes(),
             log_type: crate::data::log_record::LogRecordType::NORMAL,
         };
         let encode_record = log_record.encode();
-        merge_finished_file.write(&encode_record).unwrap();
-        merge_finished_file.sync().unwrap();
+        merge_finished_file.write(&encode_record)?;
+        merge_finished_file.sync()?;
         Ok(())
     }
 
-    fn get_merge_files(&mut self) -> Result<Vec<DataFile>> {
+    fn get_merge_files(&self) -> Result<Vec<DataFile>> {
         let mut res_merge_datafiles = Vec::new();
-        // 需要进行merge的文件id
-        let mut merge_files_ids = Vec::new();
-        // 拿到old file和active file
-        let mut old_files = self.old_files.write();
-        for file_id in old_files.keys() {
-            merge_files_ids.push(*file_id)
-        }
-        // 拿到active file
+        // 先切换活跃文件,老的active file也参与merge
+        // 和写入一样先拿活跃文件的锁再拿old files的锁
         let mut active_file = self.data_file.write();
-        let active_id = active_file.get_file_id();
-        merge_files_ids.push(active_id);
-        let old_active_file = DataFile::new(self.options.dir_path.clone(), active_id)?;
-        old_files.insert(active_id, old_active_file);
-        self.max_file_id = active_id + 1;
-        // 创建一个新的active file
-        let new_active_file = DataFile::new(self.options.dir_path.clone(), active_id + 1)?;
-        *active_file = new_active_file;
-
+        self.rotate_active_file(&mut active_file)?;
+        // 需要进行merge的文件id
+        let mut merge_files_ids: Vec<u32> = self.old_files.read().keys().copied().collect();
         merge_files_ids.sort();
 
         for file_id in merge_files_ids {
@@ -131,6 +130,10 @@ impl Engine {
 
     pub fn load_merge_files(dir_path: PathBuf) -> Result<()> {
         let merge_path = get_merge_dirpath(dir_path.clone());
+        // 没有进行过merge,直接返回
+        if !merge_path.is_dir() {
+            return Ok(());
+        }
         // 拿到merge_path下的所有文件
         let read_dir = std::fs::read_dir(merge_path.clone()).unwrap();
         let mut merge_finished = false;
@@ -156,10 +159,7 @@ impl Engine {
         }
         // merge完成,读取merge_finished_file看
         // 哪些文件被merge了
-        let merge_file = DataFile::new_finished_file(merge_path.clone())?;
-        let read_logrecord = merge_file.read_log_record(0)?;
-        let v = String::from_utf8(read_logrecord.logrecord.value).unwrap();
-        let no_merge_file_id = v.parse::<u32>().unwrap();
+        let no_merge_file_id = Engine::no_merged_file_id(merge_path.clone())?;
         // 将已经被merge过的文件给删除掉
         for file_id in 0..no_merge_file_id {
             let file_path = DataFile::get_file_name(dir_path.clone(), file_id);
@@ -168,7 +168,8 @@ impl Engine {
             }
         }
 
-        // 移动merge的文件
+        // 移动merge的文件,merge完成的标记最后移动
+        merge_names.push(MERGE_FINISHED_FILE_NAME.into());
         for file_name in merge_names {
             let ori_fil_path = merge_path.join(file_name.clone());
             let target_file_name = dir_path.join(file_name.clone());
@@ -179,6 +180,18 @@ impl Engine {
         Ok(())
     }
 
+    // 读取数据目录中merge完成的标记,返回没有参与merge的第一个文件id,没有merge过时返回0
+    pub(crate) fn no_merged_file_id(dir_path: PathBuf) -> Result<u32> {
+        if !dir_path.join(MERGE_FINISHED_FILE_NAME).is_file() {
+            return Ok(0);
+        }
+        let merge_finished_file =
+            DataFile::open_read_only(dir_path.join(MERGE_FINISHED_FILE_NAME), 0)?;
+        let read_logrecord = merge_finished_file.read_log_record(0)?;
+        let v = String::from_utf8(read_logrecord.logrecord.value).unwrap();
+        Ok(v.parse::<u32>().unwrap())
+    }
+
     pub fn load_hint_file(&self) -> Result<()> {
         let hint_file_path = self
             .options
@@ -187,7 +200,7 @@ impl Engine {
         if !hint_file_path.is_file() {
             return Ok(());
         }
-        let hint_file = DataFile::new_hint_file(self.options.dir_path.clone())?;
+        let hint_file = DataFile::open_read_only(hint_file_path, 0)?;
         let mut offset = 0;
         loop {
             let logrecord_res: Result<ReadLogRecord> = hint_file.read_log_record(offset);
@@ -208,9 +221,91 @@ impl Engine {
     }
 }
 
-fn get_merge_dirpath(dir_path: PathBuf) -> PathBuf {
+pub(crate) fn get_merge_dirpath(dir_path: PathBuf) -> PathBuf {
     let file_name = dir_path.file_name().unwrap();
     let merge_name = format!("{}-{}", file_name.to_str().unwrap(), MERGE_NAME);
     let parent = dir_path.parent().unwrap();
     parent.to_path_buf().join(merge_name)
 }
+
+#[cfg(test)]
+mod test_merge {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{
+        db::Engine,
+        options::{Options, WriteBatchOptions},
+        util::rand_kv::{get_test_key, get_test_value},
+    };
+
+    #[test]
+    fn test_merge() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-merge");
+        opts.file_size_threshlod = 32 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        // 覆盖写、删除和批量提交产生无效数据
+        for i in 0..500 {
+            engine
+                .put(get_test_key(i), Bytes::from("new value"))
+                .unwrap();
+        }
+        for i in 500..600 {
+            engine.delete(get_test_key(i)).unwrap();
+        }
+        engine
+            .delete_prefix(Bytes::from("bitcask-rs-key-00000070"))
+            .unwrap();
+        let write_batch = engine
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        write_batch
+            .put(get_test_key(2000), get_test_value(2000))
+            .unwrap();
+        write_batch.commit().unwrap();
+        let file_count = engine.old_files.read().len() + 1;
+        engine.merge().unwrap();
+        // merge之后可以继续写入
+        engine
+            .put(get_test_key(3000), get_test_value(3000))
+            .unwrap();
+        engine.close().unwrap();
+        drop(engine);
+
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(engine2.old_files.read().len() + 1 < file_count);
+        let check = |engine: &Engine| {
+            for i in 0..500 {
+                assert_eq!(engine.get(get_test_key(i)).unwrap(), "new value");
+            }
+            for i in 500..600 {
+                assert!(engine.get(get_test_key(i)).is_err());
+            }
+            for i in 600..1000 {
+                match (700..710).contains(&i) {
+                    true => assert!(engine.get(get_test_key(i)).is_err()),
+                    false => assert_eq!(engine.get(get_test_key(i)).unwrap(), get_test_value(i)),
+                }
+            }
+            assert_eq!(
+                engine.get(get_test_key(2000)).unwrap(),
+                get_test_value(2000)
+            );
+            assert_eq!(
+                engine.get(get_test_key(3000)).unwrap(),
+                get_test_value(3000)
+            );
+        };
+        check(&engine2);
+        // 再次重启依然可以从hint文件加载索引
+        drop(engine2);
+        let engine3 = Engine::open(opts.clone()).expect("failed to open engine");
+        check(&engine3);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
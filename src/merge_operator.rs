@@ -0,0 +1,161 @@
+use bytes::Bytes;
+
+use crate::{
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+// 用户自定义的合并操作,比如追加到列表、取最大值等
+// operand不会单独写入日志,engine每次都在写锁内读取当前值并写入合并后的完整结果,
+// 所以不要求满足结合律,但是每次合并的代价和当前value的大小成正比,
+// 并且期间会阻塞其他所有的写入,不适合频繁追加很大的value
+pub trait MergeOperator: Send + Sync {
+    // existing为None表示key当前不存在
+    fn merge(&self, key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> Vec<u8>;
+}
+
+impl Engine {
+    // 使用Options中配置的merge_operator把operand原子地合并到key当前的值上,返回合并后的值
+    pub fn merge_value(&self, key: Bytes, operand: Bytes) -> Result<Bytes> {
+        let merge_operator = match &self.options.merge_operator {
+            Some(merge_operator) => merge_operator.clone(),
+            None => return Err(Errors::MergeOperatorNotSet),
+        };
+        self.read_modify_write(key.clone(), |existing| {
+            Ok(merge_operator.merge(&key, existing, &operand))
+        })
+    }
+
+    // 原子地给key对应的整数加上delta,key不存在时当作0,返回加完之后的值
+    // 整数以十进制字符串的形式存储
+    pub fn increment(&self, key: Bytes, delta: i64) -> Result<i64> {
+        let value = self.read_modify_write(key, |existing| {
+            let current = match existing {
+                Some(value) => parse_integer(value)?,
+                None => 0,
+            };
+            match current.checked_add(delta) {
+                Some(n) => Ok(n.to_string().into_bytes()),
+                None => Err(Errors::IntegerOverflow),
+            }
+        })?;
+        parse_integer(&value)
+    }
+
+    // 在写锁内读取key当前的值,计算出新值后写入
+    fn read_modify_write<F>(&self, key: Bytes, f: F) -> Result<Bytes>
+    where
+        F: FnOnce(Option<&[u8]>) -> Result<Vec<u8>>,
+    {
+        if key.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        self.check_writable()?;
+        let _guard = self.conditional_write_lock.write();
+        let existing = self.get_current_value(&key)?;
+        let value = Bytes::from(f(existing.as_deref())?);
+        self.put_record(key, value.clone())?;
+        Ok(value)
+    }
+}
+
+fn parse_integer(value: &[u8]) -> Result<i64> {
+    match std::str::from_utf8(value).map(|s| s.parse::<i64>()) {
+        Ok(Ok(n)) => Ok(n),
+        _ => Err(Errors::ValueNotInteger),
+    }
+}
+
+#[cfg(test)]
+mod test_merge_operator {
+    use std::{path::PathBuf, sync::Arc};
+
+    use bytes::Bytes;
+
+    use super::MergeOperator;
+    use crate::{db::Engine, errors::Errors, options::Options};
+
+    // 把operand追加到以逗号分隔的列表后面
+    struct AppendOperator;
+
+    impl MergeOperator for AppendOperator {
+        fn merge(&self, _key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> Vec<u8> {
+            match existing {
+                Some(existing) => [existing, b",", operand].concat(),
+                None => operand.to_vec(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_value() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-merge-value");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        // 没有配置merge_operator
+        let res = engine.merge_value(Bytes::from("list"), Bytes::from("a"));
+        assert_eq!(res.err().unwrap(), Errors::MergeOperatorNotSet);
+        drop(engine);
+
+        opts.merge_operator = Some(Arc::new(AppendOperator));
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let res = engine.merge_value(Bytes::from("list"), Bytes::from("a"));
+        assert_eq!(res.unwrap(), "a");
+        let mut handles = Vec::new();
+        for _ in 0..4 {
+            let engine = engine.clone();
+            handles.push(std::thread::spawn(move || {
+                for _ in 0..50 {
+                    engine
+                        .merge_value(Bytes::from("list"), Bytes::from("b"))
+                        .unwrap();
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let value = engine.get(Bytes::from("list")).unwrap();
+        assert_eq!(value.split(|b| *b == b',').count(), 201);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_increment() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-increment");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        assert_eq!(engine.increment(Bytes::from("counter"), 5).unwrap(), 5);
+        assert_eq!(engine.increment(Bytes::from("counter"), -2).unwrap(), 3);
+
+        let mut handles = Vec::new();
+        for _ in 0..8 {
+            let engine = engine.clone();
+            handles.push(std::thread::spawn(move || {
+                for _ in 0..100 {
+                    engine.increment(Bytes::from("counter"), 1).unwrap();
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(engine.get(Bytes::from("counter")).unwrap(), "803");
+
+        // 不是整数的值
+        engine
+            .put(Bytes::from("name"), Bytes::from("bitcask"))
+            .unwrap();
+        let res = engine.increment(Bytes::from("name"), 1);
+        assert_eq!(res.err().unwrap(), Errors::ValueNotInteger);
+        // 溢出
+        engine
+            .put(Bytes::from("max"), Bytes::from(i64::MAX.to_string()))
+            .unwrap();
+        let res = engine.increment(Bytes::from("max"), 1);
+        assert_eq!(res.err().unwrap(), Errors::IntegerOverflow);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
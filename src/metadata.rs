@@ -0,0 +1,107 @@
+use bytes::Bytes;
+
+use crate::{
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+// key的元数据,全部来自内存索引,不需要读取value
+// 目前的日志格式没有记录写入时间和TTL
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyMetadata {
+    pub file_id: u32,
+    pub offset: u64,
+    pub value_size: u32,
+}
+
+impl Engine {
+    // 判断key是否存在,只查询内存索引
+    pub fn contains(&self, key: Bytes) -> Result<bool> {
+        if key.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        Ok(self.indexer.get(key.to_vec()).is_some())
+    }
+
+    // 获取key的元数据,只查询内存索引
+    pub fn metadata(&self, key: Bytes) -> Result<KeyMetadata> {
+        if key.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        match self.indexer.get(key.to_vec()) {
+            Some(pos) => Ok(KeyMetadata {
+                file_id: pos.file_id,
+                offset: pos.offset,
+                value_size: pos.value_size,
+            }),
+            None => Err(Errors::KeyNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_metadata {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{
+        db::Engine,
+        errors::Errors,
+        options::{Options, WriteBatchOptions},
+    };
+
+    #[test]
+    fn test_contains_and_metadata() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-metadata");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let big_value = Bytes::from(vec![b'x'; 1024 * 1024]);
+        engine.put(Bytes::from("blob"), big_value).unwrap();
+        engine.put(Bytes::from("empty"), Bytes::new()).unwrap();
+        let write_batch = engine
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        write_batch
+            .put(Bytes::from("batch"), Bytes::from("value"))
+            .unwrap();
+        write_batch.commit().unwrap();
+
+        assert!(engine.contains(Bytes::from("blob")).unwrap());
+        assert!(!engine.contains(Bytes::from("not existed key")).unwrap());
+        assert_eq!(
+            engine.contains(Bytes::new()).err().unwrap(),
+            Errors::KeyEmptyErr
+        );
+
+        let check = |engine: &Engine| {
+            let metadata = engine.metadata(Bytes::from("blob")).unwrap();
+            assert_eq!(metadata.file_id, 0);
+            // 第一条记录之前是时间标记
+            assert!(metadata.offset > 0);
+            assert_eq!(metadata.value_size, 1024 * 1024);
+            let metadata = engine.metadata(Bytes::from("empty")).unwrap();
+            assert!(metadata.offset > 0);
+            assert_eq!(metadata.value_size, 0);
+            let metadata = engine.metadata(Bytes::from("batch")).unwrap();
+            assert_eq!(metadata.value_size, 5);
+            assert_eq!(
+                engine
+                    .metadata(Bytes::from("not existed key"))
+                    .err()
+                    .unwrap(),
+                Errors::KeyNotFound
+            );
+        };
+        check(&engine);
+        // 重启后从数据文件中重建的索引也有value的长度
+        engine.close().unwrap();
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        check(&engine2);
+
+        engine2.delete(Bytes::from("blob")).unwrap();
+        assert!(!engine2.contains(Bytes::from("blob")).unwrap());
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
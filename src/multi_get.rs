@@ -0,0 +1,164 @@
+use bytes::Bytes;
+
+use crate::{
+    blob::BlobFiles,
+    data::{
+        data_file::DataFile,
+        log_record::{BlobPos, LogRecordPos, LogRecordType},
+    },
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+// 同一个文件中起始位置相差在这个范围内的记录合并成一次读取
+const MULTI_GET_COALESCE_SIZE: u64 = 64 * 1024;
+// 合并读取时在最后一条记录之后多读的字节数,记录超出这个范围时单独读取
+const MULTI_GET_TAIL_SIZE: u64 = 4 * 1024;
+
+impl Engine {
+    // 批量读取,返回的结果和keys一一对应,key不存在时为Ok(None)
+    // 只拿一次文件的读锁,并按照(file_id, offset)排序后合并相邻的读取
+    pub fn multi_get(&self, keys: &[Bytes]) -> Vec<Result<Option<Bytes>>> {
+        let mut results = Vec::with_capacity(keys.len());
+        let mut lookups = Vec::new();
+        for (idx, key) in keys.iter().enumerate() {
+            if key.is_empty() {
+                results.push(Err(Errors::KeyEmptyErr));
+                continue;
+            }
+            if let Some(pos) = self.indexer.get(key.to_vec()) {
+                lookups.push((pos, idx));
+            }
+            results.push(Ok(None));
+        }
+        lookups.sort_by_key(|(pos, _)| (pos.file_id, pos.offset));
+
+        let active_file_read_guard = self.data_file.read();
+        let old_files_read_guard = self.old_files.read();
+        let mut start = 0;
+        while start < lookups.len() {
+            let file_id = lookups[start].0.file_id;
+            let region_start = lookups[start].0.offset;
+            let mut end = start + 1;
+            while end < lookups.len()
+                && lookups[end].0.file_id == file_id
+                && lookups[end].0.offset - region_start <= MULTI_GET_COALESCE_SIZE
+            {
+                end += 1;
+            }
+            let data_file = match active_file_read_guard.get_file_id() == file_id {
+                true => Some(&*active_file_read_guard),
+                false => old_files_read_guard.get(&file_id),
+            };
+            match data_file {
+                Some(data_file) => read_region(
+                    data_file,
+                    &self.blob_files,
+                    &lookups[start..end],
+                    &mut results,
+                ),
+                None => {
+                    for (_, idx) in lookups[start..end].iter() {
+                        results[*idx] = Err(Errors::KeyNotFoundInDataFile);
+                    }
+                }
+            }
+            start = end;
+        }
+        results
+    }
+}
+
+// 一次读出lookups覆盖的文件区域,再从中解析出每条记录
+fn read_region(
+    data_file: &DataFile,
+    blob_files: &BlobFiles,
+    lookups: &[(LogRecordPos, usize)],
+    results: &mut [Result<Option<Bytes>>],
+) {
+    let region_start = lookups[0].0.offset;
+    let region_len = lookups[lookups.len() - 1].0.offset - region_start + MULTI_GET_TAIL_SIZE;
+    let buf = match data_file.read_bytes(region_start, region_len as usize) {
+        Ok(buf) => buf,
+        Err(e) => {
+            for (_, idx) in lookups.iter() {
+                results[*idx] = Err(e.clone());
+            }
+            return;
+        }
+    };
+    for (pos, idx) in lookups.iter() {
+        let relative = (pos.offset - region_start) as usize;
+        let read_log_record = match DataFile::decode_log_record(&buf[relative.min(buf.len())..]) {
+            Some(res) => res,
+            // 记录超出了读取的区域,单独读取
+            None => data_file.read_log_record(pos.offset),
+        };
+        results[*idx] = match read_log_record {
+            Ok(read_log_record) => match read_log_record.logrecord.log_type {
+                LogRecordType::DELETED => Ok(None),
+                LogRecordType::BLOBINDEX => blob_files
+                    .read(&BlobPos::decode(&read_log_record.logrecord.value))
+                    .map(|value| Some(Bytes::from(value))),
+                _ => Ok(Some(Bytes::from(read_log_record.logrecord.value))),
+            },
+            Err(e) => Err(e),
+        };
+    }
+}
+
+#[cfg(test)]
+mod test_multi_get {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{
+        db::Engine,
+        errors::Errors,
+        options::Options,
+        util::rand_kv::{get_test_key, get_test_value},
+    };
+
+    #[test]
+    fn test_multi_get() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-multi-get");
+        opts.file_size_threshlod = 16 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        // 超过合并读取范围的大value
+        let big_value = Bytes::from(vec![b'x'; 10 * 1024]);
+        engine.put(get_test_key(2000), big_value.clone()).unwrap();
+        engine.delete(get_test_key(500)).unwrap();
+
+        let mut keys = vec![
+            get_test_key(999),
+            Bytes::new(),
+            get_test_key(2000),
+            get_test_key(500),
+            Bytes::from("not existed key"),
+            get_test_key(0),
+        ];
+        for i in (1..1000).step_by(7) {
+            keys.push(get_test_key(i));
+        }
+        let results = engine.multi_get(&keys);
+        assert_eq!(results.len(), keys.len());
+        assert_eq!(results[0].clone().unwrap().unwrap(), get_test_value(999));
+        assert_eq!(results[1].clone().err().unwrap(), Errors::KeyEmptyErr);
+        assert_eq!(results[2].clone().unwrap().unwrap(), big_value);
+        assert!(results[3].clone().unwrap().is_none());
+        assert!(results[4].clone().unwrap().is_none());
+        assert_eq!(results[5].clone().unwrap().unwrap(), get_test_value(0));
+        for (key, res) in keys.iter().zip(results.iter()).skip(6) {
+            assert_eq!(
+                res.clone().unwrap().unwrap(),
+                engine.get(key.clone()).unwrap()
+            );
+        }
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
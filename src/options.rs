@@ -1,13 +1,25 @@
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::errors::Errors;
+pub use crate::index::IndexIteratorOptions;
+use crate::merge_operator::MergeOperator;
 
 #[derive(Clone)]
 pub struct Options {
     pub dir_path: PathBuf,
     pub file_size_threshlod: u64,
-    pub sync: bool,
+    pub sync_policy: SyncPolicy,
     pub index_type: IndexType,
+    // merge_value使用的合并操作
+    pub merge_operator: Option<Arc<dyn MergeOperator>>,
+    // value不小于这个大小时单独写入blob文件,None表示不分离
+    pub blob_value_threshold: Option<u64>,
+    // 只读打开,不会创建和修改数据目录中的任何文件,多个进程可以同时只读打开同一个目录
+    pub read_only: bool,
+    // 写入时间标记的最小间隔,按照时间恢复时单条写入的精度就是这个间隔,None表示不写时间标记
+    pub timestamp_marker_interval: Option<Duration>,
 }
 
 impl Options {
@@ -21,6 +33,18 @@ impl Options {
         if self.file_size_threshlod <= 0 {
             return Some(Errors::InvalidDataFileSizeOption);
         }
+        // 3.检测持久化策略配置是否合理
+        match self.sync_policy {
+            SyncPolicy::EveryNBytes(0) => return Some(Errors::InvalidSyncPolicyOption),
+            SyncPolicy::Interval(interval) if interval.is_zero() => {
+                return Some(Errors::InvalidSyncPolicyOption)
+            }
+            _ => (),
+        }
+        // 4.检测blob分离的阈值是否合理
+        if self.blob_value_threshold == Some(0) {
+            return Some(Errors::InvalidBlobValueThreshold);
+        }
         None
     }
 }
@@ -31,19 +55,38 @@ pub enum IndexType {
     SkipList,
 }
 
+// 数据持久化策略,决定了宕机时最多会丢失多少数据
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncPolicy {
+    // 每次写入都持久化
+    Always,
+    // 累计写入超过n字节后持久化
+    EveryNBytes(u64),
+    // 后台线程每隔一段时间持久化
+    Interval(Duration),
+    // 交给操作系统决定
+    Never,
+}
+
 impl Default for Options {
     fn default() -> Self {
         Options {
             dir_path: std::env::temp_dir(),
             file_size_threshlod: 256 * 1024 * 1024,
-            sync: false,
+            sync_policy: SyncPolicy::Never,
             index_type: IndexType::Btree,
+            merge_operator: None,
+            blob_value_threshold: None,
+            read_only: false,
+            timestamp_marker_interval: Some(Duration::from_secs(1)),
         }
     }
 }
 
 pub struct WriteBatchOptions {
     pub batch_max_rows: u32,
+    // 暂存的key和value的总字节数上限
+    pub batch_max_bytes: u64,
     pub sync_writes: bool,
 }
 
@@ -51,6 +94,7 @@ impl Default for WriteBatchOptions {
     fn default() -> Self {
         Self {
             batch_max_rows: 10000,
+            batch_max_bytes: 64 * 1024 * 1024,
             sync_writes: true,
         }
     }
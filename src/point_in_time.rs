@@ -0,0 +1,418 @@
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::error;
+
+use crate::{
+    backup::{
+        clear_dir, copy_file, copy_prefix, create_empty_dir, load_manifest, sibling_dir, sync_dir,
+        BACKUP_MANIFEST_FILE_NAME,
+    },
+    data::{
+        data_file::DataFile,
+        log_record::{LogRecordPos, LogRecordType},
+    },
+    db::{replay_data_files, Engine, ReplayEntry},
+    errors::{Errors, Result},
+    options::Options,
+};
+
+// 恢复到的时间点
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestorePoint {
+    // 恢复到这个批量提交为止,包含这个批量提交
+    SeqNo(usize),
+    // 恢复到这个时间为止,截断在第一条晚于这个时间的时间标记或批量提交处
+    // 批量提交按照提交时间精确截断,单条写入只能按照时间标记截断,
+    // 时间标记最多每隔Options::timestamp_marker_interval写入一次,
+    // 所以可能保留这个时间之后不超过一个间隔的单条写入
+    Timestamp(SystemTime),
+}
+
+impl Engine {
+    // 用备份加上之后的数据文件恢复到某个时间点,恢复到options.dir_path并打开
+    // log_dir为备份之后的数据文件所在的目录(通常就是原来的数据目录),为None时只用备份中的数据
+    // 先在旁边的临时目录中恢复,成功之后再rename成options.dir_path,失败时不会留下恢复了一半的目录
+    pub fn restore_to_point(
+        backup_dir: PathBuf,
+        log_dir: Option<PathBuf>,
+        point: RestorePoint,
+        options: Options,
+    ) -> Result<Engine> {
+        let dir_path = options.dir_path.clone();
+        create_empty_dir(&dir_path)?;
+        let tmp_dir = sibling_dir(&dir_path, "restore")?;
+        // 清理上次中途失败留下的临时目录
+        clear_dir(&tmp_dir)?;
+        create_empty_dir(&tmp_dir)?;
+        if let Err(e) = restore_to_dir(&backup_dir, log_dir.as_deref(), point, &tmp_dir) {
+            clear_dir(&tmp_dir)?;
+            return Err(e);
+        }
+        let res = fs::remove_dir(&dir_path).and_then(|_| fs::rename(&tmp_dir, &dir_path));
+        if let Err(e) = res {
+            error!("failed to rename restore dir: {}", e);
+            return Err(Errors::FailWriteDataToFile);
+        }
+        if let Some(parent) = dir_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            sync_dir(parent)?;
+        }
+        Engine::open(options)
+    }
+}
+
+// 把备份和之后的数据文件拷贝到dir_path,并截断到恢复的时间点
+fn restore_to_dir(
+    backup_dir: &Path,
+    log_dir: Option<&Path>,
+    point: RestorePoint,
+    dir_path: &Path,
+) -> Result<()> {
+    let read_dir = match fs::read_dir(backup_dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) => {
+            error!("failed to read backup dir: {}", e);
+            return Err(Errors::DirPathReadFailed);
+        }
+    };
+    for entry in read_dir.flatten() {
+        if entry.path().is_file() && entry.file_name() != BACKUP_MANIFEST_FILE_NAME {
+            copy_file(&entry.path(), &dir_path.join(entry.file_name()))?;
+        }
+    }
+    if let Some(log_dir) = log_dir {
+        copy_later_files(backup_dir, log_dir, dir_path)?;
+    }
+
+    // 和加载索引一样回放日志,找到需要截断的位置
+    let data_files = DataFile::load_data_files(dir_path.to_path_buf())?;
+    let no_merged_file_id = Engine::no_merged_file_id(dir_path.to_path_buf())?;
+    // merge之后批量提交的seq_no会从没有参与merge的文件中重新计算,可能和merge之前的重复
+    if no_merged_file_id > 0 && matches!(point, RestorePoint::SeqNo(_)) {
+        return Err(Errors::RestoreSeqNoAfterMerge);
+    }
+    let files: Vec<&DataFile> = data_files.iter().collect();
+    let mut cut: Option<LogRecordPos> = None;
+    replay_data_files(&files, no_merged_file_id, |entry| match point {
+        RestorePoint::SeqNo(seq_no) => {
+            if entry.seq_no != seq_no {
+                return true;
+            }
+            cut = Some(entry.end);
+            false
+        }
+        RestorePoint::Timestamp(timestamp) => match entry_timestamp(&entry) {
+            Some(time) if time > to_millis(timestamp) => {
+                cut = Some(entry.start);
+                false
+            }
+            _ => true,
+        },
+    })?;
+    drop(files);
+    drop(data_files);
+    match (cut, point) {
+        (Some(cut), _) => truncate_log(dir_path, cut)?,
+        (None, RestorePoint::SeqNo(_)) => return Err(Errors::RestorePointNotFound),
+        // 所有的批量提交都在这个时间之前
+        (None, RestorePoint::Timestamp(_)) => (),
+    }
+    sync_dir(dir_path)
+}
+
+// 时间标记和批量提交的TXN_FIN记录了写入时间,其它记录没有时间
+// 旧版本写入的日志没有时间标记,TXN_FIN的value也为空,这些记录当作时间未知,不会在它们处截断
+fn entry_timestamp(entry: &ReplayEntry) -> Option<u64> {
+    let value = match entry.records.first() {
+        Some((logrecord, _)) if logrecord.log_type == LogRecordType::TIMESTAMP => &logrecord.value,
+        _ => &entry.txn_fin_value,
+    };
+    std::str::from_utf8(value).ok()?.parse::<u64>().ok()
+}
+
+fn to_millis(timestamp: SystemTime) -> u64 {
+    timestamp
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+// 拷贝备份之后新写入的数据文件和blob文件
+// 备份中最大的数据文件只拷贝到了当时的持久化位置,用log_dir中的文件替换它
+// log_dir中最新的文件可能正在被写入,只拷贝到最后一条完整的日志
+fn copy_later_files(backup_dir: &Path, log_dir: &Path, dir_path: &Path) -> Result<()> {
+    // 之后发生过merge的话,log_dir中的文件已经不能接在备份之后了
+    if let Some(manifest) = load_manifest(backup_dir)? {
+        if manifest.merge_generation != Engine::no_merged_file_id(log_dir.to_path_buf())? {
+            return Err(Errors::RestoreLogMismatch);
+        }
+    }
+    let backup_files = DataFile::load_data_files(backup_dir.to_path_buf())?;
+    let max_backup_file_id = backup_files.last().map(|file| file.get_file_id());
+    for data_file in DataFile::load_read_only_data_files(log_dir.to_path_buf())? {
+        let file_id = data_file.get_file_id();
+        if max_backup_file_id.is_some_and(|max_file_id| file_id < max_file_id) {
+            continue;
+        }
+        copy_prefix(
+            &DataFile::get_file_name(log_dir.to_path_buf(), file_id),
+            &DataFile::get_file_name(dir_path.to_path_buf(), file_id),
+            data_file.get_wtite_offset(),
+        )?;
+    }
+    for blob_file in DataFile::load_blob_files(log_dir.to_path_buf())? {
+        let file_id = blob_file.get_file_id();
+        let dst = DataFile::get_blob_file_name(dir_path.to_path_buf(), file_id);
+        let dst_size = fs::metadata(&dst).map_or(0, |metadata| metadata.len());
+        if dst_size < blob_file.get_wtite_offset() {
+            copy_prefix(
+                &DataFile::get_blob_file_name(log_dir.to_path_buf(), file_id),
+                &dst,
+                blob_file.get_wtite_offset(),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// 截断到cut的位置,之后的数据文件全部删除
+fn truncate_log(dir_path: &Path, cut: LogRecordPos) -> Result<()> {
+    let file_name = DataFile::get_file_name(dir_path.to_path_buf(), cut.file_id);
+    let res = OpenOptions::new()
+        .write(true)
+        .open(file_name)
+        .and_then(|file| file.set_len(cut.offset).and_then(|_| file.sync_all()));
+    if let Err(e) = res {
+        error!("failed to truncate data file: {}", e);
+        return Err(Errors::FailWriteDataToFile);
+    }
+    for data_file in DataFile::load_data_files(dir_path.to_path_buf())? {
+        let file_id = data_file.get_file_id();
+        if file_id > cut.file_id
+            && fs::remove_file(DataFile::get_file_name(dir_path.to_path_buf(), file_id)).is_err()
+        {
+            return Err(Errors::FailWriteDataToFile);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_point_in_time {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use bytes::Bytes;
+
+    use super::RestorePoint;
+    use crate::{
+        data::{
+            data_file::DataFile,
+            log_record::{LogRecord, LogRecordType},
+        },
+        db::{replay_data_files, Engine},
+        errors::Errors,
+        options::{Options, WriteBatchOptions},
+        util::rand_kv::{get_test_key, get_test_value},
+    };
+
+    fn write_batch(engine: &Engine, start: i32, end: i32, value: &str) {
+        let write_batch = engine
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        for i in start..end {
+            write_batch
+                .put(get_test_key(i), Bytes::from(value.to_string()))
+                .unwrap();
+        }
+        write_batch.commit().unwrap();
+    }
+
+    #[test]
+    fn test_restore_to_point() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-pitr");
+        opts.file_size_threshlod = 32 * 1024;
+        opts.timestamp_marker_interval = Some(Duration::from_millis(50));
+        let backup_dir = PathBuf::from("/tmp/bitcask-rs-pitr-backup");
+        let restore_dir = PathBuf::from("/tmp/bitcask-rs-pitr-restore");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..500 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        write_batch(&engine, 0, 100, "batch1");
+        engine.backup(backup_dir.clone()).unwrap();
+
+        // 备份之后的写入在原来的数据目录中
+        for i in 500..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        write_batch(&engine, 100, 200, "batch2");
+        let seq_no = engine.seq_no.load(std::sync::atomic::Ordering::SeqCst);
+        // 超过时间标记的间隔之后,单条写入之前会有新的时间标记
+        std::thread::sleep(Duration::from_millis(60));
+        let good_time = SystemTime::now();
+        std::thread::sleep(Duration::from_millis(60));
+        // 错误的写入,单条写入也要按照时间截断
+        engine
+            .put(get_test_key(2000), get_test_value(2000))
+            .unwrap();
+        write_batch(&engine, 0, 1000, "garbage");
+        engine.sync().unwrap();
+
+        // 时间标记按照间隔写入,不会每组写入都有一条
+        let data_files = DataFile::load_data_files(opts.dir_path.clone()).unwrap();
+        let files: Vec<&DataFile> = data_files.iter().collect();
+        let mut markers = 0;
+        replay_data_files(&files, 0, |entry| {
+            if let Some((logrecord, _)) = entry.records.first() {
+                if logrecord.log_type == LogRecordType::TIMESTAMP {
+                    markers += 1;
+                }
+            }
+            true
+        })
+        .unwrap();
+        assert!(markers >= 2 && markers < 100);
+
+        let check = |restored: &Engine| {
+            for i in 0..100 {
+                assert_eq!(restored.get(get_test_key(i)).unwrap(), "batch1");
+            }
+            for i in 100..200 {
+                assert_eq!(restored.get(get_test_key(i)).unwrap(), "batch2");
+            }
+            for i in 200..1000 {
+                assert_eq!(restored.get(get_test_key(i)).unwrap(), get_test_value(i));
+            }
+            assert!(restored.get(get_test_key(2000)).is_err());
+        };
+        let mut restore_opts = opts.clone();
+        restore_opts.dir_path = restore_dir.clone();
+        for point in [
+            RestorePoint::SeqNo(seq_no),
+            RestorePoint::Timestamp(good_time),
+        ] {
+            let restored = Engine::restore_to_point(
+                backup_dir.clone(),
+                Some(opts.dir_path.clone()),
+                point,
+                restore_opts.clone(),
+            )
+            .unwrap();
+            check(&restored);
+            // 恢复出来的数据库可以继续写入,重启后数据不变
+            restored
+                .put(get_test_key(3000), get_test_value(3000))
+                .unwrap();
+            drop(restored);
+            let restored = Engine::open(restore_opts.clone()).unwrap();
+            check(&restored);
+            assert_eq!(
+                restored.get(get_test_key(3000)).unwrap(),
+                get_test_value(3000)
+            );
+            std::fs::remove_dir_all(restore_dir.clone()).expect("failed to remove path");
+        }
+
+        // 只用备份恢复时找不到之后的seq_no
+        assert_eq!(
+            Engine::restore_to_point(
+                backup_dir.clone(),
+                None,
+                RestorePoint::SeqNo(seq_no),
+                restore_opts.clone(),
+            )
+            .err()
+            .unwrap(),
+            Errors::RestorePointNotFound
+        );
+        // 恢复失败时不会留下恢复了一半的数据
+        assert!(std::fs::read_dir(&restore_dir).unwrap().next().is_none());
+
+        // 活跃文件末尾写了一半的日志不会被拷贝
+        let active_file_id = engine.data_file.read().get_file_id();
+        let torn = LogRecord {
+            key: get_test_key(4000).to_vec(),
+            value: get_test_value(4000).to_vec(),
+            log_type: LogRecordType::NORMAL,
+        }
+        .encode();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(DataFile::get_file_name(
+                opts.dir_path.clone(),
+                active_file_id,
+            ))
+            .unwrap();
+        file.write_all(&torn[..torn.len() / 2]).unwrap();
+        let restored = Engine::restore_to_point(
+            backup_dir.clone(),
+            Some(opts.dir_path.clone()),
+            RestorePoint::Timestamp(SystemTime::now()),
+            restore_opts.clone(),
+        )
+        .unwrap();
+        for i in 0..1000 {
+            assert_eq!(restored.get(get_test_key(i)).unwrap(), "garbage");
+        }
+        assert!(restored.get(get_test_key(4000)).is_err());
+        drop(restored);
+        drop(engine);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(backup_dir).expect("failed to remove path");
+        std::fs::remove_dir_all(restore_dir).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_restore_seq_no_after_merge() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-pitr-merge");
+        opts.file_size_threshlod = 32 * 1024;
+        let backup_dir = PathBuf::from("/tmp/bitcask-rs-pitr-merge-backup");
+        let restore_dir = PathBuf::from("/tmp/bitcask-rs-pitr-merge-restore");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        write_batch(&engine, 0, 100, "batch1");
+        let seq_no = engine.seq_no.load(std::sync::atomic::Ordering::SeqCst);
+        for i in 0..500 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine.merge().unwrap();
+        engine.close().unwrap();
+        drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine.backup(backup_dir.clone()).unwrap();
+
+        // merge之后seq_no不再唯一,只能按照时间恢复
+        let mut restore_opts = opts.clone();
+        restore_opts.dir_path = restore_dir.clone();
+        assert_eq!(
+            Engine::restore_to_point(
+                backup_dir.clone(),
+                None,
+                RestorePoint::SeqNo(seq_no),
+                restore_opts.clone(),
+            )
+            .err()
+            .unwrap(),
+            Errors::RestoreSeqNoAfterMerge
+        );
+        let restored = Engine::restore_to_point(
+            backup_dir.clone(),
+            None,
+            RestorePoint::Timestamp(SystemTime::now()),
+            restore_opts.clone(),
+        )
+        .unwrap();
+        for i in 0..500 {
+            assert_eq!(restored.get(get_test_key(i)).unwrap(), get_test_value(i));
+        }
+        drop(restored);
+        drop(engine);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(backup_dir).expect("failed to remove path");
+        std::fs::remove_dir_all(restore_dir).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,150 @@
+use bytes::Bytes;
+
+use super::{RedisStore, RedisType, MEMBER_KEY_PREFIX};
+use crate::{
+    errors::{Errors, Result},
+    options::WriteBatchOptions,
+};
+
+impl RedisStore {
+    // 写入hash的多个字段,返回新增的字段个数
+    pub fn hset(&self, key: &[u8], fvs: &[(Bytes, Bytes)]) -> Result<i64> {
+        if key.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        let _guard = self.lock_key(key);
+        let mut hash = self.get_or_new_collection(key, RedisType::Hash)?;
+        let write_batch = self.engine.new_write_batch(WriteBatchOptions::default())?;
+        let mut added = 0;
+        for (field, value) in fvs {
+            let field_key = hash.member_key(key, field);
+            match write_batch.get(field_key.clone()) {
+                Ok(_) => (),
+                Err(Errors::KeyNotFound) => added += 1,
+                Err(e) => return Err(e),
+            }
+            write_batch.put(field_key, value.clone())?;
+        }
+        hash.size += added;
+        self.put_collection(&write_batch, key, &hash)?;
+        write_batch.commit()?;
+        Ok(added as i64)
+    }
+
+    pub fn hget(&self, key: &[u8], field: &[u8]) -> Result<Option<Bytes>> {
+        let hash = match self.get_collection(key, RedisType::Hash)? {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
+        match self.engine.get(hash.member_key(key, field)) {
+            Ok(value) => Ok(Some(value)),
+            Err(Errors::KeyNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // 删除hash的多个字段,返回删除的字段个数
+    pub fn hdel(&self, key: &[u8], fields: &[Bytes]) -> Result<i64> {
+        let _guard = self.lock_key(key);
+        let mut hash = match self.get_collection(key, RedisType::Hash)? {
+            Some(hash) => hash,
+            None => return Ok(0),
+        };
+        let write_batch = self.engine.new_write_batch(WriteBatchOptions::default())?;
+        let mut removed = 0;
+        for field in fields {
+            let field_key = hash.member_key(key, field);
+            match write_batch.get(field_key.clone()) {
+                Ok(_) => removed += 1,
+                Err(Errors::KeyNotFound) => continue,
+                Err(e) => return Err(e),
+            }
+            write_batch.delete(field_key)?;
+        }
+        hash.size -= removed;
+        self.put_collection(&write_batch, key, &hash)?;
+        write_batch.commit()?;
+        Ok(removed as i64)
+    }
+
+    // 按照字段的顺序返回所有的字段和值
+    pub fn hgetall(&self, key: &[u8]) -> Result<Vec<(Bytes, Bytes)>> {
+        match self.get_collection(key, RedisType::Hash)? {
+            Some(hash) => self.scan_members(&hash.member_prefix(MEMBER_KEY_PREFIX, key)),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_redis_hash {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use crate::{
+        db::Engine,
+        errors::Errors,
+        options::Options,
+        redis::{RedisStore, SetOptions},
+    };
+
+    #[test]
+    fn test_redis_hash() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-redis-hash");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let store = RedisStore::new(engine);
+        let fv = |f: &str, v: &str| (Bytes::from(f.to_string()), Bytes::from(v.to_string()));
+
+        assert_eq!(
+            store
+                .hset(
+                    b"user:1",
+                    &[fv("name", "tom"), fv("age", "10"), fv("age", "11")]
+                )
+                .unwrap(),
+            2
+        );
+        assert_eq!(store.hset(b"user:1", &[fv("age", "12")]).unwrap(), 0);
+        assert_eq!(
+            store.hget(b"user:1", b"age").unwrap(),
+            Some(Bytes::from("12"))
+        );
+        assert_eq!(store.hget(b"user:1", b"missing").unwrap(), None);
+        assert_eq!(
+            store.hgetall(b"user:1").unwrap(),
+            vec![fv("age", "12"), fv("name", "tom")]
+        );
+        // 同名的其他key的字段不会混在一起
+        store.hset(b"user:10", &[fv("name", "jerry")]).unwrap();
+        assert_eq!(store.hgetall(b"user:1").unwrap().len(), 2);
+
+        assert_eq!(
+            store
+                .hdel(b"user:1", &[Bytes::from("age"), Bytes::from("missing")])
+                .unwrap(),
+            1
+        );
+        assert_eq!(store.hgetall(b"user:1").unwrap(), vec![fv("name", "tom")]);
+        // 删除最后一个字段之后key也被删除
+        store.hdel(b"user:1", &[Bytes::from("name")]).unwrap();
+        assert_eq!(store.key_type(b"user:1").unwrap(), None);
+
+        // 删除之后重新创建,之前的字段不可见
+        store.hset(b"user:10", &[fv("age", "3")]).unwrap();
+        assert_eq!(store.del(&[Bytes::from("user:10")]).unwrap(), 1);
+        store.hset(b"user:10", &[fv("city", "x")]).unwrap();
+        assert_eq!(store.hgetall(b"user:10").unwrap(), vec![fv("city", "x")]);
+
+        // 类型不一致
+        store
+            .set(Bytes::from("s"), Bytes::from("v"), SetOptions::default())
+            .unwrap();
+        assert_eq!(store.hget(b"s", b"f").err(), Some(Errors::WrongType));
+        assert_eq!(store.get(b"user:10").err(), Some(Errors::WrongType));
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,169 @@
+use bytes::Bytes;
+
+use super::{Collection, RedisStore, RedisType};
+use crate::{
+    errors::{Errors, Result},
+    options::WriteBatchOptions,
+};
+
+// list元素的key,用大端的位置作为元素的名字,按照key的顺序就是list的顺序
+fn element_key(list: &Collection, key: &[u8], index: u64) -> Bytes {
+    list.member_key(key, &index.to_be_bytes())
+}
+
+impl RedisStore {
+    // 依次插入到头部,返回插入之后的长度
+    pub fn lpush(&self, key: &[u8], values: &[Bytes]) -> Result<i64> {
+        self.push(key, values, true)
+    }
+
+    // 依次插入到尾部,返回插入之后的长度
+    pub fn rpush(&self, key: &[u8], values: &[Bytes]) -> Result<i64> {
+        self.push(key, values, false)
+    }
+
+    fn push(&self, key: &[u8], values: &[Bytes], left: bool) -> Result<i64> {
+        if key.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        let _guard = self.lock_key(key);
+        let mut list = self.get_or_new_collection(key, RedisType::List)?;
+        let write_batch = self.engine.new_write_batch(WriteBatchOptions::default())?;
+        for value in values {
+            let index = match left {
+                true => {
+                    list.head -= 1;
+                    list.head
+                }
+                false => {
+                    list.tail += 1;
+                    list.tail - 1
+                }
+            };
+            write_batch.put(element_key(&list, key, index), value.clone())?;
+        }
+        list.size += values.len() as u64;
+        self.put_collection(&write_batch, key, &list)?;
+        write_batch.commit()?;
+        Ok(list.size as i64)
+    }
+
+    // 弹出头部的元素,list为空时返回None
+    pub fn lpop(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        self.pop(key, true)
+    }
+
+    // 弹出尾部的元素,list为空时返回None
+    pub fn rpop(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        self.pop(key, false)
+    }
+
+    fn pop(&self, key: &[u8], left: bool) -> Result<Option<Bytes>> {
+        let _guard = self.lock_key(key);
+        let mut list = match self.get_collection(key, RedisType::List)? {
+            Some(list) => list,
+            None => return Ok(None),
+        };
+        let index = match left {
+            true => {
+                list.head += 1;
+                list.head - 1
+            }
+            false => {
+                list.tail -= 1;
+                list.tail
+            }
+        };
+        let element_key = element_key(&list, key, index);
+        let value = self.engine.get(element_key.clone())?;
+        let write_batch = self.engine.new_write_batch(WriteBatchOptions::default())?;
+        write_batch.delete(element_key)?;
+        list.size -= 1;
+        self.put_collection(&write_batch, key, &list)?;
+        write_batch.commit()?;
+        Ok(Some(value))
+    }
+
+    // 返回[start, stop]范围内的元素,负数表示从尾部开始的位置,-1是最后一个元素
+    pub fn lrange(&self, key: &[u8], start: i64, stop: i64) -> Result<Vec<Bytes>> {
+        let list = match self.get_collection(key, RedisType::List)? {
+            Some(list) => list,
+            None => return Ok(Vec::new()),
+        };
+        let (start, stop) = match normalize_range(start, stop, list.size) {
+            Some(range) => range,
+            None => return Ok(Vec::new()),
+        };
+        let kvs = self.engine.scan(
+            element_key(&list, key, list.head + start),
+            Some(element_key(&list, key, list.head + stop + 1)),
+            (stop - start + 1) as usize,
+        )?;
+        Ok(kvs.into_iter().map(|(_, value)| value).collect())
+    }
+}
+
+// 把redis的[start, stop]转换成从0开始的位置,范围为空时返回None
+pub(crate) fn normalize_range(start: i64, stop: i64, size: u64) -> Option<(u64, u64)> {
+    let size = size as i64;
+    let start = if start < 0 {
+        (size + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        size + stop
+    } else {
+        stop.min(size - 1)
+    };
+    if start > stop || start >= size {
+        return None;
+    }
+    Some((start as u64, stop as u64))
+}
+
+#[cfg(test)]
+mod test_redis_list {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use super::normalize_range;
+    use crate::{db::Engine, options::Options, redis::RedisStore};
+
+    #[test]
+    fn test_redis_list() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-redis-list");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let store = RedisStore::new(engine);
+        let values =
+            |vs: &[&str]| -> Vec<Bytes> { vs.iter().map(|v| Bytes::from(v.to_string())).collect() };
+
+        assert_eq!(normalize_range(0, -1, 0), None);
+        assert_eq!(normalize_range(0, -1, 3), Some((0, 2)));
+        assert_eq!(normalize_range(-2, 10, 3), Some((1, 2)));
+        assert_eq!(normalize_range(-10, 0, 3), Some((0, 0)));
+        assert_eq!(normalize_range(2, 1, 3), None);
+
+        assert_eq!(store.rpush(b"list", &values(&["c", "d"])).unwrap(), 2);
+        // lpush依次插入到头部,顺序和参数相反
+        assert_eq!(store.lpush(b"list", &values(&["b", "a"])).unwrap(), 4);
+        assert_eq!(
+            store.lrange(b"list", 0, -1).unwrap(),
+            values(&["a", "b", "c", "d"])
+        );
+        assert_eq!(store.lrange(b"list", 1, 2).unwrap(), values(&["b", "c"]));
+        assert_eq!(store.lrange(b"list", -1, 100).unwrap(), values(&["d"]));
+        assert_eq!(store.lpop(b"list").unwrap(), Some(Bytes::from("a")));
+        assert_eq!(store.rpop(b"list").unwrap(), Some(Bytes::from("d")));
+        assert_eq!(store.lrange(b"list", 0, -1).unwrap(), values(&["b", "c"]));
+        assert_eq!(store.lpop(b"list").unwrap(), Some(Bytes::from("b")));
+        assert_eq!(store.lpop(b"list").unwrap(), Some(Bytes::from("c")));
+        assert_eq!(store.lpop(b"list").unwrap(), None);
+        assert_eq!(store.key_type(b"list").unwrap(), None);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,656 @@
+// 兼容redis的数据结构层
+// 每个redis key对应一个带前缀的元数据key,记录类型和过期时间,字符串的value直接存放在元数据中
+// hash、set、list和zset的元数据中还记录了版本号和元素个数,每个元素单独存放在带版本号的key中
+// 删除集合时只删除元数据,之前版本的元素不会再被读到,所以删除的代价和集合的大小无关
+// 旧版本的元素由reclaim_stale_members用范围删除清理,之后merge时会被丢弃
+// 所有的key都带有前缀,但是和直接通过Engine写入的key共用同一个key空间
+// 例如直接写入的Mfoo就是redis的key foo,同一个数据目录不要混用两种方式写入
+mod hash;
+mod list;
+mod reclaim;
+pub mod resp;
+pub mod server;
+mod set;
+mod string;
+mod zset;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::{Buf, BufMut, Bytes};
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::{
+    db::Engine,
+    delete_range::prefix_end,
+    errors::{Errors, Result},
+    options::WriteBatchOptions,
+    scan::next_key,
+    sequence::Sequence,
+    write_batch::WriteBatch,
+};
+
+pub use reclaim::Reclaimer;
+pub use string::{SetCondition, SetOptions};
+
+// 元数据key的前缀
+const META_KEY_PREFIX: &[u8] = b"M";
+// 集合元素key的前缀
+const MEMBER_KEY_PREFIX: u8 = b'D';
+// zset按照分数排序的索引key的前缀
+const SCORE_KEY_PREFIX: u8 = b'Z';
+// 记录集合版本号的预留上界的key
+const VERSION_KEY: &[u8] = b"Vversion";
+// 按照key分段的写锁个数
+const KEY_LOCK_STRIPES: usize = 64;
+// 遍历集合元素时每次读取的个数
+const MEMBER_SCAN_BATCH: usize = 1024;
+// list的第一个元素的位置,两端都可以继续插入
+const LIST_INITIAL_INDEX: u64 = u64::MAX / 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RedisType {
+    String,
+    Hash,
+    Set,
+    List,
+    ZSet,
+}
+
+impl RedisType {
+    fn from_byte(b: u8) -> Result<Self> {
+        match b {
+            0 => Ok(RedisType::String),
+            1 => Ok(RedisType::Hash),
+            2 => Ok(RedisType::Set),
+            3 => Ok(RedisType::List),
+            4 => Ok(RedisType::ZSet),
+            _ => Err(Errors::InvalidRedisMetadata),
+        }
+    }
+
+    // TYPE命令返回的名字
+    pub fn name(&self) -> &'static str {
+        match self {
+            RedisType::String => "string",
+            RedisType::Hash => "hash",
+            RedisType::Set => "set",
+            RedisType::List => "list",
+            RedisType::ZSet => "zset",
+        }
+    }
+}
+
+// 元数据的格式: | 类型 u8 | 过期时间 u64 | 类型相关的数据 |
+// 过期时间为毫秒时间戳,0表示不过期
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Metadata {
+    pub(crate) redis_type: RedisType,
+    pub(crate) expire_at: u64,
+    pub(crate) payload: Bytes,
+}
+
+impl Metadata {
+    pub(crate) fn encode(&self) -> Bytes {
+        let mut buf = Vec::with_capacity(9 + self.payload.len());
+        buf.put_u8(self.redis_type as u8);
+        buf.put_u64(self.expire_at);
+        buf.extend_from_slice(&self.payload);
+        Bytes::from(buf)
+    }
+
+    pub(crate) fn decode(mut buf: Bytes) -> Result<Self> {
+        if buf.len() < 9 {
+            return Err(Errors::InvalidRedisMetadata);
+        }
+        let redis_type = RedisType::from_byte(buf.get_u8())?;
+        let expire_at = buf.get_u64();
+        Ok(Metadata {
+            redis_type,
+            expire_at,
+            payload: buf,
+        })
+    }
+
+    pub(crate) fn is_expired(&self, now: u64) -> bool {
+        self.expire_at != 0 && self.expire_at <= now
+    }
+}
+
+// 集合的元数据,存放在Metadata的payload中
+// | 版本号 u64 | 元素个数 u64 | list的头部位置 u64 | list的尾部位置 u64 |
+// list的元素存放在[head, tail)的位置上,只有list有head和tail
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Collection {
+    pub(crate) redis_type: RedisType,
+    pub(crate) expire_at: u64,
+    pub(crate) version: u64,
+    pub(crate) size: u64,
+    pub(crate) head: u64,
+    pub(crate) tail: u64,
+}
+
+impl Collection {
+    fn decode(metadata: Metadata) -> Result<Self> {
+        let mut payload = metadata.payload;
+        let is_list = metadata.redis_type == RedisType::List;
+        if payload.len() != if is_list { 32 } else { 16 } {
+            return Err(Errors::InvalidRedisMetadata);
+        }
+        let version = payload.get_u64();
+        let size = payload.get_u64();
+        let (head, tail) = match is_list {
+            true => (payload.get_u64(), payload.get_u64()),
+            false => (0, 0),
+        };
+        Ok(Collection {
+            redis_type: metadata.redis_type,
+            expire_at: metadata.expire_at,
+            version,
+            size,
+            head,
+            tail,
+        })
+    }
+
+    fn encode(&self) -> Bytes {
+        let mut payload = Vec::with_capacity(32);
+        payload.put_u64(self.version);
+        payload.put_u64(self.size);
+        if self.redis_type == RedisType::List {
+            payload.put_u64(self.head);
+            payload.put_u64(self.tail);
+        }
+        Metadata {
+            redis_type: self.redis_type,
+            expire_at: self.expire_at,
+            payload: Bytes::from(payload),
+        }
+        .encode()
+    }
+
+    // 元素key的前缀: | 前缀 u8 | key的长度 u32 | key | 版本号 u64 |
+    pub(crate) fn member_prefix(&self, prefix: u8, key: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(13 + key.len());
+        buf.put_u8(prefix);
+        buf.put_u32(key.len() as u32);
+        buf.extend_from_slice(key);
+        buf.put_u64(self.version);
+        buf
+    }
+
+    pub(crate) fn member_key(&self, key: &[u8], member: &[u8]) -> Bytes {
+        let mut buf = self.member_prefix(MEMBER_KEY_PREFIX, key);
+        buf.extend_from_slice(member);
+        Bytes::from(buf)
+    }
+}
+
+// 在Engine之上提供redis的数据结构和命令语义
+pub struct RedisStore {
+    engine: Arc<Engine>,
+    // 读取元数据、修改之后再写回的操作需要拿对应key的锁
+    key_locks: Vec<Mutex<()>>,
+    // 集合的版本号,删除过的集合的版本号不会再被分配
+    versions: Sequence,
+}
+
+impl RedisStore {
+    pub fn new(engine: Arc<Engine>) -> Self {
+        RedisStore {
+            engine,
+            key_locks: (0..KEY_LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+            versions: Sequence::new(VERSION_KEY),
+        }
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    // 读取原始的元数据,过期的也会返回
+    fn get_raw_metadata(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        match self.engine.get(meta_key(key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(Errors::KeyNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // 读取没有过期的元数据
+    pub(crate) fn get_metadata(&self, key: &[u8]) -> Result<Option<Metadata>> {
+        let metadata = match self.get_raw_metadata(key)? {
+            Some(raw) => Metadata::decode(raw)?,
+            None => return Ok(None),
+        };
+        match metadata.is_expired(now_millis()) {
+            true => Ok(None),
+            false => Ok(Some(metadata)),
+        }
+    }
+
+    fn lock_stripe(key: &[u8]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish() as usize % KEY_LOCK_STRIPES
+    }
+
+    pub(crate) fn lock_key(&self, key: &[u8]) -> MutexGuard<'_, ()> {
+        self.key_locks[Self::lock_stripe(key)].lock()
+    }
+
+    // 同时锁住多个key,按照固定的顺序加锁避免死锁
+    pub(crate) fn lock_keys(&self, keys: &[Bytes]) -> Vec<MutexGuard<'_, ()>> {
+        let mut stripes: Vec<usize> = keys.iter().map(|key| Self::lock_stripe(key)).collect();
+        stripes.sort_unstable();
+        stripes.dedup();
+        stripes
+            .into_iter()
+            .map(|stripe| self.key_locks[stripe].lock())
+            .collect()
+    }
+
+    // 读取集合的元数据,key不存在时返回None,类型不一致时返回WrongType
+    pub(crate) fn get_collection(
+        &self,
+        key: &[u8],
+        redis_type: RedisType,
+    ) -> Result<Option<Collection>> {
+        match self.get_metadata(key)? {
+            Some(metadata) if metadata.redis_type == redis_type => {
+                Collection::decode(metadata).map(Some)
+            }
+            Some(_) => Err(Errors::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    // 读取集合的元数据,key不存在时返回一个新版本的空集合
+    pub(crate) fn get_or_new_collection(
+        &self,
+        key: &[u8],
+        redis_type: RedisType,
+    ) -> Result<Collection> {
+        if let Some(collection) = self.get_collection(key, redis_type)? {
+            return Ok(collection);
+        }
+        Ok(Collection {
+            redis_type,
+            expire_at: 0,
+            version: self.versions.next(&self.engine)?,
+            size: 0,
+            head: LIST_INITIAL_INDEX,
+            tail: LIST_INITIAL_INDEX,
+        })
+    }
+
+    // 写回集合的元数据,集合为空时删除这个key
+    pub(crate) fn put_collection(
+        &self,
+        write_batch: &WriteBatch,
+        key: &[u8],
+        collection: &Collection,
+    ) -> Result<()> {
+        match collection.size {
+            0 => write_batch.delete(meta_key(key)),
+            _ => write_batch.put(meta_key(key), collection.encode()),
+        }
+    }
+
+    // 按照顺序读取前缀为prefix的所有元素,返回去掉前缀之后的key和value
+    pub(crate) fn scan_members(&self, prefix: &[u8]) -> Result<Vec<(Bytes, Bytes)>> {
+        let prefix = Bytes::copy_from_slice(prefix);
+        let mut members = Vec::new();
+        let mut start = prefix.clone();
+        loop {
+            let kvs = self
+                .engine
+                .scan_prefix(prefix.clone(), start.clone(), MEMBER_SCAN_BATCH)?;
+            if let Some((key, _)) = kvs.last() {
+                start = next_key(key);
+            }
+            let done = kvs.len() < MEMBER_SCAN_BATCH;
+            members.extend(
+                kvs.into_iter()
+                    .map(|(key, value)| (key.slice(prefix.len()..), value)),
+            );
+            if done {
+                return Ok(members);
+            }
+        }
+    }
+
+    // 返回key的类型,不存在时返回None
+    pub fn key_type(&self, key: &[u8]) -> Result<Option<RedisType>> {
+        Ok(self.get_metadata(key)?.map(|metadata| metadata.redis_type))
+    }
+
+    // 返回存在的key的个数,重复的key会重复计数
+    pub fn exists(&self, keys: &[Bytes]) -> Result<i64> {
+        let mut count = 0;
+        for key in keys {
+            if self.get_metadata(key)?.is_some() {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    // 原子地删除多个key,返回删除之前存在的key的个数
+    pub fn del(&self, keys: &[Bytes]) -> Result<i64> {
+        let _guards = self.lock_keys(keys);
+        let write_batch = self.engine.new_write_batch(WriteBatchOptions::default())?;
+        let mut count = 0;
+        let mut deleted = HashSet::new();
+        for key in keys {
+            // 重复的key只计数一次
+            if !deleted.insert(key) {
+                continue;
+            }
+            let raw = match self.get_raw_metadata(key)? {
+                Some(raw) => raw,
+                None => continue,
+            };
+            if !Metadata::decode(raw)?.is_expired(now_millis()) {
+                count += 1;
+            }
+            // 过期的key也一起删除,集合只需要删除元数据
+            write_batch.delete(meta_key(key))?;
+        }
+        write_batch.commit()?;
+        Ok(count)
+    }
+
+    // 剩余的过期时间,单位为毫秒,key不存在返回-2,没有过期时间返回-1
+    pub fn pttl(&self, key: &[u8]) -> Result<i64> {
+        match self.get_metadata(key)? {
+            None => Ok(-2),
+            Some(metadata) if metadata.expire_at == 0 => Ok(-1),
+            Some(metadata) => Ok(metadata.expire_at.saturating_sub(now_millis()) as i64),
+        }
+    }
+
+    // 从游标开始返回最多count个key,返回的游标为0表示已经遍历完成
+    // 游标中编码了上一次返回的最后一个key,遍历期间一直存在的key一定会被返回,重启之后游标依然有效
+    pub fn scan(
+        &self,
+        cursor: &[u8],
+        pattern: Option<&[u8]>,
+        count: usize,
+    ) -> Result<(Bytes, Vec<Bytes>)> {
+        let start = match decode_scan_cursor(cursor)? {
+            Some(last_key) => next_key(&meta_key(&last_key)),
+            None => Bytes::from_static(META_KEY_PREFIX),
+        };
+        let count = count.max(1);
+        let kvs = self
+            .engine
+            .scan(start, prefix_end(META_KEY_PREFIX), count)?;
+        let now = now_millis();
+        let mut keys = Vec::new();
+        for (key, value) in kvs.iter() {
+            if Metadata::decode(value.clone())?.is_expired(now) {
+                continue;
+            }
+            let key = key.slice(META_KEY_PREFIX.len()..);
+            if pattern.is_none_or(|pattern| glob_match(pattern, &key)) {
+                keys.push(key);
+            }
+        }
+        if kvs.len() < count {
+            return Ok((Bytes::from_static(b"0"), keys));
+        }
+        let last_key = &kvs.last().unwrap().0[META_KEY_PREFIX.len()..];
+        Ok((encode_scan_cursor(last_key), keys))
+    }
+
+    // 返回所有匹配pattern的key
+    pub fn keys(&self, pattern: &[u8]) -> Result<Vec<Bytes>> {
+        let mut keys = Vec::new();
+        let mut start = Bytes::from_static(META_KEY_PREFIX);
+        loop {
+            let kvs = self.engine.scan(start, prefix_end(META_KEY_PREFIX), 1024)?;
+            let now = now_millis();
+            for (key, value) in kvs.iter() {
+                let user_key = key.slice(META_KEY_PREFIX.len()..);
+                if !Metadata::decode(value.clone())?.is_expired(now)
+                    && glob_match(pattern, &user_key)
+                {
+                    keys.push(user_key);
+                }
+            }
+            match kvs.last() {
+                Some((key, _)) => start = next_key(key),
+                None => return Ok(keys),
+            }
+        }
+    }
+}
+
+pub(crate) fn meta_key(key: &[u8]) -> Bytes {
+    let mut meta_key = Vec::with_capacity(META_KEY_PREFIX.len() + key.len());
+    meta_key.extend_from_slice(META_KEY_PREFIX);
+    meta_key.extend_from_slice(key);
+    Bytes::from(meta_key)
+}
+
+// 游标为1之后跟着key的每个字节的三位十进制数,客户端可以把它当作整数处理
+// 0表示从头开始遍历
+fn encode_scan_cursor(last_key: &[u8]) -> Bytes {
+    let mut cursor = String::with_capacity(1 + last_key.len() * 3);
+    cursor.push('1');
+    for b in last_key {
+        cursor.push_str(&format!("{:03}", b));
+    }
+    Bytes::from(cursor)
+}
+
+fn decode_scan_cursor(cursor: &[u8]) -> Result<Option<Vec<u8>>> {
+    if cursor == b"0" {
+        return Ok(None);
+    }
+    let digits = match cursor.split_first() {
+        Some((b'1', digits)) if digits.len() % 3 == 0 => digits,
+        _ => return Err(Errors::InvalidScanCursor),
+    };
+    let mut last_key = Vec::with_capacity(digits.len() / 3);
+    for chunk in digits.chunks(3) {
+        match std::str::from_utf8(chunk)
+            .ok()
+            .and_then(|s| s.parse::<u8>().ok())
+        {
+            Some(b) if chunk.iter().all(u8::is_ascii_digit) => last_key.push(b),
+            _ => return Err(Errors::InvalidScanCursor),
+        }
+    }
+    Ok(Some(last_key))
+}
+
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+// redis的glob匹配,支持*、?、[abc]、[^a-z]和\转义
+pub fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // 最近一次*的位置和它匹配到的位置,匹配失败时回溯
+    let mut star: Option<(usize, usize)> = None;
+    while i < s.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    star = Some((p, i));
+                    p += 1;
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    i += 1;
+                    continue;
+                }
+                b'[' => {
+                    if let Some((matched, len)) = match_class(&pattern[p..], s[i]) {
+                        if matched {
+                            p += len;
+                            i += 1;
+                            continue;
+                        }
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == s[i] {
+                        p += 2;
+                        i += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == s[i] {
+                        p += 1;
+                        i += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        match star {
+            Some((star_p, star_i)) => {
+                p = star_p + 1;
+                i = star_i + 1;
+                star = Some((star_p, star_i + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+// 匹配[...]字符集,返回是否匹配以及字符集在pattern中的长度,没有结束的]时返回None
+fn match_class(pattern: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut idx = 1;
+    let negate = pattern.get(idx) == Some(&b'^');
+    if negate {
+        idx += 1;
+    }
+    let mut matched = false;
+    while idx < pattern.len() && pattern[idx] != b']' {
+        if pattern[idx] == b'\\' && idx + 1 < pattern.len() {
+            matched |= pattern[idx + 1] == c;
+            idx += 2;
+        } else if idx + 2 < pattern.len() && pattern[idx + 1] == b'-' && pattern[idx + 2] != b']' {
+            let (low, high) = (pattern[idx], pattern[idx + 2]);
+            matched |= low.min(high) <= c && c <= low.max(high);
+            idx += 3;
+        } else {
+            matched |= pattern[idx] == c;
+            idx += 1;
+        }
+    }
+    if idx >= pattern.len() {
+        return None;
+    }
+    Some((matched != negate, idx + 1))
+}
+
+#[cfg(test)]
+mod test_redis {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::{glob_match, RedisStore, RedisType, SetOptions};
+    use crate::{db::Engine, errors::Errors, options::Options};
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"user:*", b"user:1"));
+        assert!(!glob_match(b"user:*", b"order:1"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-c]llo", b"hbllo"));
+        assert!(glob_match(b"h\\*llo", b"h*llo"));
+        assert!(!glob_match(b"h\\*llo", b"hello"));
+        assert!(glob_match(b"*a*b*", b"xxaxxbxx"));
+        assert!(!glob_match(b"*a*b", b"xxaxxbxx"));
+    }
+
+    #[test]
+    fn test_redis_keys() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-redis-keys");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let store = RedisStore::new(engine.clone());
+        for i in 0..100 {
+            let key = Bytes::from(format!("user:{:03}", i));
+            store
+                .set(key, Bytes::from("v"), SetOptions::default())
+                .unwrap();
+        }
+        store
+            .set(
+                Bytes::from("temp"),
+                Bytes::from("v"),
+                SetOptions {
+                    expire: Some(Duration::from_millis(1)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        // 直接通过engine写入的key不可见
+        engine
+            .put(Bytes::from("raw"), Bytes::from("value"))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+
+        assert_eq!(store.keys(b"*").unwrap().len(), 100);
+        assert_eq!(store.keys(b"user:00?").unwrap().len(), 10);
+        assert_eq!(
+            store.key_type(b"user:001").unwrap(),
+            Some(RedisType::String)
+        );
+        assert_eq!(store.key_type(b"temp").unwrap(), None);
+        assert_eq!(store.pttl(b"temp").unwrap(), -2);
+        assert_eq!(store.pttl(b"user:001").unwrap(), -1);
+
+        // 分批遍历,游标不依赖进程内的状态,换一个RedisStore也可以继续
+        let mut cursor = Bytes::from("0");
+        let mut keys = Vec::new();
+        loop {
+            let store = RedisStore::new(engine.clone());
+            let (next_cursor, batch) = store.scan(&cursor, Some(b"user:*"), 30).unwrap();
+            keys.extend(batch);
+            if next_cursor == "0" {
+                break;
+            }
+            cursor = next_cursor;
+        }
+        assert_eq!(keys.len(), 100);
+        for cursor in ["12345", "abc", "1999", ""] {
+            assert_eq!(
+                store.scan(cursor.as_bytes(), None, 10).err(),
+                Some(Errors::InvalidScanCursor)
+            );
+        }
+
+        let keys = [
+            Bytes::from("user:001"),
+            Bytes::from("user:001"),
+            Bytes::from("temp"),
+            Bytes::from("missing"),
+        ];
+        assert_eq!(store.exists(&keys).unwrap(), 2);
+        assert_eq!(store.del(&keys).unwrap(), 1);
+        assert_eq!(store.exists(&keys).unwrap(), 0);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
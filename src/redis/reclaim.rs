@@ -0,0 +1,191 @@
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use bytes::{Buf, Bytes};
+use log::error;
+use parking_lot::{Condvar, Mutex};
+
+use super::{Collection, RedisStore, RedisType, MEMBER_KEY_PREFIX, SCORE_KEY_PREFIX};
+use crate::{delete_range::prefix_end, errors::Result, scan::next_key};
+
+impl RedisStore {
+    // 清理被删除、覆盖或者过期的集合留下的元素和zset的分数索引
+    // 每个旧版本的元素只写一条范围删除,返回清理的版本个数
+    pub fn reclaim_stale_members(&self) -> Result<u64> {
+        let mut reclaimed = 0;
+        for prefix in [MEMBER_KEY_PREFIX, SCORE_KEY_PREFIX] {
+            let end = prefix_end(&[prefix]);
+            let mut start = Bytes::copy_from_slice(&[prefix]);
+            loop {
+                let kvs = self.engine.scan(start, end.clone(), 1)?;
+                let member_key = match kvs.into_iter().next() {
+                    Some((member_key, _)) => member_key,
+                    None => break,
+                };
+                // 不是元素key的格式,可能是直接通过Engine写入的key
+                let (key, version, member_len) = match parse_member_key(&member_key) {
+                    Some(parsed) => parsed,
+                    None => {
+                        start = next_key(&member_key);
+                        continue;
+                    }
+                };
+                let version_prefix = member_key.slice(..member_key.len() - member_len);
+                {
+                    // 和修改集合的写入互斥,不会看到写了一半的集合
+                    let _guard = self.lock_key(&key);
+                    if !self.is_live_version(&key, version)? {
+                        self.engine.delete_prefix(version_prefix.clone())?;
+                        reclaimed += 1;
+                    }
+                }
+                // 跳过这个版本的所有元素
+                match prefix_end(&version_prefix) {
+                    Some(next) => start = next,
+                    None => break,
+                }
+            }
+        }
+        Ok(reclaimed)
+    }
+
+    // 版本号是否是key当前的集合的版本号,过期的集合不会再被读到
+    fn is_live_version(&self, key: &[u8], version: u64) -> Result<bool> {
+        match self.get_metadata(key)? {
+            Some(metadata) if metadata.redis_type != RedisType::String => {
+                Ok(Collection::decode(metadata)?.version == version)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+// 解析元素key: | 前缀 u8 | key的长度 u32 | key | 版本号 u64 | 元素 |
+// 返回key、版本号和元素的长度
+fn parse_member_key(member_key: &[u8]) -> Option<(Bytes, u64, usize)> {
+    let mut buf = member_key.get(1..)?;
+    if buf.remaining() < 4 {
+        return None;
+    }
+    let key_len = buf.get_u32() as usize;
+    if buf.remaining() < key_len + 8 {
+        return None;
+    }
+    let key = Bytes::copy_from_slice(&buf[..key_len]);
+    buf.advance(key_len);
+    let version = buf.get_u64();
+    Some((key, version, buf.remaining()))
+}
+
+// 后台定期清理旧版本元素的线程,Drop的时候停止
+pub struct Reclaimer {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Reclaimer {
+    pub fn start(store: Arc<RedisStore>, interval: Duration) -> Self {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_stop = stop.clone();
+        let handle = std::thread::spawn(move || loop {
+            {
+                let (lock, cond) = &*thread_stop;
+                let mut stopped = lock.lock();
+                if !*stopped {
+                    cond.wait_for(&mut stopped, interval);
+                }
+                if *stopped {
+                    return;
+                }
+            }
+            if let Err(e) = store.reclaim_stale_members() {
+                error!("failed to reclaim stale redis members: {}", e);
+            }
+        });
+        Reclaimer {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Reclaimer {
+    fn drop(&mut self) {
+        let (lock, cond) = &*self.stop;
+        *lock.lock() = true;
+        cond.notify_all();
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_reclaim {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::Reclaimer;
+    use crate::{
+        db::Engine,
+        options::Options,
+        redis::{RedisStore, SetOptions},
+    };
+
+    #[test]
+    fn test_reclaim_stale_members() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-redis-reclaim");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let store = Arc::new(RedisStore::new(engine.clone()));
+        let members: Vec<Bytes> = (0..100).map(|i| Bytes::from(format!("m{}", i))).collect();
+        // 删除的集合、被覆盖的集合都会留下旧版本的元素,zset还有分数索引
+        store.sadd(b"deleted", &members).unwrap();
+        store.del(&[Bytes::from("deleted")]).unwrap();
+        store.sadd(b"overwritten", &members).unwrap();
+        store
+            .set(
+                Bytes::from("overwritten"),
+                Bytes::from("v"),
+                SetOptions::default(),
+            )
+            .unwrap();
+        let scored: Vec<(f64, Bytes)> = members
+            .iter()
+            .enumerate()
+            .map(|(i, member)| (i as f64, member.clone()))
+            .collect();
+        store.zadd(b"zset", &scored).unwrap();
+        store.del(&[Bytes::from("zset")]).unwrap();
+        store.sadd(b"live", &members[..10]).unwrap();
+        // 直接通过engine写入的key不受影响
+        engine.put(Bytes::from("Draw"), Bytes::from("v")).unwrap();
+
+        assert_eq!(store.reclaim_stale_members().unwrap(), 4);
+        assert_eq!(store.reclaim_stale_members().unwrap(), 0);
+        assert_eq!(store.smembers(b"live").unwrap().len(), 10);
+        assert!(engine.get(Bytes::from("Draw")).is_ok());
+        // 剩下live的10个元素,live和overwritten的元数据,版本号记录和直接写入的key
+        assert_eq!(engine.list_keys().unwrap().len(), 10 + 2 + 1 + 1);
+
+        // 后台清理
+        store.del(&[Bytes::from("live")]).unwrap();
+        let reclaimer = Reclaimer::start(store.clone(), Duration::from_millis(5));
+        let mut reclaimed = false;
+        for _ in 0..200 {
+            if engine.list_keys().unwrap().len() == 3 {
+                reclaimed = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(reclaimed);
+        drop(reclaimer);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,345 @@
+use std::io::{BufRead, Error, ErrorKind, Read, Result};
+
+use bytes::Bytes;
+
+// 单个bulk string和数组的大小上限,防止恶意的请求占用过多内存
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+const MAX_ARRAY_LEN: usize = 1024 * 1024;
+// 一行的长度上限,inline命令也受这个限制
+const MAX_LINE_LEN: usize = 64 * 1024;
+// 数组和map嵌套的最大深度,防止递归解析时栈溢出
+const MAX_NESTING_DEPTH: usize = 32;
+// 按照客户端声明的长度最多预先分配的元素个数,更多的空间随着数据到达再分配
+const MAX_PREALLOC_LEN: usize = 1024;
+
+// RESP2/RESP3中的一个值
+#[derive(Clone, Debug, PartialEq)]
+pub enum RespValue {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Bytes),
+    Null,
+    Array(Vec<RespValue>),
+    // RESP2中编码为key和value交替的数组
+    Map(Vec<(RespValue, RespValue)>),
+}
+
+impl RespValue {
+    pub fn ok() -> Self {
+        RespValue::Simple("OK".to_string())
+    }
+
+    pub fn error(message: &str) -> Self {
+        RespValue::Error(message.to_string())
+    }
+
+    pub fn bulk(value: &str) -> Self {
+        RespValue::Bulk(Bytes::from(value.to_string()))
+    }
+
+    // 按照协议版本编码,protocol为2或者3
+    pub fn encode(&self, protocol: u8, buf: &mut Vec<u8>) {
+        match self {
+            RespValue::Simple(s) => write_line(buf, b'+', s.as_bytes()),
+            RespValue::Error(s) => write_line(buf, b'-', s.as_bytes()),
+            RespValue::Integer(n) => write_line(buf, b':', n.to_string().as_bytes()),
+            RespValue::Bulk(data) => {
+                write_line(buf, b'$', data.len().to_string().as_bytes());
+                buf.extend_from_slice(data);
+                buf.extend_from_slice(b"\r\n");
+            }
+            RespValue::Null if protocol >= 3 => buf.extend_from_slice(b"_\r\n"),
+            RespValue::Null => buf.extend_from_slice(b"$-1\r\n"),
+            RespValue::Array(values) => {
+                write_line(buf, b'*', values.len().to_string().as_bytes());
+                for value in values {
+                    value.encode(protocol, buf);
+                }
+            }
+            RespValue::Map(entries) => {
+                match protocol >= 3 {
+                    true => write_line(buf, b'%', entries.len().to_string().as_bytes()),
+                    false => write_line(buf, b'*', (entries.len() * 2).to_string().as_bytes()),
+                }
+                for (key, value) in entries {
+                    key.encode(protocol, buf);
+                    value.encode(protocol, buf);
+                }
+            }
+        }
+    }
+}
+
+fn write_line(buf: &mut Vec<u8>, prefix: u8, line: &[u8]) {
+    buf.push(prefix);
+    buf.extend_from_slice(line);
+    buf.extend_from_slice(b"\r\n");
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+// 读取一行,去掉结尾的\r\n,连接关闭时返回None
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    // 最多读取MAX_LINE_LEN + 2个字节,包括结尾的\r\n
+    let mut limited = reader.by_ref().take(MAX_LINE_LEN as u64 + 2);
+    if limited.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        if line.len() > MAX_LINE_LEN {
+            return Err(invalid_data("line is too long"));
+        }
+        return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected eof"));
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_number(line: &[u8]) -> Result<i64> {
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or_else(|| invalid_data("invalid number"))
+}
+
+fn parse_len(line: &[u8], max: usize) -> Result<Option<usize>> {
+    match parse_number(line)? {
+        -1 => Ok(None),
+        n if n < 0 || n as usize > max => Err(invalid_data("invalid length")),
+        n => Ok(Some(n as usize)),
+    }
+}
+
+// 读取一个完整的RESP值,连接在两个值之间关闭时返回None
+pub fn read_value<R: BufRead>(reader: &mut R) -> Result<Option<RespValue>> {
+    let line = match read_line(reader)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    read_value_with_line(reader, line, 0).map(Some)
+}
+
+fn read_value_with_line<R: BufRead>(
+    reader: &mut R,
+    line: Vec<u8>,
+    depth: usize,
+) -> Result<RespValue> {
+    let (prefix, rest) = match line.split_first() {
+        Some((prefix, rest)) => (*prefix, rest),
+        None => return Err(invalid_data("empty line")),
+    };
+    let value = match prefix {
+        b'+' => RespValue::Simple(String::from_utf8_lossy(rest).to_string()),
+        b'-' => RespValue::Error(String::from_utf8_lossy(rest).to_string()),
+        b':' => RespValue::Integer(parse_number(rest)?),
+        b'_' => RespValue::Null,
+        b'$' => match read_bulk(reader, rest)? {
+            Some(data) => RespValue::Bulk(data),
+            None => RespValue::Null,
+        },
+        b'*' | b'%' if depth >= MAX_NESTING_DEPTH => {
+            return Err(invalid_data("too many nested aggregates"))
+        }
+        b'*' => match parse_len(rest, MAX_ARRAY_LEN)? {
+            Some(len) => {
+                let mut values = Vec::with_capacity(len.min(MAX_PREALLOC_LEN));
+                for _ in 0..len {
+                    values.push(read_nested(reader, depth + 1)?);
+                }
+                RespValue::Array(values)
+            }
+            None => RespValue::Null,
+        },
+        b'%' => {
+            let len = parse_len(rest, MAX_ARRAY_LEN)?.unwrap_or(0);
+            let mut entries = Vec::with_capacity(len.min(MAX_PREALLOC_LEN));
+            for _ in 0..len {
+                entries.push((
+                    read_nested(reader, depth + 1)?,
+                    read_nested(reader, depth + 1)?,
+                ));
+            }
+            RespValue::Map(entries)
+        }
+        _ => return Err(invalid_data("unknown value type")),
+    };
+    Ok(value)
+}
+
+// 读取$之后的长度和bulk string的内容,长度为-1时返回None
+fn read_bulk<R: BufRead>(reader: &mut R, len: &[u8]) -> Result<Option<Bytes>> {
+    let len = match parse_len(len, MAX_BULK_LEN)? {
+        Some(len) => len,
+        None => return Ok(None),
+    };
+    // 不按照声明的长度一次分配,数据到达之后缓冲区才会增长
+    let mut data = Vec::with_capacity((len + 2).min(MAX_PREALLOC_LEN));
+    reader
+        .by_ref()
+        .take(len as u64 + 2)
+        .read_to_end(&mut data)?;
+    if data.len() < len + 2 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected eof"));
+    }
+    if !data.ends_with(b"\r\n") {
+        return Err(invalid_data("bulk string is not terminated by CRLF"));
+    }
+    data.truncate(len);
+    Ok(Some(Bytes::from(data)))
+}
+
+fn read_nested<R: BufRead>(reader: &mut R, depth: usize) -> Result<RespValue> {
+    match read_line(reader)? {
+        Some(line) => read_value_with_line(reader, line, depth),
+        None => Err(Error::new(ErrorKind::UnexpectedEof, "unexpected eof")),
+    }
+}
+
+// 读取客户端的一个命令,支持bulk string数组和telnet使用的inline命令
+// 连接关闭时返回None
+pub fn read_command<R: BufRead>(reader: &mut R) -> Result<Option<Vec<Bytes>>> {
+    loop {
+        let line = match read_line(reader)? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        if line.first() != Some(&b'*') {
+            let args: Vec<Bytes> = line
+                .split(|c| c.is_ascii_whitespace())
+                .filter(|arg| !arg.is_empty())
+                .map(Bytes::copy_from_slice)
+                .collect();
+            // 忽略空行
+            if args.is_empty() {
+                continue;
+            }
+            return Ok(Some(args));
+        }
+        // 命令是bulk string的数组,不允许嵌套,直接逐个读取参数
+        let len = match parse_len(&line[1..], MAX_ARRAY_LEN)? {
+            Some(len) => len,
+            None => continue,
+        };
+        let mut args = Vec::with_capacity(len.min(MAX_PREALLOC_LEN));
+        for _ in 0..len {
+            let line = match read_line(reader)? {
+                Some(line) => line,
+                None => return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected eof")),
+            };
+            match line.split_first() {
+                Some((b'$', len)) => match read_bulk(reader, len)? {
+                    Some(data) => args.push(data),
+                    None => return Err(invalid_data("command arguments must be bulk strings")),
+                },
+                _ => return Err(invalid_data("command arguments must be bulk strings")),
+            }
+        }
+        if !args.is_empty() {
+            return Ok(Some(args));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_resp {
+    use std::io::{BufReader, ErrorKind};
+
+    use bytes::Bytes;
+
+    use super::{read_command, read_value, RespValue, MAX_LINE_LEN, MAX_NESTING_DEPTH};
+
+    #[test]
+    fn test_resp_encode_decode() {
+        let value = RespValue::Array(vec![
+            RespValue::ok(),
+            RespValue::error("ERR bad"),
+            RespValue::Integer(-3),
+            RespValue::bulk("hello\r\nworld"),
+            RespValue::Null,
+            RespValue::Map(vec![(RespValue::bulk("k"), RespValue::Integer(1))]),
+        ]);
+        let mut resp3 = Vec::new();
+        value.encode(3, &mut resp3);
+        let decoded = read_value(&mut BufReader::new(&resp3[..])).unwrap();
+        assert_eq!(decoded, Some(value.clone()));
+
+        // RESP2中null是$-1,map是数组
+        let mut resp2 = Vec::new();
+        value.encode(2, &mut resp2);
+        let decoded = read_value(&mut BufReader::new(&resp2[..]))
+            .unwrap()
+            .unwrap();
+        match decoded {
+            RespValue::Array(values) => {
+                assert_eq!(values[4], RespValue::Null);
+                assert_eq!(
+                    values[5],
+                    RespValue::Array(vec![RespValue::bulk("k"), RespValue::Integer(1)])
+                );
+            }
+            _ => panic!("expect array"),
+        }
+    }
+
+    #[test]
+    fn test_read_command() {
+        let input = b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$2\r\nv1\r\n\r\nPING hello\r\n";
+        let mut reader = BufReader::new(&input[..]);
+        assert_eq!(
+            read_command(&mut reader).unwrap(),
+            Some(vec![
+                Bytes::from("SET"),
+                Bytes::from("k"),
+                Bytes::from("v1")
+            ])
+        );
+        assert_eq!(
+            read_command(&mut reader).unwrap(),
+            Some(vec![Bytes::from("PING"), Bytes::from("hello")])
+        );
+        assert_eq!(read_command(&mut reader).unwrap(), None);
+        // 不完整的请求
+        let mut reader = BufReader::new(&b"*2\r\n$3\r\nGET\r\n"[..]);
+        assert!(read_command(&mut reader).is_err());
+        // 命令中不能有嵌套的数组
+        let nested = b"*1\r\n".repeat(100_000);
+        assert!(read_command(&mut BufReader::new(&nested[..])).is_err());
+        // 太长的行
+        let long_line = vec![b'a'; MAX_LINE_LEN + 1];
+        assert!(read_command(&mut BufReader::new(&long_line[..])).is_err());
+    }
+
+    #[test]
+    fn test_read_header_only() {
+        // 只有声明了很大长度的头部,没有数据,不会按照声明的长度分配内存
+        for input in [
+            &b"*1048576\r\n"[..],
+            &b"%1048576\r\n"[..],
+            &b"$536870912\r\n"[..],
+            &b"$536870912\r\nabc"[..],
+        ] {
+            let err = read_value(&mut BufReader::new(input)).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        }
+        for input in [&b"*1048576\r\n"[..], &b"*1\r\n$536870912\r\n"[..]] {
+            let err = read_command(&mut BufReader::new(input)).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn test_read_value_nesting_limit() {
+        let nested = b"*1\r\n".repeat(100_000);
+        assert!(read_value(&mut BufReader::new(&nested[..])).is_err());
+        let mut nested = b"*1\r\n".repeat(MAX_NESTING_DEPTH);
+        nested.extend_from_slice(b":1\r\n");
+        assert!(read_value(&mut BufReader::new(&nested[..])).is_ok());
+    }
+}
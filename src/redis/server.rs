@@ -0,0 +1,578 @@
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use log::error;
+
+use super::{
+    resp::{read_command, RespValue},
+    RedisStore, SetCondition, SetOptions,
+};
+use crate::{
+    errors::{Errors, Result},
+    tcp_server::TcpServer,
+};
+
+// SCAN默认每次返回的key数
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+// 兼容RESP2/RESP3协议的服务,可以直接用redis-cli或者redis的客户端访问
+// 每个连接一个线程,同一个连接上的请求按顺序执行,支持pipeline
+pub struct RespServer {
+    server: TcpServer,
+}
+
+impl RespServer {
+    pub fn start<A: ToSocketAddrs>(store: Arc<RedisStore>, addr: A) -> Result<Self> {
+        let server = TcpServer::start(addr, move |stream| {
+            if let Err(e) = handle_connection(&store, stream) {
+                error!("failed to handle resp connection: {}", e);
+            }
+        })?;
+        Ok(RespServer { server })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+// 连接的状态
+struct Connection {
+    // 协议版本,HELLO 3之后使用RESP3
+    protocol: u8,
+    quit: bool,
+}
+
+fn handle_connection(store: &RedisStore, stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let mut conn = Connection {
+        protocol: 2,
+        quit: false,
+    };
+    let mut buf = Vec::new();
+    while !conn.quit {
+        let args = match read_command(&mut reader) {
+            Ok(Some(args)) => args,
+            Ok(None) => break,
+            Err(e) => {
+                buf.clear();
+                RespValue::Error(format!("ERR Protocol error: {}", e))
+                    .encode(conn.protocol, &mut buf);
+                let _ = writer.write_all(&buf).and_then(|_| writer.flush());
+                return Ok(());
+            }
+        };
+        let reply = execute(store, &mut conn, &args);
+        buf.clear();
+        reply.encode(conn.protocol, &mut buf);
+        writer.write_all(&buf)?;
+        // pipeline中的请求全部处理完之后再一起发送
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+    writer.flush()
+}
+
+fn error_reply(e: Errors) -> RespValue {
+    match e {
+        Errors::ReadOnly => {
+            RespValue::error("READONLY You can't write against a read only replica.")
+        }
+        Errors::InvalidScanCursor => RespValue::error("ERR invalid cursor"),
+        Errors::WrongType => {
+            RespValue::error("WRONGTYPE Operation against a key holding the wrong kind of value")
+        }
+        e => RespValue::Error(format!("ERR {}", e)),
+    }
+}
+
+fn wrong_args(name: &str) -> RespValue {
+    RespValue::Error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        name.to_lowercase()
+    ))
+}
+
+fn syntax_error() -> RespValue {
+    RespValue::error("ERR syntax error")
+}
+
+fn not_integer() -> RespValue {
+    RespValue::error("ERR value is not an integer or out of range")
+}
+
+fn parse_int(arg: &[u8]) -> Option<i64> {
+    std::str::from_utf8(arg).ok()?.parse::<i64>().ok()
+}
+
+fn bulk_or_null(value: Option<Bytes>) -> RespValue {
+    match value {
+        Some(value) => RespValue::Bulk(value),
+        None => RespValue::Null,
+    }
+}
+
+fn key_array(keys: Vec<Bytes>) -> RespValue {
+    RespValue::Array(keys.into_iter().map(RespValue::Bulk).collect())
+}
+
+fn parse_score(arg: &[u8]) -> Option<f64> {
+    std::str::from_utf8(arg)
+        .ok()?
+        .parse::<f64>()
+        .ok()
+        .filter(|score| !score.is_nan())
+}
+
+fn score_reply(score: f64) -> RespValue {
+    RespValue::Bulk(Bytes::from(score.to_string()))
+}
+
+fn pairs(args: &[Bytes]) -> Vec<(Bytes, Bytes)> {
+    args.chunks(2)
+        .map(|kv| (kv[0].clone(), kv[1].clone()))
+        .collect()
+}
+
+fn execute(store: &RedisStore, conn: &mut Connection, args: &[Bytes]) -> RespValue {
+    let name = String::from_utf8_lossy(&args[0]).to_uppercase();
+    let res = match name.as_str() {
+        "PING" => match args.len() {
+            1 => Ok(RespValue::Simple("PONG".to_string())),
+            2 => Ok(RespValue::Bulk(args[1].clone())),
+            _ => Ok(wrong_args(&name)),
+        },
+        "ECHO" => match args.len() {
+            2 => Ok(RespValue::Bulk(args[1].clone())),
+            _ => Ok(wrong_args(&name)),
+        },
+        "HELLO" => Ok(hello(conn, args)),
+        "SELECT" => match args.len() {
+            2 if args[1].as_ref() == b"0" => Ok(RespValue::ok()),
+            2 => Ok(RespValue::error("ERR DB index is out of range")),
+            _ => Ok(wrong_args(&name)),
+        },
+        "QUIT" => {
+            conn.quit = true;
+            Ok(RespValue::ok())
+        }
+        // redis-cli启动时会查询命令的文档
+        "COMMAND" => Ok(RespValue::Array(Vec::new())),
+        "GET" => match args.len() {
+            2 => store.get(&args[1]).map(bulk_or_null),
+            _ => Ok(wrong_args(&name)),
+        },
+        "SET" => set(store, args),
+        "DEL" if args.len() > 1 => store.del(&args[1..]).map(RespValue::Integer),
+        "EXISTS" if args.len() > 1 => store.exists(&args[1..]).map(RespValue::Integer),
+        "MGET" if args.len() > 1 => store
+            .mget(&args[1..])
+            .map(|values| RespValue::Array(values.into_iter().map(bulk_or_null).collect())),
+        "MSET" if args.len() > 1 && args.len() % 2 == 1 => {
+            store.mset(&pairs(&args[1..])).map(|_| RespValue::ok())
+        }
+        "DEL" | "EXISTS" | "MGET" | "MSET" => Ok(wrong_args(&name)),
+        "SCAN" => scan(store, args),
+        "KEYS" => match args.len() {
+            2 => store.keys(&args[1]).map(key_array),
+            _ => Ok(wrong_args(&name)),
+        },
+        "TTL" | "PTTL" => match args.len() {
+            2 => store
+                .pttl(&args[1])
+                .map(|pttl| match (name.as_str(), pttl) {
+                    ("TTL", pttl) if pttl > 0 => RespValue::Integer((pttl + 500) / 1000),
+                    (_, pttl) => RespValue::Integer(pttl),
+                }),
+            _ => Ok(wrong_args(&name)),
+        },
+        "TYPE" => match args.len() {
+            2 => store.key_type(&args[1]).map(|redis_type| {
+                RespValue::Simple(redis_type.map_or("none", |t| t.name()).to_string())
+            }),
+            _ => Ok(wrong_args(&name)),
+        },
+        "HSET" if args.len() > 2 && args.len().is_multiple_of(2) => store
+            .hset(&args[1], &pairs(&args[2..]))
+            .map(RespValue::Integer),
+        "HGET" if args.len() == 3 => store.hget(&args[1], &args[2]).map(bulk_or_null),
+        "HDEL" if args.len() > 2 => store.hdel(&args[1], &args[2..]).map(RespValue::Integer),
+        "HGETALL" if args.len() == 2 => store.hgetall(&args[1]).map(|fvs| {
+            RespValue::Map(
+                fvs.into_iter()
+                    .map(|(field, value)| (RespValue::Bulk(field), RespValue::Bulk(value)))
+                    .collect(),
+            )
+        }),
+        "SADD" if args.len() > 2 => store.sadd(&args[1], &args[2..]).map(RespValue::Integer),
+        "SREM" if args.len() > 2 => store.srem(&args[1], &args[2..]).map(RespValue::Integer),
+        "SISMEMBER" if args.len() == 3 => store
+            .sismember(&args[1], &args[2])
+            .map(|is_member| RespValue::Integer(is_member as i64)),
+        "SMEMBERS" if args.len() == 2 => store.smembers(&args[1]).map(key_array),
+        "LPUSH" if args.len() > 2 => store.lpush(&args[1], &args[2..]).map(RespValue::Integer),
+        "RPUSH" if args.len() > 2 => store.rpush(&args[1], &args[2..]).map(RespValue::Integer),
+        "LPOP" if args.len() == 2 => store.lpop(&args[1]).map(bulk_or_null),
+        "RPOP" if args.len() == 2 => store.rpop(&args[1]).map(bulk_or_null),
+        "LRANGE" if args.len() == 4 => match (parse_int(&args[2]), parse_int(&args[3])) {
+            (Some(start), Some(stop)) => store.lrange(&args[1], start, stop).map(key_array),
+            _ => Ok(not_integer()),
+        },
+        "ZADD" if args.len() > 2 && args.len().is_multiple_of(2) => zadd(store, args),
+        "ZSCORE" if args.len() == 3 => store
+            .zscore(&args[1], &args[2])
+            .map(|score| score.map_or(RespValue::Null, score_reply)),
+        "ZRANGE" => zrange(store, args),
+        "HSET" | "HGET" | "HDEL" | "HGETALL" | "SADD" | "SREM" | "SISMEMBER" | "SMEMBERS"
+        | "LPUSH" | "RPUSH" | "LPOP" | "RPOP" | "LRANGE" | "ZADD" | "ZSCORE" => {
+            Ok(wrong_args(&name))
+        }
+        _ => Ok(RespValue::Error(format!(
+            "ERR unknown command '{}'",
+            String::from_utf8_lossy(&args[0])
+        ))),
+    };
+    res.unwrap_or_else(error_reply)
+}
+
+// HELLO [protover],切换协议版本并返回服务的信息
+fn hello(conn: &mut Connection, args: &[Bytes]) -> RespValue {
+    if args.len() > 2 {
+        return syntax_error();
+    }
+    if args.len() == 2 {
+        match parse_int(&args[1]) {
+            Some(protocol) if protocol == 2 || protocol == 3 => conn.protocol = protocol as u8,
+            Some(_) => return RespValue::error("NOPROTO unsupported protocol version"),
+            None => {
+                return RespValue::error("ERR Protocol version is not an integer or out of range")
+            }
+        }
+    }
+    RespValue::Map(vec![
+        (RespValue::bulk("server"), RespValue::bulk("bitcask_kv")),
+        (
+            RespValue::bulk("version"),
+            RespValue::bulk(env!("CARGO_PKG_VERSION")),
+        ),
+        (
+            RespValue::bulk("proto"),
+            RespValue::Integer(conn.protocol as i64),
+        ),
+        (RespValue::bulk("mode"), RespValue::bulk("standalone")),
+        (RespValue::bulk("role"), RespValue::bulk("master")),
+        (RespValue::bulk("modules"), RespValue::Array(Vec::new())),
+    ])
+}
+
+// SET key value [NX|XX] [EX seconds|PX milliseconds|KEEPTTL]
+fn set(store: &RedisStore, args: &[Bytes]) -> Result<RespValue> {
+    if args.len() < 3 {
+        return Ok(wrong_args("set"));
+    }
+    let mut options = SetOptions::default();
+    let mut idx = 3;
+    while idx < args.len() {
+        let option = String::from_utf8_lossy(&args[idx]).to_uppercase();
+        match option.as_str() {
+            "NX" | "XX" if options.condition == SetCondition::Always => {
+                options.condition = match option.as_str() {
+                    "NX" => SetCondition::IfAbsent,
+                    _ => SetCondition::IfPresent,
+                };
+            }
+            "KEEPTTL" if options.expire.is_none() => options.keep_ttl = true,
+            "EX" | "PX" if options.expire.is_none() && !options.keep_ttl => {
+                idx += 1;
+                let n = match args.get(idx) {
+                    Some(arg) => parse_int(arg),
+                    None => return Ok(syntax_error()),
+                };
+                let n = match n {
+                    Some(n) if n > 0 => n as u64,
+                    Some(_) => {
+                        return Ok(RespValue::error("ERR invalid expire time in 'set' command"))
+                    }
+                    None => return Ok(not_integer()),
+                };
+                options.expire = Some(match option.as_str() {
+                    "EX" => Duration::from_secs(n),
+                    _ => Duration::from_millis(n),
+                });
+            }
+            _ => return Ok(syntax_error()),
+        }
+        idx += 1;
+    }
+    match store.set(args[1].clone(), args[2].clone(), options)? {
+        true => Ok(RespValue::ok()),
+        false => Ok(RespValue::Null),
+    }
+}
+
+// ZADD key score member [score member ...]
+fn zadd(store: &RedisStore, args: &[Bytes]) -> Result<RespValue> {
+    let mut members = Vec::with_capacity(args.len() / 2 - 1);
+    for pair in args[2..].chunks(2) {
+        match parse_score(&pair[0]) {
+            Some(score) => members.push((score, pair[1].clone())),
+            None => return Ok(RespValue::error("ERR value is not a valid float")),
+        }
+    }
+    store.zadd(&args[1], &members).map(RespValue::Integer)
+}
+
+// ZRANGE key start stop [WITHSCORES]
+fn zrange(store: &RedisStore, args: &[Bytes]) -> Result<RespValue> {
+    let with_scores = match args.len() {
+        4 => false,
+        5 if args[4].eq_ignore_ascii_case(b"WITHSCORES") => true,
+        5 => return Ok(syntax_error()),
+        _ => return Ok(wrong_args("zrange")),
+    };
+    let (start, stop) = match (parse_int(&args[2]), parse_int(&args[3])) {
+        (Some(start), Some(stop)) => (start, stop),
+        _ => return Ok(not_integer()),
+    };
+    let mut values = Vec::new();
+    for (member, score) in store.zrange(&args[1], start, stop)? {
+        values.push(RespValue::Bulk(member));
+        if with_scores {
+            values.push(score_reply(score));
+        }
+    }
+    Ok(RespValue::Array(values))
+}
+
+// SCAN cursor [MATCH pattern] [COUNT count]
+fn scan(store: &RedisStore, args: &[Bytes]) -> Result<RespValue> {
+    if args.len() < 2 {
+        return Ok(wrong_args("scan"));
+    }
+    let mut pattern = None;
+    let mut count = DEFAULT_SCAN_COUNT;
+    let mut idx = 2;
+    while idx + 1 < args.len() {
+        match String::from_utf8_lossy(&args[idx]).to_uppercase().as_str() {
+            "MATCH" => pattern = Some(args[idx + 1].clone()),
+            "COUNT" => match parse_int(&args[idx + 1]) {
+                Some(n) if n > 0 => count = n as usize,
+                Some(_) => return Ok(syntax_error()),
+                None => return Ok(not_integer()),
+            },
+            _ => return Ok(syntax_error()),
+        }
+        idx += 2;
+    }
+    if idx != args.len() {
+        return Ok(syntax_error());
+    }
+    let (next_cursor, keys) = store.scan(&args[1], pattern.as_deref(), count)?;
+    Ok(RespValue::Array(vec![
+        RespValue::Bulk(next_cursor),
+        key_array(keys),
+    ]))
+}
+
+#[cfg(test)]
+mod test_resp_server {
+    use std::io::{BufReader, Write};
+    use std::net::TcpStream;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use super::RespServer;
+    use crate::{
+        db::Engine,
+        options::Options,
+        redis::{
+            resp::{read_value, RespValue},
+            RedisStore,
+        },
+    };
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(server: &RespServer) -> Client {
+            let stream = TcpStream::connect(server.local_addr()).unwrap();
+            Client {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+            }
+        }
+
+        fn call(&mut self, args: &[&str]) -> RespValue {
+            let request = RespValue::Array(args.iter().map(|arg| RespValue::bulk(arg)).collect());
+            let mut buf = Vec::new();
+            request.encode(2, &mut buf);
+            self.writer.write_all(&buf).unwrap();
+            read_value(&mut self.reader).unwrap().unwrap()
+        }
+    }
+
+    fn bulk(s: &str) -> RespValue {
+        RespValue::Bulk(Bytes::from(s.to_string()))
+    }
+
+    #[test]
+    fn test_resp_server() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-resp-server");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let server = RespServer::start(Arc::new(RedisStore::new(engine)), "127.0.0.1:0").unwrap();
+        let mut client = Client::connect(&server);
+
+        assert_eq!(
+            client.call(&["PING"]),
+            RespValue::Simple("PONG".to_string())
+        );
+        assert_eq!(client.call(&["SET", "k1", "v1"]), RespValue::ok());
+        assert_eq!(client.call(&["SET", "k1", "v2", "NX"]), RespValue::Null);
+        assert_eq!(
+            client.call(&["SET", "k2", "v2", "EX", "100"]),
+            RespValue::ok()
+        );
+        assert_eq!(client.call(&["GET", "k1"]), bulk("v1"));
+        assert_eq!(client.call(&["GET", "missing"]), RespValue::Null);
+        assert_eq!(client.call(&["TTL", "k2"]), RespValue::Integer(100));
+        assert_eq!(client.call(&["TTL", "k1"]), RespValue::Integer(-1));
+        assert_eq!(
+            client.call(&["SET", "k3", "v", "EX", "0"]),
+            RespValue::error("ERR invalid expire time in 'set' command")
+        );
+        assert_eq!(
+            client.call(&["SET", "k3", "v", "BAD"]),
+            RespValue::error("ERR syntax error")
+        );
+        assert_eq!(client.call(&["MSET", "a", "1", "b", "2"]), RespValue::ok());
+        assert_eq!(
+            client.call(&["MGET", "a", "missing", "b"]),
+            RespValue::Array(vec![bulk("1"), RespValue::Null, bulk("2")])
+        );
+        assert_eq!(
+            client.call(&["MSET", "a"]),
+            RespValue::error("ERR wrong number of arguments for 'mset' command")
+        );
+        assert_eq!(
+            client.call(&["EXISTS", "a", "b", "c"]),
+            RespValue::Integer(2)
+        );
+        assert_eq!(
+            client.call(&["KEYS", "k*"]),
+            RespValue::Array(vec![bulk("k1"), bulk("k2")])
+        );
+        assert_eq!(
+            client.call(&["SCAN", "0", "MATCH", "?", "COUNT", "100"]),
+            RespValue::Array(vec![
+                bulk("0"),
+                RespValue::Array(vec![bulk("a"), bulk("b")])
+            ])
+        );
+        assert_eq!(
+            client.call(&["TYPE", "a"]),
+            RespValue::Simple("string".to_string())
+        );
+        assert_eq!(client.call(&["DEL", "a", "b", "c"]), RespValue::Integer(2));
+        assert!(matches!(client.call(&["NOSUCH"]), RespValue::Error(_)));
+
+        // RESP3中null的编码不同
+        assert!(matches!(client.call(&["HELLO", "3"]), RespValue::Map(_)));
+        assert_eq!(client.call(&["GET", "missing"]), RespValue::Null);
+
+        // pipeline和inline命令
+        client.writer.write_all(b"PING\r\nECHO hi\r\n").unwrap();
+        assert_eq!(
+            read_value(&mut client.reader).unwrap().unwrap(),
+            RespValue::Simple("PONG".to_string())
+        );
+        assert_eq!(read_value(&mut client.reader).unwrap().unwrap(), bulk("hi"));
+
+        // 多个连接并发写入
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let mut client = Client::connect(&server);
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        let key = format!("t{}-{}", t, i);
+                        assert_eq!(client.call(&["SET", &key, "v"]), RespValue::ok());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(
+            client.call(&["KEYS", "t*"]),
+            RespValue::Array(
+                (0..8)
+                    .flat_map(|t| {
+                        let mut keys: Vec<String> =
+                            (0..100).map(|i| format!("t{}-{}", t, i)).collect();
+                        keys.sort();
+                        keys
+                    })
+                    .map(|key| bulk(&key))
+                    .collect()
+            )
+        );
+
+        // 数据结构的命令,当前连接使用RESP3
+        assert_eq!(
+            client.call(&["HSET", "h", "f1", "v1", "f2", "v2"]),
+            RespValue::Integer(2)
+        );
+        assert_eq!(client.call(&["HGET", "h", "f1"]), bulk("v1"));
+        assert_eq!(
+            client.call(&["HGETALL", "h"]),
+            RespValue::Map(vec![(bulk("f1"), bulk("v1")), (bulk("f2"), bulk("v2"))])
+        );
+        assert_eq!(client.call(&["SADD", "s", "a", "b"]), RespValue::Integer(2));
+        assert_eq!(client.call(&["SISMEMBER", "s", "a"]), RespValue::Integer(1));
+        assert_eq!(
+            client.call(&["RPUSH", "l", "a", "b", "c"]),
+            RespValue::Integer(3)
+        );
+        assert_eq!(client.call(&["LPOP", "l"]), bulk("a"));
+        assert_eq!(
+            client.call(&["LRANGE", "l", "0", "-1"]),
+            RespValue::Array(vec![bulk("b"), bulk("c")])
+        );
+        assert_eq!(
+            client.call(&["ZADD", "z", "2", "b", "1.5", "a"]),
+            RespValue::Integer(2)
+        );
+        assert_eq!(client.call(&["ZSCORE", "z", "a"]), bulk("1.5"));
+        assert_eq!(
+            client.call(&["ZRANGE", "z", "0", "-1", "WITHSCORES"]),
+            RespValue::Array(vec![bulk("a"), bulk("1.5"), bulk("b"), bulk("2")])
+        );
+        assert_eq!(
+            client.call(&["TYPE", "z"]),
+            RespValue::Simple("zset".to_string())
+        );
+        assert!(
+            matches!(client.call(&["GET", "h"]), RespValue::Error(e) if e.starts_with("WRONGTYPE"))
+        );
+        assert_eq!(
+            client.call(&["DEL", "h", "s", "l", "z"]),
+            RespValue::Integer(4)
+        );
+        assert_eq!(client.call(&["QUIT"]), RespValue::ok());
+        drop(client);
+        drop(server);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
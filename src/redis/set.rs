@@ -0,0 +1,127 @@
+use bytes::Bytes;
+
+use super::{RedisStore, RedisType, MEMBER_KEY_PREFIX};
+use crate::{
+    errors::{Errors, Result},
+    options::WriteBatchOptions,
+};
+
+impl RedisStore {
+    // 添加多个元素,返回新增的元素个数
+    pub fn sadd(&self, key: &[u8], members: &[Bytes]) -> Result<i64> {
+        if key.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        let _guard = self.lock_key(key);
+        let mut set = self.get_or_new_collection(key, RedisType::Set)?;
+        let write_batch = self.engine.new_write_batch(WriteBatchOptions::default())?;
+        let mut added = 0;
+        for member in members {
+            let member_key = set.member_key(key, member);
+            match write_batch.get(member_key.clone()) {
+                Ok(_) => continue,
+                Err(Errors::KeyNotFound) => added += 1,
+                Err(e) => return Err(e),
+            }
+            // 元素本身就在key中,value为空
+            write_batch.put(member_key, Bytes::new())?;
+        }
+        set.size += added;
+        self.put_collection(&write_batch, key, &set)?;
+        write_batch.commit()?;
+        Ok(added as i64)
+    }
+
+    // 删除多个元素,返回删除的元素个数
+    pub fn srem(&self, key: &[u8], members: &[Bytes]) -> Result<i64> {
+        let _guard = self.lock_key(key);
+        let mut set = match self.get_collection(key, RedisType::Set)? {
+            Some(set) => set,
+            None => return Ok(0),
+        };
+        let write_batch = self.engine.new_write_batch(WriteBatchOptions::default())?;
+        let mut removed = 0;
+        for member in members {
+            let member_key = set.member_key(key, member);
+            match write_batch.get(member_key.clone()) {
+                Ok(_) => removed += 1,
+                Err(Errors::KeyNotFound) => continue,
+                Err(e) => return Err(e),
+            }
+            write_batch.delete(member_key)?;
+        }
+        set.size -= removed;
+        self.put_collection(&write_batch, key, &set)?;
+        write_batch.commit()?;
+        Ok(removed as i64)
+    }
+
+    pub fn sismember(&self, key: &[u8], member: &[u8]) -> Result<bool> {
+        let set = match self.get_collection(key, RedisType::Set)? {
+            Some(set) => set,
+            None => return Ok(false),
+        };
+        match self.engine.get(set.member_key(key, member)) {
+            Ok(_) => Ok(true),
+            Err(Errors::KeyNotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    // 按照字典序返回所有的元素
+    pub fn smembers(&self, key: &[u8]) -> Result<Vec<Bytes>> {
+        let set = match self.get_collection(key, RedisType::Set)? {
+            Some(set) => set,
+            None => return Ok(Vec::new()),
+        };
+        let members = self.scan_members(&set.member_prefix(MEMBER_KEY_PREFIX, key))?;
+        Ok(members.into_iter().map(|(member, _)| member).collect())
+    }
+}
+
+#[cfg(test)]
+mod test_redis_set {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use crate::{db::Engine, options::Options, redis::RedisStore};
+
+    #[test]
+    fn test_redis_set() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-redis-set");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let store = RedisStore::new(engine.clone());
+        let members =
+            |ms: &[&str]| -> Vec<Bytes> { ms.iter().map(|m| Bytes::from(m.to_string())).collect() };
+
+        assert_eq!(store.sadd(b"tags", &members(&["b", "a", "b"])).unwrap(), 2);
+        assert_eq!(store.sadd(b"tags", &members(&["a", "c"])).unwrap(), 1);
+        assert!(store.sismember(b"tags", b"a").unwrap());
+        assert!(!store.sismember(b"tags", b"d").unwrap());
+        assert_eq!(store.smembers(b"tags").unwrap(), members(&["a", "b", "c"]));
+        assert_eq!(store.srem(b"tags", &members(&["a", "d"])).unwrap(), 1);
+        assert_eq!(store.smembers(b"tags").unwrap(), members(&["b", "c"]));
+
+        // 大集合的删除只删除元数据
+        let many: Vec<Bytes> = (0..3000).map(|i| Bytes::from(format!("m{}", i))).collect();
+        store.sadd(b"big", &many).unwrap();
+        assert_eq!(store.smembers(b"big").unwrap().len(), 3000);
+        assert_eq!(store.del(&members(&["big"])).unwrap(), 1);
+        assert!(store.smembers(b"big").unwrap().is_empty());
+        assert!(!store.sismember(b"big", b"m1").unwrap());
+
+        // 重启之后数据不变
+        drop(store);
+        drop(engine);
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let store = RedisStore::new(engine);
+        assert_eq!(store.smembers(b"tags").unwrap(), members(&["b", "c"]));
+        store.sadd(b"big", &members(&["x"])).unwrap();
+        assert_eq!(store.smembers(b"big").unwrap(), members(&["x"]));
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
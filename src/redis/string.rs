@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::{meta_key, now_millis, Metadata, RedisStore, RedisType};
+use crate::{
+    errors::{Errors, Result},
+    options::WriteBatchOptions,
+};
+
+// SET写入的条件
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SetCondition {
+    #[default]
+    Always,
+    // NX,key不存在时才写入
+    IfAbsent,
+    // XX,key存在时才写入
+    IfPresent,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SetOptions {
+    // 过期时间,None表示不过期
+    pub expire: Option<Duration>,
+    pub condition: SetCondition,
+    // KEEPTTL,保留key原来的过期时间
+    pub keep_ttl: bool,
+}
+
+impl RedisStore {
+    // 读取字符串,key不存在时返回None,不是字符串时返回WrongType
+    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        match self.get_metadata(key)? {
+            Some(metadata) if metadata.redis_type == RedisType::String => {
+                Ok(Some(metadata.payload))
+            }
+            Some(_) => Err(Errors::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    // 写入字符串,会覆盖其他类型的值,条件不满足时返回false
+    pub fn set(&self, key: Bytes, value: Bytes, options: SetOptions) -> Result<bool> {
+        if key.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        let _guard = self.lock_key(&key);
+        let expire_at = options
+            .expire
+            .map_or(0, |expire| now_millis() + expire.as_millis() as u64);
+        let new_metadata = |expire_at| {
+            Metadata {
+                redis_type: RedisType::String,
+                expire_at,
+                payload: value.clone(),
+            }
+            .encode()
+        };
+        if options.condition == SetCondition::Always && !options.keep_ttl {
+            self.engine.put(meta_key(&key), new_metadata(expire_at))?;
+            return Ok(true);
+        }
+        // 需要根据当前的值决定是否写入,持有key的锁,读取和写入之间没有其他写入
+        let current = self.get_metadata(&key)?;
+        let allowed = match options.condition {
+            SetCondition::Always => true,
+            SetCondition::IfAbsent => current.is_none(),
+            SetCondition::IfPresent => current.is_some(),
+        };
+        if !allowed {
+            return Ok(false);
+        }
+        let expire_at = match (&current, options.keep_ttl) {
+            (Some(current), true) => current.expire_at,
+            _ => expire_at,
+        };
+        self.engine.put(meta_key(&key), new_metadata(expire_at))?;
+        Ok(true)
+    }
+
+    // 批量读取字符串,结果和keys一一对应,不是字符串的key返回None
+    pub fn mget(&self, keys: &[Bytes]) -> Result<Vec<Option<Bytes>>> {
+        let meta_keys: Vec<Bytes> = keys.iter().map(|key| meta_key(key)).collect();
+        let now = now_millis();
+        let mut values = Vec::with_capacity(keys.len());
+        for res in self.engine.multi_get(&meta_keys) {
+            let value = match res? {
+                Some(raw) => Some(Metadata::decode(raw)?)
+                    .filter(|m| m.redis_type == RedisType::String && !m.is_expired(now))
+                    .map(|m| m.payload),
+                None => None,
+            };
+            values.push(value);
+        }
+        Ok(values)
+    }
+
+    // 原子地写入多个字符串,之前的过期时间全部清除
+    pub fn mset(&self, kvs: &[(Bytes, Bytes)]) -> Result<()> {
+        let keys: Vec<Bytes> = kvs.iter().map(|(key, _)| key.clone()).collect();
+        let _guards = self.lock_keys(&keys);
+        let write_batch = self.engine.new_write_batch(WriteBatchOptions::default())?;
+        for (key, value) in kvs {
+            if key.is_empty() {
+                return Err(Errors::KeyEmptyErr);
+            }
+            let metadata = Metadata {
+                redis_type: RedisType::String,
+                expire_at: 0,
+                payload: value.clone(),
+            };
+            write_batch.put(meta_key(key), metadata.encode())?;
+        }
+        write_batch.commit()
+    }
+}
+
+#[cfg(test)]
+mod test_redis_string {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::{SetCondition, SetOptions};
+    use crate::{db::Engine, options::Options, redis::RedisStore};
+
+    #[test]
+    fn test_redis_string() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-redis-string");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let store = RedisStore::new(engine);
+        let (k1, k2) = (Bytes::from("k1"), Bytes::from("k2"));
+
+        assert_eq!(store.get(&k1).unwrap(), None);
+        let nx = SetOptions {
+            condition: SetCondition::IfAbsent,
+            ..Default::default()
+        };
+        let xx = SetOptions {
+            condition: SetCondition::IfPresent,
+            ..Default::default()
+        };
+        assert!(!store
+            .set(k1.clone(), Bytes::from("v0"), xx.clone())
+            .unwrap());
+        assert!(store
+            .set(k1.clone(), Bytes::from("v1"), nx.clone())
+            .unwrap());
+        assert!(!store
+            .set(k1.clone(), Bytes::from("v2"), nx.clone())
+            .unwrap());
+        assert!(store.set(k1.clone(), Bytes::from("v3"), xx).unwrap());
+        assert_eq!(store.get(&k1).unwrap(), Some(Bytes::from("v3")));
+
+        // 带过期时间写入,KEEPTTL保留过期时间
+        let ex = SetOptions {
+            expire: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        store.set(k2.clone(), Bytes::from("v1"), ex).unwrap();
+        let keep_ttl = SetOptions {
+            keep_ttl: true,
+            ..Default::default()
+        };
+        store.set(k2.clone(), Bytes::from("v2"), keep_ttl).unwrap();
+        assert_eq!(store.get(&k2).unwrap(), Some(Bytes::from("v2")));
+        let pttl = store.pttl(&k2).unwrap();
+        assert!(pttl > 0 && pttl <= 50);
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(store.get(&k2).unwrap(), None);
+        // 过期之后NX可以写入
+        assert!(store.set(k2.clone(), Bytes::from("v3"), nx).unwrap());
+        assert_eq!(store.pttl(&k2).unwrap(), -1);
+
+        store
+            .mset(&[
+                (k1.clone(), Bytes::from("m1")),
+                (Bytes::from("k3"), Bytes::from("m3")),
+            ])
+            .unwrap();
+        assert_eq!(
+            store
+                .mget(&[k1, Bytes::from("missing"), Bytes::from("k3")])
+                .unwrap(),
+            vec![Some(Bytes::from("m1")), None, Some(Bytes::from("m3"))]
+        );
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,174 @@
+use bytes::Bytes;
+
+use super::{list::normalize_range, Collection, RedisStore, RedisType, SCORE_KEY_PREFIX};
+use crate::{
+    errors::{Errors, Result},
+    options::WriteBatchOptions,
+};
+
+// 把分数编码成按照字节比较时和数值顺序一致的8个字节
+// 正数翻转符号位,负数翻转所有的位
+fn encode_score(score: f64) -> [u8; 8] {
+    let bits = score.to_bits();
+    let bits = match bits >> 63 {
+        0 => bits | (1 << 63),
+        _ => !bits,
+    };
+    bits.to_be_bytes()
+}
+
+fn decode_score(buf: &[u8]) -> f64 {
+    let bits = u64::from_be_bytes(buf[..8].try_into().unwrap());
+    let bits = match bits >> 63 {
+        1 => bits & !(1 << 63),
+        _ => !bits,
+    };
+    f64::from_bits(bits)
+}
+
+// 按照分数排序的索引key: | 索引前缀 | 分数 | 元素 |,value为空
+fn score_key(zset: &Collection, key: &[u8], score: f64, member: &[u8]) -> Bytes {
+    let mut buf = zset.member_prefix(SCORE_KEY_PREFIX, key);
+    buf.extend_from_slice(&encode_score(score));
+    buf.extend_from_slice(member);
+    Bytes::from(buf)
+}
+
+impl RedisStore {
+    // 添加元素或者更新元素的分数,返回新增的元素个数
+    // 每个元素有两个key,元素key的value是分数,另外按照分数排序的索引key用来按照排名读取
+    pub fn zadd(&self, key: &[u8], members: &[(f64, Bytes)]) -> Result<i64> {
+        if key.is_empty() {
+            return Err(Errors::KeyEmptyErr);
+        }
+        let _guard = self.lock_key(key);
+        let mut zset = self.get_or_new_collection(key, RedisType::ZSet)?;
+        let write_batch = self.engine.new_write_batch(WriteBatchOptions::default())?;
+        let mut added = 0;
+        for (score, member) in members {
+            let member_key = zset.member_key(key, member);
+            match write_batch.get(member_key.clone()) {
+                Ok(old) => write_batch.delete(score_key(&zset, key, decode_score(&old), member))?,
+                Err(Errors::KeyNotFound) => added += 1,
+                Err(e) => return Err(e),
+            }
+            write_batch.put(member_key, Bytes::copy_from_slice(&encode_score(*score)))?;
+            write_batch.put(score_key(&zset, key, *score, member), Bytes::new())?;
+        }
+        zset.size += added;
+        self.put_collection(&write_batch, key, &zset)?;
+        write_batch.commit()?;
+        Ok(added as i64)
+    }
+
+    pub fn zscore(&self, key: &[u8], member: &[u8]) -> Result<Option<f64>> {
+        let zset = match self.get_collection(key, RedisType::ZSet)? {
+            Some(zset) => zset,
+            None => return Ok(None),
+        };
+        match self.engine.get(zset.member_key(key, member)) {
+            Ok(score) => Ok(Some(decode_score(&score))),
+            Err(Errors::KeyNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // 按照分数从小到大返回排名在[start, stop]范围内的元素和分数,分数相同时按照元素的字典序
+    pub fn zrange(&self, key: &[u8], start: i64, stop: i64) -> Result<Vec<(Bytes, f64)>> {
+        let zset = match self.get_collection(key, RedisType::ZSet)? {
+            Some(zset) => zset,
+            None => return Ok(Vec::new()),
+        };
+        let (start, stop) = match normalize_range(start, stop, zset.size) {
+            Some(range) => range,
+            None => return Ok(Vec::new()),
+        };
+        let prefix = Bytes::from(zset.member_prefix(SCORE_KEY_PREFIX, key));
+        let kvs = self
+            .engine
+            .scan_prefix(prefix.clone(), prefix.clone(), (stop + 1) as usize)?;
+        Ok(kvs
+            .into_iter()
+            .skip(start as usize)
+            .map(|(score_key, _)| {
+                let encoded = &score_key[prefix.len()..];
+                (score_key.slice(prefix.len() + 8..), decode_score(encoded))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test_redis_zset {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use super::{decode_score, encode_score};
+    use crate::{db::Engine, options::Options, redis::RedisStore};
+
+    #[test]
+    fn test_redis_zset() {
+        let scores = [
+            f64::NEG_INFINITY,
+            -10.5,
+            -1.0,
+            -0.0,
+            0.0,
+            0.25,
+            3.0,
+            1e100,
+            f64::INFINITY,
+        ];
+        for pair in scores.windows(2) {
+            assert!(encode_score(pair[0]) <= encode_score(pair[1]));
+            assert_eq!(decode_score(&encode_score(pair[0])), pair[0]);
+        }
+
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-redis-zset");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let store = RedisStore::new(engine);
+        let m = |s: &str| Bytes::from(s.to_string());
+
+        assert_eq!(
+            store
+                .zadd(
+                    b"board",
+                    &[(30.0, m("carol")), (10.0, m("alice")), (-5.0, m("bob"))]
+                )
+                .unwrap(),
+            3
+        );
+        // 更新分数,同一次调用中重复的元素以最后一个为准
+        assert_eq!(
+            store
+                .zadd(
+                    b"board",
+                    &[(50.0, m("alice")), (20.0, m("dave")), (25.0, m("dave"))]
+                )
+                .unwrap(),
+            1
+        );
+        assert_eq!(store.zscore(b"board", b"alice").unwrap(), Some(50.0));
+        assert_eq!(store.zscore(b"board", b"dave").unwrap(), Some(25.0));
+        assert_eq!(store.zscore(b"board", b"nobody").unwrap(), None);
+        assert_eq!(
+            store.zrange(b"board", 0, -1).unwrap(),
+            vec![
+                (m("bob"), -5.0),
+                (m("dave"), 25.0),
+                (m("carol"), 30.0),
+                (m("alice"), 50.0)
+            ]
+        );
+        assert_eq!(
+            store.zrange(b"board", -2, -1).unwrap(),
+            vec![(m("carol"), 30.0), (m("alice"), 50.0)]
+        );
+        assert!(store.zrange(b"board", 5, 10).unwrap().is_empty());
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
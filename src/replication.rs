@@ -0,0 +1,1226 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use bytes::{Buf, BufMut};
+use log::error;
+use parking_lot::{Condvar, Mutex, RwLockWriteGuard};
+use prost::encoding::{decode_varint, encode_varint};
+
+use crate::{
+    backup::sync_dir,
+    data::data_file::{DataFile, HIT_FILE_NAME, MERGE_FINISHED_FILE_NAME},
+    db::{replay_data_files, Engine, LogReplayer},
+    errors::{Errors, Result},
+    merge::get_merge_dirpath,
+    tcp_server::TcpServer,
+};
+
+// 一次同步默认最多传输的日志字节数
+pub const DEFAULT_FETCH_MAX_BYTES: u64 = 4 * 1024 * 1024;
+
+// 一次同步最多传输的日志字节数,follower请求的更多时按这个值处理
+pub const MAX_FETCH_BYTES: u64 = 64 * 1024 * 1024;
+
+// tcp传输中一个消息的最大长度
+pub const MAX_FRAME_SIZE: usize = 256 * 1024 * 1024;
+
+// 副本的回放状态,没有读到TXN_FIN的批量提交需要等后面的日志同步过来
+pub(crate) struct ReplicaState {
+    pub(crate) replayer: LogReplayer,
+    // 正在同步的merge之后的文件,重启后merge目录会被清理,从头开始同步
+    snapshot: Option<SnapshotProgress>,
+}
+
+impl ReplicaState {
+    pub(crate) fn new() -> Self {
+        ReplicaState {
+            replayer: LogReplayer::new(),
+            snapshot: None,
+        }
+    }
+}
+
+// merge之后需要同步的文件,先同步数据文件,最后同步hint文件
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SnapshotFile {
+    Data(u32),
+    Hint,
+}
+
+// follower已经同步到的merge之后的文件的位置
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SnapshotProgress {
+    pub merge_generation: u32,
+    pub file: SnapshotFile,
+    pub offset: u64,
+}
+
+// follower发给leader的同步请求,位置都来自follower自己的文件,重启后可以直接继续
+#[derive(Clone, Debug, PartialEq)]
+pub struct FetchRequest {
+    // follower当前的merge代数,和leader不一致时需要先同步merge之后的文件
+    pub merge_generation: u32,
+    // follower日志的末尾
+    pub file_id: u32,
+    pub offset: u64,
+    // follower已有的blob文件和大小
+    pub blob_sizes: Vec<(u32, u64)>,
+    // 这次最多传输的字节数,至少会传输一条完整的日志
+    pub max_bytes: u64,
+    // 正在同步merge之后的文件时,已经同步到的位置
+    pub snapshot: Option<SnapshotProgress>,
+}
+
+// blob文件中从offset开始的一段数据
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlobChunk {
+    pub file_id: u32,
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FetchPayload {
+    // 数据文件file_id中从offset开始的完整日志,data为空表示没有新的日志
+    Records {
+        file_id: u32,
+        offset: u64,
+        data: Vec<u8>,
+    },
+    // leader merge过,follower需要替换掉merge过的文件
+    // 每次只传输一个文件中从offset开始的一段,最后一段带上merge完成的标记
+    Snapshot {
+        file: SnapshotFile,
+        offset: u64,
+        data: Vec<u8>,
+        merge_finished: Option<Vec<u8>>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FetchResponse {
+    pub merge_generation: u32,
+    pub payload: FetchPayload,
+    // 在日志之前写入的blob数据,follower先写blob再写日志
+    pub blobs: Vec<BlobChunk>,
+    // 读取日志之前leader上的blob文件,follower追上之后删除其他的blob文件
+    pub blob_file_ids: Vec<u32>,
+    // 是否已经同步到leader持久化的位置
+    pub caught_up: bool,
+}
+
+// 复制的传输方式,follower通过它向leader发送同步请求
+pub trait ReplicationTransport: Send {
+    fn fetch(&mut self, request: &FetchRequest) -> Result<FetchResponse>;
+}
+
+impl Engine {
+    // 是否是只读副本
+    pub fn is_replica(&self) -> bool {
+        self.replica.is_some()
+    }
+
+    // leader处理follower的同步请求,只会发送已经持久化的日志
+    pub fn serve_fetch(&self, request: &FetchRequest) -> Result<FetchResponse> {
+        let merge_generation = Engine::no_merged_file_id(self.options.dir_path.clone())?;
+        if request.merge_generation > merge_generation {
+            return Err(Errors::ReplicationPositionMismatch);
+        }
+        // 要在读取日志之前拿到blob文件列表,列表中没有的文件被删除之前写入的日志一定会被读到
+        let blob_file_ids: Vec<u32> = self
+            .blob_files
+            .file_sizes()
+            .iter()
+            .map(|(file_id, _)| *file_id)
+            .collect();
+        let max_bytes = request.max_bytes.min(MAX_FETCH_BYTES);
+        let (mut payload, mut caught_up) = match request.merge_generation == merge_generation {
+            true => self.read_log_chunk(request, max_bytes)?,
+            false => (
+                self.read_snapshot(merge_generation, request.snapshot, max_bytes)?,
+                false,
+            ),
+        };
+        // 日志引用的blob在日志之前写入,读完日志再读取blob
+        let mut pending_blobs = Vec::new();
+        for (file_id, size) in self.blob_files.file_sizes() {
+            let offset = request
+                .blob_sizes
+                .iter()
+                .find(|(id, _)| *id == file_id)
+                .map_or(0, |(_, size)| *size);
+            if offset < size {
+                pending_blobs.push((file_id, offset, size));
+            }
+        }
+        // 日志和blob共用一份max_bytes,放不下所有需要的blob时这次只同步blob,日志下次再同步
+        // 否则follower可能收到引用了还没有同步的blob的日志
+        let blob_bytes: u64 = pending_blobs
+            .iter()
+            .map(|(_, offset, size)| size - offset)
+            .sum();
+        // 一条日志可能就超过了max_bytes,没有需要同步的blob时照常返回
+        let mut budget = match blob_bytes == 0 || payload_len(&payload) + blob_bytes <= max_bytes {
+            true => max_bytes.saturating_sub(payload_len(&payload)),
+            false => {
+                payload = FetchPayload::Records {
+                    file_id: request.file_id,
+                    offset: request.offset,
+                    data: Vec::new(),
+                };
+                caught_up = false;
+                max_bytes.max(1)
+            }
+        };
+        let mut blobs = Vec::new();
+        for (file_id, offset, size) in pending_blobs {
+            if budget == 0 {
+                break;
+            }
+            let len = (size - offset).min(budget);
+            budget -= len;
+            blobs.push(BlobChunk {
+                file_id,
+                offset,
+                data: self.blob_files.read_bytes(file_id, offset, len as usize)?,
+            });
+        }
+        Ok(FetchResponse {
+            merge_generation,
+            payload,
+            blobs,
+            blob_file_ids,
+            caught_up,
+        })
+    }
+
+    // 从follower的位置开始读取完整的日志
+    fn read_log_chunk(
+        &self,
+        request: &FetchRequest,
+        max_bytes: u64,
+    ) -> Result<(FetchPayload, bool)> {
+        let durable_pos = self.durable_pos();
+        let active_file_read_guard = self.data_file.read();
+        let old_files_read_guard = self.old_files.read();
+        let active_file_id = active_file_read_guard.get_file_id();
+        let mut file_ids: Vec<u32> = old_files_read_guard.keys().copied().collect();
+        file_ids.push(active_file_id);
+        file_ids.sort();
+        let mut file_id = request.file_id;
+        let mut offset = request.offset;
+        loop {
+            let next_file_id = file_ids.iter().find(|id| **id > file_id).copied();
+            let data_file = match old_files_read_guard.get(&file_id) {
+                Some(data_file) => data_file,
+                None if file_id == active_file_id => &*active_file_read_guard,
+                None => {
+                    // follower刚开始写一个leader上没有的文件
+                    if offset != 0 {
+                        return Err(Errors::ReplicationPositionMismatch);
+                    }
+                    match next_file_id {
+                        Some(next_file_id) => {
+                            file_id = next_file_id;
+                            continue;
+                        }
+                        None => return Err(Errors::ReplicationPositionMismatch),
+                    }
+                }
+            };
+            if offset > data_file.get_wtite_offset() {
+                return Err(Errors::ReplicationPositionMismatch);
+            }
+            // 活跃文件只发送持久化过的部分
+            let end = match file_id == active_file_id {
+                true if durable_pos.file_id == file_id => durable_pos.offset,
+                true => 0,
+                false => data_file.get_wtite_offset(),
+            };
+            if offset >= end {
+                match next_file_id {
+                    // 这个文件已经写满了,继续同步下一个文件
+                    Some(next_file_id) if file_id != active_file_id => {
+                        file_id = next_file_id;
+                        offset = 0;
+                        continue;
+                    }
+                    _ => {
+                        let payload = FetchPayload::Records {
+                            file_id,
+                            offset,
+                            data: Vec::new(),
+                        };
+                        return Ok((payload, true));
+                    }
+                }
+            }
+            // 按照日志的边界切分,至少发送一条
+            let mut chunk_end = offset;
+            while chunk_end < end {
+                let (_, key_size, value_size, header_size) =
+                    data_file.read_log_record_header(chunk_end)?;
+                let size = (header_size + key_size + value_size + 4) as u64;
+                if chunk_end > offset && chunk_end + size - offset > max_bytes {
+                    break;
+                }
+                chunk_end += size;
+            }
+            let data = data_file.read_bytes(offset, (chunk_end - offset) as usize)?;
+            let payload = FetchPayload::Records {
+                file_id,
+                offset,
+                data,
+            };
+            return Ok((payload, file_id == active_file_id && chunk_end >= end));
+        }
+    }
+
+    // 从follower同步到的位置开始,读取merge过的数据文件或者hint文件中的一段
+    fn read_snapshot(
+        &self,
+        merge_generation: u32,
+        progress: Option<SnapshotProgress>,
+        max_bytes: u64,
+    ) -> Result<FetchPayload> {
+        let dir_path = self.options.dir_path.clone();
+        let old_files_read_guard = self.old_files.read();
+        let mut files: Vec<SnapshotFile> = old_files_read_guard
+            .keys()
+            .copied()
+            .filter(|file_id| *file_id < merge_generation)
+            .map(SnapshotFile::Data)
+            .collect();
+        files.sort();
+        files.push(SnapshotFile::Hint);
+        // 之后leader又merge过的话,follower从头开始同步
+        let (mut file, mut offset) = match progress {
+            Some(progress) if progress.merge_generation == merge_generation => {
+                (progress.file, progress.offset)
+            }
+            _ => (files[0], 0),
+        };
+        loop {
+            let size = match file {
+                SnapshotFile::Data(file_id) => match old_files_read_guard.get(&file_id) {
+                    Some(data_file) if file_id < merge_generation => data_file.get_wtite_offset(),
+                    _ => return Err(Errors::ReplicationPositionMismatch),
+                },
+                SnapshotFile::Hint => std::fs::metadata(dir_path.join(HIT_FILE_NAME))
+                    .map_or(0, |metadata| metadata.len()),
+            };
+            if offset > size {
+                return Err(Errors::ReplicationPositionMismatch);
+            }
+            let next_file = files.iter().find(|next| **next > file).copied();
+            if let Some(next_file) = next_file {
+                if offset == size {
+                    file = next_file;
+                    offset = 0;
+                    continue;
+                }
+            }
+            let len = (size - offset).min(max_bytes.max(1));
+            let data = match file {
+                SnapshotFile::Data(file_id) => old_files_read_guard
+                    .get(&file_id)
+                    .unwrap()
+                    .read_bytes(offset, len as usize)?,
+                SnapshotFile::Hint => read_file_range(&dir_path.join(HIT_FILE_NAME), offset, len)?,
+            };
+            let merge_finished = match next_file.is_none() && offset + len == size {
+                true => Some(read_file_range(
+                    &dir_path.join(MERGE_FINISHED_FILE_NAME),
+                    0,
+                    u64::MAX,
+                )?),
+                false => None,
+            };
+            return Ok(FetchPayload::Snapshot {
+                file,
+                offset,
+                data,
+                merge_finished,
+            });
+        }
+    }
+
+    // 从leader同步一次,返回是否已经追上leader
+    pub fn replicate_once(
+        &self,
+        transport: &mut dyn ReplicationTransport,
+        max_bytes: u64,
+    ) -> Result<bool> {
+        if !self.is_replica() {
+            return Err(Errors::NotReplica);
+        }
+        let request = {
+            let active_file_read_guard = self.data_file.read();
+            FetchRequest {
+                merge_generation: Engine::no_merged_file_id(self.options.dir_path.clone())?,
+                file_id: active_file_read_guard.get_file_id(),
+                offset: active_file_read_guard.get_wtite_offset(),
+                blob_sizes: self.blob_files.file_sizes(),
+                max_bytes,
+                snapshot: self.replica.as_ref().unwrap().lock().snapshot,
+            }
+        };
+        let response = transport.fetch(&request)?;
+        self.apply_fetch_response(response)
+    }
+
+    // 一直同步到追上leader为止
+    pub fn catch_up(&self, transport: &mut dyn ReplicationTransport) -> Result<()> {
+        while !self.replicate_once(transport, DEFAULT_FETCH_MAX_BYTES)? {}
+        Ok(())
+    }
+
+    fn apply_fetch_response(&self, response: FetchResponse) -> Result<bool> {
+        for blob in response.blobs.iter() {
+            self.blob_files
+                .append_replicated(blob.file_id, blob.offset, &blob.data)?;
+        }
+        match response.payload {
+            FetchPayload::Records {
+                file_id,
+                offset,
+                data,
+            } => self.apply_records(file_id, offset, &data)?,
+            FetchPayload::Snapshot {
+                file,
+                offset,
+                data,
+                merge_finished,
+            } => {
+                let res = self.apply_snapshot_chunk(
+                    response.merge_generation,
+                    file,
+                    offset,
+                    &data,
+                    merge_finished,
+                );
+                // 写入失败后merge目录中的文件不再可信,下次从头开始同步
+                if res.is_err() {
+                    self.replica.as_ref().unwrap().lock().snapshot = None;
+                }
+                res?
+            }
+        }
+        // 追上之后日志中已经没有对被删除的blob文件的引用了
+        if response.caught_up {
+            for file_id in self.blob_files.old_file_ids() {
+                if !response.blob_file_ids.contains(&file_id) {
+                    self.blob_files.remove(file_id)?;
+                }
+            }
+        }
+        Ok(response.caught_up)
+    }
+
+    // 把leader的日志写到相同的文件和位置,然后回放到索引中
+    fn apply_records(&self, file_id: u32, offset: u64, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let mut active_file_write_guard = self.data_file.write();
+        if file_id < active_file_write_guard.get_file_id() {
+            return Err(Errors::ReplicationPositionMismatch);
+        }
+        if file_id > active_file_write_guard.get_file_id() {
+            if offset != 0 {
+                return Err(Errors::ReplicationPositionMismatch);
+            }
+            self.switch_active_file(&mut active_file_write_guard, file_id)?;
+        }
+        if offset != active_file_write_guard.get_wtite_offset() {
+            return Err(Errors::ReplicationPositionMismatch);
+        }
+        active_file_write_guard.write(data)?;
+        self.sync_state.sync(&active_file_write_guard)?;
+        let mut replica = self.replica.as_ref().unwrap().lock();
+        replica
+            .replayer
+            .replay_file(&active_file_write_guard, offset, &mut |entry| {
+                self.apply_replay_entry(entry);
+                true
+            })?;
+        self.seq_no
+            .store(replica.replayer.max_seq_no(), Ordering::SeqCst);
+        Ok(())
+    }
+
+    // 切换到leader上的下一个文件,空的活跃文件直接删除
+    fn switch_active_file(
+        &self,
+        active_file_write_guard: &mut RwLockWriteGuard<DataFile>,
+        file_id: u32,
+    ) -> Result<()> {
+        let dir_path = self.options.dir_path.clone();
+        let old_file_id = active_file_write_guard.get_file_id();
+        self.sync_state.sync(active_file_write_guard)?;
+        let new_data_file = DataFile::new(dir_path.clone(), file_id)?;
+        let old_file = std::mem::replace(&mut **active_file_write_guard, new_data_file);
+        match old_file.get_wtite_offset() {
+            0 => {
+                drop(old_file);
+                if std::fs::remove_file(DataFile::get_file_name(dir_path, old_file_id)).is_err() {
+                    return Err(Errors::FailWriteDataToFile);
+                }
+            }
+            _ => {
+                self.old_files.write().insert(old_file_id, old_file);
+            }
+        }
+        Ok(())
+    }
+
+    // 把leader merge之后的文件的一段写到merge目录,全部同步完之后替换本地merge过的文件
+    // 和merge一样最后写入merge完成的标记,中途宕机的话重启时会清理或者由load_merge_files完成替换
+    fn apply_snapshot_chunk(
+        &self,
+        merge_generation: u32,
+        file: SnapshotFile,
+        offset: u64,
+        data: &[u8],
+        merge_finished: Option<Vec<u8>>,
+    ) -> Result<()> {
+        let dir_path = self.options.dir_path.clone();
+        let merge_dir_path = get_merge_dirpath(dir_path.clone());
+        let progress = self.replica.as_ref().unwrap().lock().snapshot;
+        // 第一段或者leader又merge过,清理掉之前同步的文件
+        if progress.is_none_or(|progress| progress.merge_generation != merge_generation) {
+            if offset != 0 {
+                return Err(Errors::ReplicationPositionMismatch);
+            }
+            if merge_dir_path.is_dir() && std::fs::remove_dir_all(&merge_dir_path).is_err() {
+                return Err(Errors::FailWriteDataToFile);
+            }
+            if let Err(e) = std::fs::create_dir_all(&merge_dir_path) {
+                error!("failed to create merge dir path: {}", e);
+                return Err(Errors::DirPathCreateFailed);
+            }
+        }
+        let file_name = match file {
+            SnapshotFile::Data(file_id) => DataFile::get_file_name(merge_dir_path.clone(), file_id),
+            SnapshotFile::Hint => merge_dir_path.join(HIT_FILE_NAME),
+        };
+        append_file_at(&file_name, offset, data)?;
+        let merge_finished = match merge_finished {
+            Some(merge_finished) => merge_finished,
+            None => {
+                self.replica.as_ref().unwrap().lock().snapshot = Some(SnapshotProgress {
+                    merge_generation,
+                    file,
+                    offset: offset + data.len() as u64,
+                });
+                return Ok(());
+            }
+        };
+        // merge完成的标记最后写入
+        sync_dir(&merge_dir_path)?;
+        let merge_finished_file = DataFile::new_finished_file(merge_dir_path.clone())?;
+        merge_finished_file.write(&merge_finished)?;
+        merge_finished_file.sync()?;
+        sync_dir(&merge_dir_path)?;
+        self.replica.as_ref().unwrap().lock().snapshot = None;
+
+        let mut active_file_write_guard = self.data_file.write();
+        let mut old_files_write_guard = self.old_files.write();
+        old_files_write_guard.retain(|file_id, _| *file_id >= merge_generation);
+        Engine::load_merge_files(dir_path.clone())?;
+        for data_file in DataFile::load_data_files(dir_path.clone())? {
+            if data_file.get_file_id() < merge_generation {
+                old_files_write_guard.insert(data_file.get_file_id(), data_file);
+            }
+        }
+        // 本地的日志都被merge过了,从leader上没有被merge的第一个文件开始同步
+        if active_file_write_guard.get_file_id() < merge_generation {
+            *active_file_write_guard = DataFile::new(dir_path, merge_generation)?;
+        }
+        self.sync_state.sync(&active_file_write_guard)?;
+
+        // 重建索引
+        self.indexer.delete_range(&[], None);
+        self.load_hint_file()?;
+        let mut files: Vec<&DataFile> = old_files_write_guard.values().collect();
+        files.push(&*active_file_write_guard);
+        files.sort_by_key(|file| file.get_file_id());
+        let replayer = replay_data_files(&files, merge_generation, |entry| {
+            self.apply_replay_entry(entry);
+            true
+        })?;
+        self.seq_no.store(replayer.max_seq_no(), Ordering::SeqCst);
+        self.replica.as_ref().unwrap().lock().replayer = replayer;
+        Ok(())
+    }
+}
+
+// 进程内的传输方式,leader在后台线程中处理请求,主要用于测试
+pub struct ChannelTransport {
+    sender: Sender<(FetchRequest, Sender<Result<FetchResponse>>)>,
+}
+
+impl ChannelTransport {
+    pub fn connect(leader: Arc<Engine>) -> Self {
+        let (sender, receiver) = channel::<(FetchRequest, Sender<Result<FetchResponse>>)>();
+        // transport被drop之后线程随之退出
+        std::thread::spawn(move || {
+            for (request, reply) in receiver {
+                let _ = reply.send(leader.serve_fetch(&request));
+            }
+        });
+        ChannelTransport { sender }
+    }
+}
+
+impl ReplicationTransport for ChannelTransport {
+    fn fetch(&mut self, request: &FetchRequest) -> Result<FetchResponse> {
+        let (reply, receiver) = channel();
+        if self.sender.send((request.clone(), reply)).is_err() {
+            return Err(Errors::ReplicationTransportFailed);
+        }
+        match receiver.recv() {
+            Ok(res) => res,
+            Err(_) => Err(Errors::ReplicationTransportFailed),
+        }
+    }
+}
+
+// leader上的tcp复制服务,每个连接一个线程,Drop的时候停止接收新的连接
+pub struct TcpReplicationServer {
+    server: TcpServer,
+}
+
+impl TcpReplicationServer {
+    pub fn start<A: ToSocketAddrs>(leader: Arc<Engine>, addr: A) -> Result<Self> {
+        let server = TcpServer::start(addr, move |stream| serve_connection(&leader, stream))?;
+        Ok(TcpReplicationServer { server })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+fn serve_connection(leader: &Engine, mut stream: TcpStream) {
+    loop {
+        let frame = match read_frame(&mut stream) {
+            Ok(Some(frame)) => frame,
+            // 对端关闭了连接
+            Ok(None) => return,
+            Err(e) => {
+                error!("failed to read replication request: {}", e);
+                return;
+            }
+        };
+        // 处理失败时把错误信息发回给follower
+        let reply = decode_request(&frame)
+            .and_then(|request| leader.serve_fetch(&request))
+            .map(|response| encode_response(&response))
+            .and_then(|body| match body.len() < MAX_FRAME_SIZE {
+                true => Ok(body),
+                false => Err(Errors::ReplicationFrameTooLarge),
+            });
+        let mut buf = Vec::new();
+        match reply {
+            Ok(body) => {
+                buf.put_u8(0);
+                buf.extend_from_slice(&body);
+            }
+            Err(e) => {
+                buf.put_u8(1);
+                buf.extend_from_slice(e.to_string().as_bytes());
+            }
+        }
+        if let Err(e) = write_frame(&mut stream, &buf) {
+            error!("failed to write replication response: {}", e);
+            return;
+        }
+    }
+}
+
+// follower端的tcp传输
+pub struct TcpTransport {
+    stream: TcpStream,
+}
+
+impl TcpTransport {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        match TcpStream::connect(addr) {
+            Ok(stream) => Ok(TcpTransport { stream }),
+            Err(e) => {
+                error!("failed to connect to replication server: {}", e);
+                Err(Errors::ReplicationTransportFailed)
+            }
+        }
+    }
+}
+
+impl ReplicationTransport for TcpTransport {
+    fn fetch(&mut self, request: &FetchRequest) -> Result<FetchResponse> {
+        if let Err(e) = write_frame(&mut self.stream, &encode_request(request)) {
+            error!("failed to send replication request: {}", e);
+            return Err(Errors::ReplicationTransportFailed);
+        }
+        let frame = match read_frame(&mut self.stream) {
+            Ok(Some(frame)) => frame,
+            _ => return Err(Errors::ReplicationTransportFailed),
+        };
+        match frame.split_first() {
+            Some((0, body)) => decode_response(body),
+            Some((_, message)) => {
+                error!(
+                    "replication request failed on leader: {}",
+                    String::from_utf8_lossy(message)
+                );
+                Err(Errors::ReplicationTransportFailed)
+            }
+            None => Err(Errors::ReplicationDecodeFailed),
+        }
+    }
+}
+
+// 后台复制线程,追上leader之后每隔interval同步一次,Drop的时候停止
+pub struct Replicator {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Replicator {
+    pub fn start<T: ReplicationTransport + 'static>(
+        follower: Arc<Engine>,
+        mut transport: T,
+        interval: Duration,
+    ) -> Self {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_stop = stop.clone();
+        let handle = std::thread::spawn(move || loop {
+            match follower.replicate_once(&mut transport, DEFAULT_FETCH_MAX_BYTES) {
+                // 还没有追上就继续同步
+                Ok(false) if !*thread_stop.0.lock() => continue,
+                Ok(_) => (),
+                Err(e) => error!("replication failed: {}", e),
+            }
+            let (lock, cond) = &*thread_stop;
+            let mut stopped = lock.lock();
+            if !*stopped {
+                cond.wait_for(&mut stopped, interval);
+            }
+            if *stopped {
+                return;
+            }
+        });
+        Replicator {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Replicator {
+    fn drop(&mut self) {
+        let (lock, cond) = &*self.stop;
+        *lock.lock() = true;
+        cond.notify_all();
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+// 每个消息前面是4个字节的长度,超过MAX_FRAME_SIZE的消息不会发送和接收
+fn write_frame(stream: &mut TcpStream, body: &[u8]) -> std::io::Result<()> {
+    if body.len() > MAX_FRAME_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "replication frame is too large",
+        ));
+    }
+    stream.write_all(&(body.len() as u32).to_be_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn read_frame(stream: &mut TcpStream) -> std::io::Result<Option<Vec<u8>>> {
+    let mut len_buf = [0; 4];
+    if let Err(e) = stream.read_exact(&mut len_buf) {
+        return match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => Ok(None),
+            _ => Err(e),
+        };
+    }
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "replication frame is too large",
+        ));
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+// 这次返回的日志或者快照占用的字节数
+fn payload_len(payload: &FetchPayload) -> u64 {
+    match payload {
+        FetchPayload::Records { data, .. } => data.len() as u64,
+        FetchPayload::Snapshot {
+            data,
+            merge_finished,
+            ..
+        } => (data.len() + merge_finished.as_ref().map_or(0, |data| data.len())) as u64,
+    }
+}
+
+// 读取文件中从offset开始最多len个字节
+fn read_file_range(path: &Path, offset: u64, len: u64) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let res = File::open(path).and_then(|mut file| {
+        file.seek(SeekFrom::Start(offset))?;
+        file.take(len).read_to_end(&mut data)
+    });
+    if let Err(e) = res {
+        error!("failed to read merge file: {}", e);
+        return Err(Errors::FailReadFromFile);
+    }
+    Ok(data)
+}
+
+// 在文件末尾追加数据并持久化,文件的长度必须等于offset
+fn append_file_at(path: &Path, offset: u64, data: &[u8]) -> Result<()> {
+    let mut file = match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            error!("failed to open merge file: {}", e);
+            return Err(Errors::FailWriteDataToFile);
+        }
+    };
+    if file
+        .metadata()
+        .map_or(true, |metadata| metadata.len() != offset)
+    {
+        return Err(Errors::ReplicationPositionMismatch);
+    }
+    if let Err(e) = file.write_all(data).and_then(|_| file.sync_all()) {
+        error!("failed to write merge file: {}", e);
+        return Err(Errors::FailWriteDataToFile);
+    }
+    Ok(())
+}
+
+fn put_bytes(buf: &mut Vec<u8>, data: &[u8]) {
+    encode_varint(data.len() as u64, buf);
+    buf.extend_from_slice(data);
+}
+
+fn get_varint(buf: &mut &[u8]) -> Result<u64> {
+    decode_varint(buf).map_err(|_| Errors::ReplicationDecodeFailed)
+}
+
+fn get_bytes(buf: &mut &[u8]) -> Result<Vec<u8>> {
+    let len = get_varint(buf)? as usize;
+    if buf.remaining() < len {
+        return Err(Errors::ReplicationDecodeFailed);
+    }
+    let data = buf[..len].to_vec();
+    buf.advance(len);
+    Ok(data)
+}
+
+fn encode_request(request: &FetchRequest) -> Vec<u8> {
+    let mut buf = Vec::new();
+    encode_varint(request.merge_generation as u64, &mut buf);
+    encode_varint(request.file_id as u64, &mut buf);
+    encode_varint(request.offset, &mut buf);
+    encode_varint(request.max_bytes, &mut buf);
+    encode_varint(request.blob_sizes.len() as u64, &mut buf);
+    for (file_id, size) in request.blob_sizes.iter() {
+        encode_varint(*file_id as u64, &mut buf);
+        encode_varint(*size, &mut buf);
+    }
+    match &request.snapshot {
+        Some(progress) => {
+            encode_varint(1, &mut buf);
+            encode_varint(progress.merge_generation as u64, &mut buf);
+            put_snapshot_file(&mut buf, progress.file);
+            encode_varint(progress.offset, &mut buf);
+        }
+        None => encode_varint(0, &mut buf),
+    }
+    buf
+}
+
+fn put_snapshot_file(buf: &mut Vec<u8>, file: SnapshotFile) {
+    match file {
+        SnapshotFile::Data(file_id) => {
+            encode_varint(0, buf);
+            encode_varint(file_id as u64, buf);
+        }
+        SnapshotFile::Hint => encode_varint(1, buf),
+    }
+}
+
+fn get_snapshot_file(buf: &mut &[u8]) -> Result<SnapshotFile> {
+    match get_varint(buf)? {
+        0 => Ok(SnapshotFile::Data(get_varint(buf)? as u32)),
+        1 => Ok(SnapshotFile::Hint),
+        _ => Err(Errors::ReplicationDecodeFailed),
+    }
+}
+
+fn decode_request(mut buf: &[u8]) -> Result<FetchRequest> {
+    let buf = &mut buf;
+    let merge_generation = get_varint(buf)? as u32;
+    let file_id = get_varint(buf)? as u32;
+    let offset = get_varint(buf)?;
+    let max_bytes = get_varint(buf)?;
+    let mut blob_sizes = Vec::new();
+    for _ in 0..get_varint(buf)? {
+        blob_sizes.push((get_varint(buf)? as u32, get_varint(buf)?));
+    }
+    let snapshot = match get_varint(buf)? {
+        0 => None,
+        1 => Some(SnapshotProgress {
+            merge_generation: get_varint(buf)? as u32,
+            file: get_snapshot_file(buf)?,
+            offset: get_varint(buf)?,
+        }),
+        _ => return Err(Errors::ReplicationDecodeFailed),
+    };
+    Ok(FetchRequest {
+        merge_generation,
+        file_id,
+        offset,
+        blob_sizes,
+        max_bytes,
+        snapshot,
+    })
+}
+
+fn encode_response(response: &FetchResponse) -> Vec<u8> {
+    let mut buf = Vec::new();
+    encode_varint(response.merge_generation as u64, &mut buf);
+    encode_varint(response.caught_up as u64, &mut buf);
+    match &response.payload {
+        FetchPayload::Records {
+            file_id,
+            offset,
+            data,
+        } => {
+            encode_varint(0, &mut buf);
+            encode_varint(*file_id as u64, &mut buf);
+            encode_varint(*offset, &mut buf);
+            put_bytes(&mut buf, data);
+        }
+        FetchPayload::Snapshot {
+            file,
+            offset,
+            data,
+            merge_finished,
+        } => {
+            encode_varint(1, &mut buf);
+            put_snapshot_file(&mut buf, *file);
+            encode_varint(*offset, &mut buf);
+            put_bytes(&mut buf, data);
+            match merge_finished {
+                Some(merge_finished) => {
+                    encode_varint(1, &mut buf);
+                    put_bytes(&mut buf, merge_finished);
+                }
+                None => encode_varint(0, &mut buf),
+            }
+        }
+    }
+    encode_varint(response.blobs.len() as u64, &mut buf);
+    for blob in response.blobs.iter() {
+        encode_varint(blob.file_id as u64, &mut buf);
+        encode_varint(blob.offset, &mut buf);
+        put_bytes(&mut buf, &blob.data);
+    }
+    encode_varint(response.blob_file_ids.len() as u64, &mut buf);
+    for file_id in response.blob_file_ids.iter() {
+        encode_varint(*file_id as u64, &mut buf);
+    }
+    buf
+}
+
+fn decode_response(mut buf: &[u8]) -> Result<FetchResponse> {
+    let buf = &mut buf;
+    let merge_generation = get_varint(buf)? as u32;
+    let caught_up = get_varint(buf)? != 0;
+    let payload = match get_varint(buf)? {
+        0 => FetchPayload::Records {
+            file_id: get_varint(buf)? as u32,
+            offset: get_varint(buf)?,
+            data: get_bytes(buf)?,
+        },
+        1 => FetchPayload::Snapshot {
+            file: get_snapshot_file(buf)?,
+            offset: get_varint(buf)?,
+            data: get_bytes(buf)?,
+            merge_finished: match get_varint(buf)? {
+                0 => None,
+                _ => Some(get_bytes(buf)?),
+            },
+        },
+        _ => return Err(Errors::ReplicationDecodeFailed),
+    };
+    let mut blobs = Vec::new();
+    for _ in 0..get_varint(buf)? {
+        blobs.push(BlobChunk {
+            file_id: get_varint(buf)? as u32,
+            offset: get_varint(buf)?,
+            data: get_bytes(buf)?,
+        });
+    }
+    let mut blob_file_ids = Vec::new();
+    for _ in 0..get_varint(buf)? {
+        blob_file_ids.push(get_varint(buf)? as u32);
+    }
+    Ok(FetchResponse {
+        merge_generation,
+        payload,
+        blobs,
+        blob_file_ids,
+        caught_up,
+    })
+}
+
+#[cfg(test)]
+mod test_replication {
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::{
+        payload_len, read_frame, write_frame, ChannelTransport, FetchRequest, Replicator,
+        TcpReplicationServer, TcpTransport, MAX_FRAME_SIZE,
+    };
+    use crate::{
+        db::Engine,
+        errors::Errors,
+        options::{Options, SyncPolicy, WriteBatchOptions},
+        util::rand_kv::{get_test_key, get_test_value},
+    };
+
+    fn test_options(dir_path: &str) -> Options {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from(dir_path);
+        opts.file_size_threshlod = 32 * 1024;
+        opts.sync_policy = SyncPolicy::Always;
+        opts.blob_value_threshold = Some(1024);
+        opts
+    }
+
+    #[test]
+    fn test_replication() {
+        let leader_opts = test_options("/tmp/bitcask-rs-replication-leader");
+        let follower_opts = test_options("/tmp/bitcask-rs-replication-follower");
+        let leader = Arc::new(Engine::open(leader_opts.clone()).unwrap());
+        for i in 0..1000 {
+            leader.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        // 大value写到blob文件
+        let big_value = Bytes::from(vec![b'b'; 4096]);
+        leader.put(get_test_key(5000), big_value.clone()).unwrap();
+        let write_batch = leader
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        for i in 0..100 {
+            write_batch
+                .put(get_test_key(i), Bytes::from("batch"))
+                .unwrap();
+        }
+        write_batch.commit().unwrap();
+        leader
+            .delete_prefix(Bytes::from("bitcask-rs-key-00000009"))
+            .unwrap();
+
+        let follower = Engine::open_replica(follower_opts.clone()).unwrap();
+        let mut transport = ChannelTransport::connect(leader.clone());
+        follower.catch_up(&mut transport).unwrap();
+        let check = |follower: &Engine, n: i32| {
+            for i in 0..n {
+                match i {
+                    90..=99 => assert!(follower.get(get_test_key(i)).is_err()),
+                    0..=89 => assert_eq!(follower.get(get_test_key(i)).unwrap(), "batch"),
+                    _ => assert_eq!(follower.get(get_test_key(i)).unwrap(), get_test_value(i)),
+                }
+            }
+            assert_eq!(follower.get(get_test_key(5000)).unwrap(), big_value);
+        };
+        check(&follower, 1000);
+
+        // 副本不能写入
+        assert_eq!(
+            follower.put(get_test_key(1), get_test_value(1)).err(),
+            Some(Errors::ReadOnly)
+        );
+        assert_eq!(follower.merge().err(), Some(Errors::ReadOnly));
+
+        // 重启后从自己的文件末尾继续同步
+        drop(follower);
+        for i in 1000..2000 {
+            leader.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        let follower = Engine::open_replica(follower_opts.clone()).unwrap();
+        check(&follower, 1000);
+        follower.catch_up(&mut transport).unwrap();
+        check(&follower, 2000);
+
+        // leader merge并重启之后,follower先同步merge之后的文件
+        for i in 1000..1500 {
+            leader.delete(get_test_key(i)).unwrap();
+        }
+        leader.merge().unwrap();
+        // 等处理请求的线程退出之后再重启leader
+        drop(transport);
+        while Arc::strong_count(&leader) > 1 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        drop(leader);
+        let leader = Arc::new(Engine::open(leader_opts.clone()).unwrap());
+        for i in 2000..2100 {
+            leader.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        let mut transport = ChannelTransport::connect(leader.clone());
+        // merge之后的文件也按照max_bytes分段同步
+        let mut rounds = 0;
+        while !follower.replicate_once(&mut transport, 1024).unwrap() {
+            rounds += 1;
+        }
+        assert!(rounds > 10);
+        let check_merged = |follower: &Engine| {
+            check(follower, 1000);
+            for i in 1000..1500 {
+                assert!(follower.get(get_test_key(i)).is_err());
+            }
+            for i in 1500..2100 {
+                assert_eq!(follower.get(get_test_key(i)).unwrap(), get_test_value(i));
+            }
+            assert_eq!(follower.list_keys().unwrap().len(), 1591);
+        };
+        check_merged(&follower);
+        drop(follower);
+        let follower = Arc::new(Engine::open_replica(follower_opts.clone()).unwrap());
+        check_merged(&follower);
+
+        // 后台复制线程
+        let replicator = Replicator::start(follower.clone(), transport, Duration::from_millis(5));
+        leader
+            .put(get_test_key(3000), get_test_value(3000))
+            .unwrap();
+        let mut replicated = false;
+        for _ in 0..200 {
+            if follower.get(get_test_key(3000)).is_ok() {
+                replicated = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(replicated);
+        drop(replicator);
+        std::fs::remove_dir_all(leader_opts.dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(follower_opts.dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_fetch_large_blob() {
+        let leader_opts = test_options("/tmp/bitcask-rs-replication-blob-leader");
+        let follower_opts = test_options("/tmp/bitcask-rs-replication-blob-follower");
+        let leader = Arc::new(Engine::open(leader_opts.clone()).unwrap());
+        for i in 0..100 {
+            leader.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        // blob比一次传输的max_bytes还要大
+        let big_value = Bytes::from(vec![b'b'; 8192]);
+        leader.put(get_test_key(5000), big_value.clone()).unwrap();
+
+        let follower = Engine::open_replica(follower_opts.clone()).unwrap();
+        let mut transport = ChannelTransport::connect(leader.clone());
+        let max_bytes = 1024;
+        loop {
+            let active_file_id = follower.data_file.read().get_file_id();
+            let request = FetchRequest {
+                merge_generation: 0,
+                file_id: active_file_id,
+                offset: follower.data_file.read().get_wtite_offset(),
+                blob_sizes: follower.blob_files.file_sizes(),
+                max_bytes,
+                snapshot: None,
+            };
+            let response = leader.serve_fetch(&request).unwrap();
+            // 日志和blob一共不超过max_bytes,blob没有同步完时不返回日志
+            let blob_bytes: usize = response.blobs.iter().map(|blob| blob.data.len()).sum();
+            assert!((payload_len(&response.payload) + blob_bytes as u64) <= max_bytes);
+            let blob_done = response.blobs.iter().all(|blob| {
+                let size = leader
+                    .blob_files
+                    .file_sizes()
+                    .iter()
+                    .find(|(id, _)| *id == blob.file_id)
+                    .unwrap()
+                    .1;
+                blob.offset + blob.data.len() as u64 == size
+            });
+            if !blob_done {
+                assert_eq!(payload_len(&response.payload), 0);
+            }
+            if follower.replicate_once(&mut transport, max_bytes).unwrap() {
+                break;
+            }
+        }
+        for i in 0..100 {
+            assert_eq!(follower.get(get_test_key(i)).unwrap(), get_test_value(i));
+        }
+        assert_eq!(follower.get(get_test_key(5000)).unwrap(), big_value);
+        drop(follower);
+        std::fs::remove_dir_all(leader_opts.dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(follower_opts.dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_tcp_replication() {
+        let leader_opts = test_options("/tmp/bitcask-rs-tcp-replication-leader");
+        let follower_opts = test_options("/tmp/bitcask-rs-tcp-replication-follower");
+        let leader = Arc::new(Engine::open(leader_opts.clone()).unwrap());
+        for i in 0..500 {
+            leader.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        let server = TcpReplicationServer::start(leader.clone(), "127.0.0.1:0").unwrap();
+        let follower = Engine::open_replica(follower_opts.clone()).unwrap();
+        let mut transport = TcpTransport::connect(server.local_addr()).unwrap();
+        // 每次只同步一小段
+        while !follower.replicate_once(&mut transport, 1024).unwrap() {}
+        for i in 0..500 {
+            assert_eq!(follower.get(get_test_key(i)).unwrap(), get_test_value(i));
+        }
+        drop(transport);
+        drop(server);
+        std::fs::remove_dir_all(leader_opts.dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(follower_opts.dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_frame_too_large() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        // 超过上限的长度直接报错,不会分配内存
+        client.write_all(&u32::MAX.to_be_bytes()).unwrap();
+        assert!(read_frame(&mut server).is_err());
+        assert!(write_frame(&mut client, &vec![0; MAX_FRAME_SIZE + 1]).is_err());
+    }
+}
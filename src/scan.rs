@@ -0,0 +1,121 @@
+use bytes::Bytes;
+
+use crate::{
+    db::Engine,
+    delete_range::prefix_end,
+    errors::{Errors, Result},
+};
+
+impl Engine {
+    // 按照key的顺序读取[start, end)范围内最多limit个kv,end为None表示没有上界
+    // 可以用上一次返回的最后一个key之后的位置作为下一次的start来分页
+    pub fn scan(
+        &self,
+        start: Bytes,
+        end: Option<Bytes>,
+        limit: usize,
+    ) -> Result<Vec<(Bytes, Bytes)>> {
+        let mut kvs = Vec::new();
+        for (key, pos) in self.indexer.scan(&start, end.as_deref(), limit) {
+            match self.get_value_by_pos(&pos) {
+                Ok(value) => kvs.push((Bytes::from(key), value)),
+                // 扫描之后被并发删除了
+                Err(Errors::KeyNotFound) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(kvs)
+    }
+
+    // 和scan一样,只返回key,不需要读取value
+    pub fn scan_keys(&self, start: Bytes, end: Option<Bytes>, limit: usize) -> Vec<Bytes> {
+        self.indexer
+            .scan(&start, end.as_deref(), limit)
+            .into_iter()
+            .map(|(key, _)| Bytes::from(key))
+            .collect()
+    }
+
+    // 按照key的顺序读取以prefix开头,并且不小于start的最多limit个kv
+    pub fn scan_prefix(
+        &self,
+        prefix: Bytes,
+        start: Bytes,
+        limit: usize,
+    ) -> Result<Vec<(Bytes, Bytes)>> {
+        let start = match start < prefix {
+            true => prefix.clone(),
+            false => start,
+        };
+        self.scan(start, prefix_end(&prefix), limit)
+    }
+}
+
+// 返回紧跟在key之后的key,用来从上一页的最后一个key继续扫描
+pub fn next_key(key: &[u8]) -> Bytes {
+    let mut next = key.to_vec();
+    next.push(0);
+    Bytes::from(next)
+}
+
+#[cfg(test)]
+mod test_scan {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use super::next_key;
+    use crate::{
+        db::Engine,
+        options::Options,
+        util::rand_kv::{get_test_key, get_test_value},
+    };
+
+    #[test]
+    fn test_scan() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-scan");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..100 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine.delete(get_test_key(5)).unwrap();
+
+        // 分页读取所有的key
+        let mut start = Bytes::new();
+        let mut count = 0;
+        loop {
+            let kvs = engine.scan(start.clone(), None, 10).unwrap();
+            if kvs.is_empty() {
+                break;
+            }
+            for (key, value) in kvs.iter() {
+                assert_eq!(*value, engine.get(key.clone()).unwrap());
+            }
+            count += kvs.len();
+            start = next_key(&kvs.last().unwrap().0);
+        }
+        assert_eq!(count, 99);
+
+        // 不包含结束key
+        let keys = engine.scan_keys(get_test_key(10), Some(get_test_key(20)), 100);
+        assert_eq!(keys.len(), 10);
+        assert_eq!(keys[0], get_test_key(10));
+
+        // 前缀扫描
+        let kvs = engine
+            .scan_prefix(Bytes::from("bitcask-rs-key-00000001"), Bytes::new(), 100)
+            .unwrap();
+        assert_eq!(kvs.len(), 10);
+        let kvs = engine
+            .scan_prefix(
+                Bytes::from("bitcask-rs-key-00000001"),
+                next_key(&get_test_key(15)),
+                100,
+            )
+            .unwrap();
+        assert_eq!(kvs.len(), 4);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
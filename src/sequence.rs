@@ -0,0 +1,107 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use crate::{
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+// 每次持久化时预留的序号个数
+const SEQUENCE_RESERVE: u64 = 1024;
+
+// 持久化的单调递增序号,redis集合的版本号和memcached的cas都用它分配
+// 预留的上界记录在key中,重启之后从上界之后继续分配,不依赖系统时间
+pub(crate) struct Sequence {
+    key: Bytes,
+    // (上一次分配的序号, 已经写入的预留上界),第一次分配时从engine中加载
+    state: Mutex<Option<(u64, u64)>>,
+}
+
+impl Sequence {
+    pub(crate) fn new(key: &'static [u8]) -> Self {
+        Sequence {
+            key: Bytes::from_static(key),
+            state: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn next(&self, engine: &Engine) -> Result<u64> {
+        let mut state = self.state.lock();
+        let (last, mut reserved) = match *state {
+            Some(state) => state,
+            None => {
+                let reserved = self.load(engine)?;
+                (reserved, reserved)
+            }
+        };
+        let next = last + 1;
+        if next > reserved {
+            reserved = next + SEQUENCE_RESERVE;
+            // 使用这个序号的写入都在这条记录之后追加,它们持久化时预留的上界也一定持久化了
+            engine.put(
+                self.key.clone(),
+                Bytes::copy_from_slice(&reserved.to_be_bytes()),
+            )?;
+        }
+        *state = Some((next, reserved));
+        Ok(next)
+    }
+
+    fn load(&self, engine: &Engine) -> Result<u64> {
+        match engine.get(self.key.clone()) {
+            Ok(value) => match <[u8; 8]>::try_from(value.as_ref()) {
+                Ok(buf) => Ok(u64::from_be_bytes(buf)),
+                Err(_) => Err(Errors::InvalidSequenceRecord),
+            },
+            // 没有记录时从当前的纳秒时间开始,比之前按照系统时间分配的序号都大
+            Err(Errors::KeyNotFound) => Ok(SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_nanos() as u64)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_sequence {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use super::{Sequence, SEQUENCE_RESERVE};
+    use crate::{db::Engine, errors::Errors, options::Options};
+
+    #[test]
+    fn test_sequence() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-sequence");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let sequence = Sequence::new(b"sequence");
+        let first = sequence.next(&engine).unwrap();
+        let mut last = first;
+        for _ in 0..3 * SEQUENCE_RESERVE {
+            let next = sequence.next(&engine).unwrap();
+            assert!(next > last);
+            last = next;
+        }
+
+        // 重启后不会分配到之前已经分配过的序号
+        engine.close().unwrap();
+        drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let sequence = Sequence::new(b"sequence");
+        assert!(sequence.next(&engine).unwrap() > last);
+
+        engine
+            .put(Bytes::from("broken"), Bytes::from("value"))
+            .unwrap();
+        assert_eq!(
+            Sequence::new(b"broken").next(&engine).err(),
+            Some(Errors::InvalidSequenceRecord)
+        );
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
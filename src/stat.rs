@@ -0,0 +1,72 @@
+use std::fs;
+
+use log::error;
+
+use crate::{
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+// 存储引擎的统计信息
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stat {
+    // 索引中key的个数
+    pub key_num: usize,
+    // 数据文件的个数,包含活跃文件
+    pub data_file_num: usize,
+    // 数据目录占用的磁盘空间,单位为字节
+    pub disk_size: u64,
+}
+
+impl Engine {
+    pub fn stat(&self) -> Result<Stat> {
+        let data_file_num = self.old_files.read().len() + 1;
+        let read_dir = match fs::read_dir(&self.options.dir_path) {
+            Ok(read_dir) => read_dir,
+            Err(e) => {
+                error!("failed to read database dir: {}", e);
+                return Err(Errors::DirPathReadFailed);
+            }
+        };
+        let disk_size = read_dir
+            .flatten()
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum();
+        Ok(Stat {
+            key_num: self.indexer.len(),
+            data_file_num,
+            disk_size,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_stat {
+    use std::path::PathBuf;
+
+    use crate::{
+        db::Engine,
+        options::Options,
+        util::rand_kv::{get_test_key, get_test_value},
+    };
+
+    #[test]
+    fn test_stat() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-stat");
+        opts.file_size_threshlod = 32 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine.delete(get_test_key(0)).unwrap();
+        engine.sync().unwrap();
+        let stat = engine.stat().unwrap();
+        assert_eq!(stat.key_num, 999);
+        assert!(stat.data_file_num > 1);
+        assert!(stat.disk_size > 32 * 1024);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,75 @@
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use log::error;
+
+use crate::errors::{Errors, Result};
+
+// 通用的tcp服务,每个连接交给一个线程处理,Drop的时候停止接收新的连接
+// 已经建立的连接在对端关闭之后退出
+pub(crate) struct TcpServer {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl TcpServer {
+    pub(crate) fn start<A, F>(addr: A, handler: F) -> Result<Self>
+    where
+        A: ToSocketAddrs,
+        F: Fn(TcpStream) + Send + Sync + 'static,
+    {
+        let listener = match TcpListener::bind(addr) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("failed to bind server address: {}", e);
+                return Err(Errors::ServerBindFailed);
+            }
+        };
+        let local_addr = match listener.local_addr() {
+            Ok(local_addr) => local_addr,
+            Err(_) => return Err(Errors::ServerBindFailed),
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let handler = Arc::new(handler);
+        let handle = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_stop.load(Ordering::SeqCst) {
+                    return;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        error!("failed to accept connection: {}", e);
+                        continue;
+                    }
+                };
+                let handler = handler.clone();
+                std::thread::spawn(move || handler(stream));
+            }
+        });
+        Ok(TcpServer {
+            local_addr,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    pub(crate) fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for TcpServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // 连接一次,让阻塞在accept上的线程退出
+        let _ = TcpStream::connect(self.local_addr);
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use crate::{data::log_record::LogRecordPos, db::Engine};
+
+// 每个订阅者最多缓存的事件数,超过之后认为订阅者太慢,直接断开
+pub const WATCH_CHANNEL_CAPACITY: usize = 1024;
+
+// 一次写入产生的key变化,value为None表示key被删除
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub key: Bytes,
+    pub value: Option<Bytes>,
+}
+
+// 一次原子写入对应的变化通知,单条写入只有一个change,
+// 批量提交的所有change放在同一个事件中
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeEvent {
+    // 在当前engine实例中单调递增,和事件送达的顺序一致
+    pub seq_no: u64,
+    pub changes: Vec<Change>,
+}
+
+struct Subscriber {
+    prefix: Bytes,
+    sender: SyncSender<ChangeEvent>,
+}
+
+struct WatcherState {
+    next_seq_no: u64,
+    subscribers: Vec<Subscriber>,
+    // 已经持久化的日志位置
+    durable_pos: LogRecordPos,
+    // 日志还没有持久化的写入,按照日志的顺序排列,记录写入的最后一条日志的位置
+    pending: VecDeque<(LogRecordPos, Vec<Change>)>,
+}
+
+// 管理所有watch的订阅者
+pub(crate) struct Watchers {
+    state: Mutex<WatcherState>,
+}
+
+// 位置pos的日志是否已经在durable_pos之前持久化
+fn is_durable(pos: &LogRecordPos, durable_pos: &LogRecordPos) -> bool {
+    (pos.file_id, pos.offset) < (durable_pos.file_id, durable_pos.offset)
+}
+
+impl Watchers {
+    pub(crate) fn new(durable_pos: LogRecordPos) -> Self {
+        Watchers {
+            state: Mutex::new(WatcherState {
+                next_seq_no: 1,
+                subscribers: Vec::new(),
+                durable_pos,
+                pending: VecDeque::new(),
+            }),
+        }
+    }
+
+    // 没有订阅者时写入路径可以跳过构造通知
+    pub(crate) fn is_empty(&self) -> bool {
+        self.state.lock().subscribers.is_empty()
+    }
+
+    // 记录一次写入的变化,pos是这次写入的最后一条日志的位置
+    // 由组提交的leader在持有活跃文件的锁时按照日志的顺序调用,日志持久化之后才会发送给订阅者
+    pub(crate) fn notify(&self, changes: Vec<Change>, pos: LogRecordPos) {
+        if changes.is_empty() {
+            return;
+        }
+        let mut state = self.state.lock();
+        if state.subscribers.is_empty() {
+            return;
+        }
+        state.pending.push_back((pos, changes));
+        state.deliver();
+    }
+
+    // 日志持久化到durable_pos之后调用,发送已经持久化的写入的通知
+    pub(crate) fn on_durable(&self, durable_pos: LogRecordPos) {
+        let mut state = self.state.lock();
+        state.durable_pos = durable_pos;
+        state.deliver();
+    }
+}
+
+impl WatcherState {
+    // 按顺序发送已经持久化的通知,遇到还没有持久化的写入就停止,保证送达的顺序
+    fn deliver(&mut self) {
+        while let Some((pos, _)) = self.pending.front() {
+            if !is_durable(pos, &self.durable_pos) {
+                return;
+            }
+            let (_, changes) = self.pending.pop_front().unwrap();
+            self.send(changes);
+        }
+    }
+
+    // 把变化发送给前缀匹配的订阅者,接收端已经被drop或者缓存已满的订阅者会被移除
+    fn send(&mut self, changes: Vec<Change>) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        let seq_no = self.next_seq_no;
+        self.next_seq_no += 1;
+        self.subscribers.retain(|subscriber| {
+            let matched: Vec<Change> = changes
+                .iter()
+                .filter(|change| change.key.starts_with(&subscriber.prefix))
+                .cloned()
+                .collect();
+            if matched.is_empty() {
+                return true;
+            }
+            match subscriber.sender.try_send(ChangeEvent {
+                seq_no,
+                changes: matched,
+            }) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+}
+
+impl Engine {
+    // 订阅以prefix开头的key的变化,空的prefix表示订阅所有key
+    // 写入的日志持久化之后才会发出通知,SyncPolicy不是Always时通知会延迟到下一次持久化,
+    // SyncPolicy::Never时只有活跃文件写满或者调用sync之后才会收到通知
+    // 订阅者来不及接收,缓存超过WATCH_CHANNEL_CAPACITY个事件时会被断开,
+    // 接收端在读完已有的事件之后返回Disconnected
+    pub fn watch(&self, prefix: Bytes) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = sync_channel(WATCH_CHANNEL_CAPACITY);
+        self.watchers
+            .state
+            .lock()
+            .subscribers
+            .push(Subscriber { prefix, sender });
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod test_watch {
+    use std::path::PathBuf;
+    use std::sync::mpsc::TryRecvError;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::{Change, WATCH_CHANNEL_CAPACITY};
+    use crate::{
+        data::log_record::LogRecordType,
+        db::Engine,
+        options::{Options, SyncPolicy, WriteBatchOptions},
+    };
+
+    #[test]
+    fn test_watch() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-watch");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let config_rx = engine.watch(Bytes::from("config/"));
+        let all_rx = engine.watch(Bytes::new());
+
+        engine
+            .put(Bytes::from("config/a"), Bytes::from("1"))
+            .unwrap();
+        engine.put(Bytes::from("other"), Bytes::from("x")).unwrap();
+        // 日志持久化之前不会收到通知
+        assert!(config_rx.recv_timeout(Duration::from_millis(10)).is_err());
+        let write_batch = engine
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        write_batch
+            .put(Bytes::from("config/b"), Bytes::from("2"))
+            .unwrap();
+        write_batch.delete(Bytes::from("config/a")).unwrap();
+        write_batch
+            .put(Bytes::from("other"), Bytes::from("y"))
+            .unwrap();
+        write_batch.commit().unwrap();
+        engine.delete_prefix(Bytes::from("config/")).unwrap();
+        engine.sync().unwrap();
+
+        let event = config_rx.recv().unwrap();
+        assert_eq!(
+            event.changes,
+            vec![Change {
+                key: Bytes::from("config/a"),
+                value: Some(Bytes::from("1")),
+            }]
+        );
+        // 批量提交作为一个事件送达
+        let batch_event = config_rx.recv().unwrap();
+        assert!(batch_event.seq_no > event.seq_no);
+        assert_eq!(batch_event.changes.len(), 2);
+        assert!(batch_event.changes.contains(&Change {
+            key: Bytes::from("config/a"),
+            value: None,
+        }));
+        assert!(batch_event.changes.contains(&Change {
+            key: Bytes::from("config/b"),
+            value: Some(Bytes::from("2")),
+        }));
+        let range_event = config_rx.recv().unwrap();
+        assert_eq!(
+            range_event.changes,
+            vec![Change {
+                key: Bytes::from("config/b"),
+                value: None,
+            }]
+        );
+        assert!(config_rx.recv_timeout(Duration::from_millis(10)).is_err());
+
+        let events: Vec<_> = all_rx.try_iter().collect();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[2].changes.len(), 3);
+
+        // 接收端drop之后不再发送
+        drop(config_rx);
+        engine
+            .put(Bytes::from("config/c"), Bytes::from("3"))
+            .unwrap();
+        engine.sync().unwrap();
+        assert_eq!(all_rx.try_iter().count(), 1);
+        assert_eq!(engine.watchers.state.lock().subscribers.len(), 1);
+
+        // 来不及接收的订阅者被断开,已经缓存的事件仍然可以读到
+        for i in 0..=WATCH_CHANNEL_CAPACITY {
+            engine
+                .put(Bytes::from(format!("slow/{}", i)), Bytes::from("v"))
+                .unwrap();
+        }
+        engine.sync().unwrap();
+        assert_eq!(engine.watchers.state.lock().subscribers.len(), 0);
+        assert_eq!(all_rx.try_iter().count(), WATCH_CHANNEL_CAPACITY);
+        assert_eq!(all_rx.try_recv(), Err(TryRecvError::Disconnected));
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_watch_log_order() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-watch-order");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        opts.sync_policy = SyncPolicy::Always;
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let rx = engine.watch(Bytes::new());
+        // 并发写入,通知的顺序需要和日志中的顺序一致,总数不超过订阅者的缓存
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let engine = engine.clone();
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        engine
+                            .put(Bytes::from(format!("{}-{}", t, i)), Bytes::from("v"))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let notified: Vec<Bytes> = rx
+            .try_iter()
+            .flat_map(|event| event.changes.into_iter().map(|change| change.key))
+            .collect();
+
+        let active_file = engine.data_file.read();
+        let mut offset = 0;
+        let mut logged = Vec::new();
+        while let Ok(read_log_record) = active_file.read_log_record(offset) {
+            offset += read_log_record.size as u64;
+            if read_log_record.logrecord.log_type == LogRecordType::NORMAL {
+                let (key, _) = engine.parse_key(read_log_record.logrecord.key);
+                logged.push(Bytes::from(key));
+            }
+        }
+        assert_eq!(logged.len(), 8 * 100);
+        assert_eq!(notified, logged);
+        drop(active_file);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -1,24 +1,40 @@
 use bytes::{BufMut, Bytes, BytesMut};
 use parking_lot::Mutex;
 use prost::encode_length_delimiter;
-use std::sync::atomic::*;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::sync::atomic;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{collections::HashMap, sync::Arc};
 
-use crate::data::log_record::{self, LogRecordType};
+use crate::data::log_record::{self, LogRecordPos, LogRecordType};
+use crate::index::IndexIteratorOptions;
 use crate::{
     data::log_record::{LogRecord, LogRecordType::*},
     db::Engine,
+    delete_range::prefix_end,
     errors::{
         Errors::{self, *},
         Result,
     },
-    options::WriteBatchOptions,
+    options::{SyncPolicy, WriteBatchOptions},
+    scan::next_key,
+    watch::Change,
 };
 
 pub const TXN_FIN: &[u8] = "TXN_FIN".as_bytes();
+// 迭代时每次从索引中读取的已提交key的个数
+const ITER_SCAN_BATCH: usize = 256;
+
+// 当前时间距离UNIX_EPOCH的毫秒数
+pub(crate) fn commit_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
 
 pub struct WriteBatch<'a> {
-    pending_data: Arc<Mutex<HashMap<Vec<u8>, LogRecord>>>,
+    pending_data: Arc<Mutex<PendingWrites>>,
     engine: &'a Engine,
     options: WriteBatchOptions,
 }
@@ -26,7 +42,7 @@ pub struct WriteBatch<'a> {
 impl Engine {
     pub fn new_write_batch(&self, options: WriteBatchOptions) -> Result<WriteBatch> {
         Ok(WriteBatch {
-            pending_data: Arc::new(Mutex::new(HashMap::new())),
+            pending_data: Arc::new(Mutex::new(PendingWrites::new())),
             engine: self,
             options: options,
         })
@@ -44,7 +60,11 @@ impl WriteBatch<'_> {
             log_type: NORMAL,
         };
         let mut lock_guard = self.pending_data.lock();
-        lock_guard.insert(key.to_vec(), log_record);
+        // 暂存的数据不能超过配置的大小
+        if lock_guard.size + key.len() as u64 + value.len() as u64 > self.options.batch_max_bytes {
+            return Err(Errors::ExceedBatchMaxBytes);
+        }
+        lock_guard.stage(key.to_vec(), Some(log_record));
         Ok(())
     }
 
@@ -52,10 +72,16 @@ impl WriteBatch<'_> {
         if key.is_empty() {
             return Err(KeyEmptyErr);
         }
+        let mut guard = self.pending_data.lock();
+        // 删除也会写入一条日志,key的大小同样计入限制
+        if guard.size + key.len() as u64 > self.options.batch_max_bytes {
+            return Err(Errors::ExceedBatchMaxBytes);
+        }
         // 看索引是否真的存在这个key
         let log_record_pos = self.engine.indexer.get(key.to_vec());
-        // 不存在直接返回即可
+        // 不存在的话,只需要把暂存的写入去掉即可
         if log_record_pos.is_none() {
+            guard.stage(key.to_vec(), None);
             return Ok(());
         }
         let log_record = LogRecord {
@@ -63,11 +89,81 @@ impl WriteBatch<'_> {
             value: Vec::new(),
             log_type: DELETED,
         };
-        let mut guard = self.pending_data.lock();
-        guard.insert(key.to_vec(), log_record).unwrap();
+        guard.stage(key.to_vec(), Some(log_record));
         Ok(())
     }
 
+    // 读取key,先看暂存的数据,再去engine里面查
+    pub fn get(&self, key: Bytes) -> Result<Bytes> {
+        if key.is_empty() {
+            return Err(KeyEmptyErr);
+        }
+        let guard = self.pending_data.lock();
+        if let Some(log_record) = guard.get(&key) {
+            if log_record.log_type == DELETED {
+                return Err(KeyNotFound);
+            }
+            return Ok(Bytes::from(log_record.value.clone()));
+        }
+        self.engine.get(key)
+    }
+
+    // 暂存的操作条数
+    pub fn len(&self) -> usize {
+        self.pending_data.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 丢弃所有暂存的操作
+    pub fn discard(&self) {
+        self.pending_data.lock().clear();
+    }
+
+    // 设置一个savepoint,可以嵌套设置多个
+    pub fn set_savepoint(&self) {
+        self.pending_data.lock().set_savepoint();
+    }
+
+    // 撤销最近一个savepoint之后暂存的所有操作,并移除这个savepoint
+    pub fn rollback_to_savepoint(&self) -> Result<()> {
+        self.pending_data.lock().rollback_to_savepoint()
+    }
+
+    // 合并暂存数据和engine中已提交数据的迭代器,暂存的数据优先
+    // 暂存的数据在创建时做一次快照,已提交的数据在迭代时从索引中按照前缀分批读取
+    pub fn iter(&self, options: IndexIteratorOptions) -> WriteBatchIterator<'_> {
+        let guard = self.pending_data.lock();
+        let mut pending: Vec<(Vec<u8>, Option<Bytes>)> = guard
+            .iter()
+            .filter(|log_record| log_record.key.starts_with(&options.prefix))
+            .map(|log_record| {
+                let value = match log_record.log_type {
+                    DELETED => None,
+                    _ => Some(Bytes::from(log_record.value.clone())),
+                };
+                (log_record.key.clone(), value)
+            })
+            .collect();
+        pending.sort_by(|a, b| a.0.cmp(&b.0));
+        if options.reverse {
+            pending.reverse();
+        }
+        let mut iter = WriteBatchIterator {
+            pending,
+            pending_idx: 0,
+            committed: VecDeque::new(),
+            committed_bound: None,
+            committed_done: false,
+            options,
+            engine: self.engine,
+        };
+        iter.rewind();
+        iter
+    }
+
     pub(crate) fn encode_key_seqno(key: Bytes, seq_no: usize) -> Vec<u8> {
         let mut encode_key = BytesMut::new();
         encode_length_delimiter(seq_no, &mut encode_key).unwrap();
@@ -84,40 +180,55 @@ impl WriteBatch<'_> {
         if guard.len() > self.options.batch_max_rows as usize {
             return Err(Errors::ExceedBatchMaxRows);
         }
+        self.engine.check_writable()?;
         // 保证串行化提交
         let _lock = self.engine.batch_commit_lock.lock();
-        let mut pos_map = HashMap::new();
+        let _guard = self.engine.conditional_write_lock.read();
         // 维护全局seq_no
-        self.engine.seq_no.fetch_add(1, Ordering::SeqCst);
-        for (_, item) in guard.iter() {
-            let mut log_record = LogRecord {
-                key: WriteBatch::encode_key_seqno(
-                    Bytes::from(item.key.clone()),
-                    self.engine.seq_no.load(Ordering::SeqCst),
-                ),
-                value: item.value.clone(),
-                log_type: item.log_type,
+        let seq_no = self.engine.seq_no.fetch_add(1, atomic::Ordering::SeqCst) + 1;
+        let mut records = Vec::with_capacity(guard.len() + 1);
+        for item in guard.iter() {
+            let (value, log_type) = match item.log_type {
+                NORMAL => self.engine.separate_value(&item.key, item.value.clone())?,
+                _ => (item.value.clone(), item.log_type),
+            };
+            let log_record = LogRecord {
+                key: WriteBatch::encode_key_seqno(Bytes::from(item.key.clone()), seq_no),
+                value,
+                log_type,
             };
-            // 将每一条记录进行写盘
-            let pos = self.engine.append_log(&mut log_record).unwrap();
-            // 现在还不能更新到索引当中，要保证全部写盘成功后才能算成功
-            pos_map.insert(item.key.clone(), pos);
+            records.push(log_record.encode());
         }
         // 最后添加标记,记录我们的事务完成标记
-        let mut log_record = LogRecord {
-            key: WriteBatch::encode_key_seqno(
-                Bytes::from(TXN_FIN),
-                self.engine.seq_no.load(Ordering::SeqCst),
-            ),
-            value: Default::default(),
+        // value记录提交的时间,用于按照时间恢复
+        // 旧版本写入的TXN_FIN的value为空,按照时间恢复时当作时间未知
+        let log_record = LogRecord {
+            key: WriteBatch::encode_key_seqno(Bytes::from(TXN_FIN), seq_no),
+            value: commit_timestamp().to_string().into_bytes(),
             log_type: TXNCOMMITTED,
         };
-        self.engine.append_log(&mut log_record).unwrap();
+        records.push(log_record.encode());
+        // 整个batch作为一个请求写盘,现在还不能更新到索引当中，要保证全部写盘成功后才能算成功
+        let sync =
+            self.engine.options.sync_policy == SyncPolicy::Always || self.options.sync_writes;
+        // 整个batch作为一个事件通知
+        let mut changes = Vec::new();
+        if !self.engine.watchers.is_empty() {
+            for item in guard.iter() {
+                changes.push(Change {
+                    key: Bytes::from(item.key.clone()),
+                    value: match item.log_type {
+                        NORMAL => Some(Bytes::from(item.value.clone())),
+                        _ => None,
+                    },
+                });
+            }
+        }
+        let positions = self.engine.append_logs(records, sync, changes)?;
         // 写入完成后，加载到索引当中来
-        for (_, item) in guard.iter() {
-            let pos = pos_map.get(&item.key).unwrap();
+        for (item, pos) in guard.iter().zip(positions) {
             if item.log_type == LogRecordType::NORMAL {
-                self.engine.indexer.put(item.key.to_vec(), *pos);
+                self.engine.indexer.put(item.key.to_vec(), pos);
                 continue;
             }
 
@@ -131,6 +242,226 @@ impl WriteBatch<'_> {
     }
 }
 
+// 暂存操作的日志,按照操作的先后顺序记录,用于支持savepoint的回滚
+struct StagedOp {
+    key: Vec<u8>,
+    // 为None表示撤销这个key之前暂存的写入
+    log_record: Option<LogRecord>,
+    // 这次操作之前,该key最新一次写入在ops中的下标
+    prev: Option<usize>,
+}
+
+impl StagedOp {
+    fn size(key: &[u8], log_record: &Option<LogRecord>) -> u64 {
+        let value_len = log_record.as_ref().map_or(0, |r| r.value.len());
+        (key.len() + value_len) as u64
+    }
+}
+
+struct PendingWrites {
+    ops: Vec<StagedOp>,
+    // key -> 该key最新一次写入在ops中的下标
+    latest: HashMap<Vec<u8>, usize>,
+    // 每个savepoint记录设置时ops的长度
+    savepoints: Vec<usize>,
+    // ops中暂存的key和value的总字节数
+    size: u64,
+}
+
+impl PendingWrites {
+    fn new() -> Self {
+        PendingWrites {
+            ops: Vec::new(),
+            latest: HashMap::new(),
+            savepoints: Vec::new(),
+            size: 0,
+        }
+    }
+
+    fn stage(&mut self, key: Vec<u8>, log_record: Option<LogRecord>) {
+        let idx = self.ops.len();
+        self.size += StagedOp::size(&key, &log_record);
+        let prev = match log_record {
+            Some(_) => self.latest.insert(key.clone(), idx),
+            None => self.latest.remove(&key),
+        };
+        self.ops.push(StagedOp {
+            key,
+            log_record,
+            prev,
+        });
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&LogRecord> {
+        let idx = self.latest.get(key)?;
+        self.ops[*idx].log_record.as_ref()
+    }
+
+    // 每个key最新一次暂存的写入,按照操作的先后顺序返回
+    fn iter(&self) -> impl Iterator<Item = &LogRecord> {
+        let mut idxs: Vec<usize> = self.latest.values().copied().collect();
+        idxs.sort_unstable();
+        idxs.into_iter()
+            .filter_map(|idx| self.ops[idx].log_record.as_ref())
+    }
+
+    fn len(&self) -> usize {
+        self.latest.len()
+    }
+
+    fn clear(&mut self) {
+        self.ops.clear();
+        self.latest.clear();
+        self.savepoints.clear();
+        self.size = 0;
+    }
+
+    fn set_savepoint(&mut self) {
+        self.savepoints.push(self.ops.len());
+    }
+
+    fn rollback_to_savepoint(&mut self) -> Result<()> {
+        let savepoint = match self.savepoints.pop() {
+            Some(savepoint) => savepoint,
+            None => return Err(Errors::NoSavepoint),
+        };
+        // 倒序撤销,恢复每个key之前的写入
+        while self.ops.len() > savepoint {
+            let op = self.ops.pop().unwrap();
+            self.size -= StagedOp::size(&op.key, &op.log_record);
+            match op.prev {
+                Some(prev) => self.latest.insert(op.key, prev),
+                None => self.latest.remove(&op.key),
+            };
+        }
+        Ok(())
+    }
+}
+
+// WriteBatch的迭代器,按照key的顺序合并暂存的数据和索引中的数据
+pub struct WriteBatchIterator<'a> {
+    // 前缀范围内暂存的写入,按照迭代的顺序排列,value为None表示删除
+    pending: Vec<(Vec<u8>, Option<Bytes>)>,
+    pending_idx: usize,
+    // 从索引中读取的下一批已提交数据
+    committed: VecDeque<(Vec<u8>, LogRecordPos)>,
+    // 下一批数据的边界,正序时是下界(包含),倒序时是上界(不包含),None表示没有上界
+    committed_bound: Option<Vec<u8>>,
+    // 索引中前缀范围内的数据已经读完
+    committed_done: bool,
+    options: IndexIteratorOptions,
+    engine: &'a Engine,
+}
+
+impl WriteBatchIterator<'_> {
+    pub fn seek(&mut self, key: &[u8]) {
+        let reverse = self.options.reverse;
+        self.pending_idx = match self.pending.binary_search_by(|(x, _)| {
+            if reverse {
+                x.as_slice().cmp(key).reverse()
+            } else {
+                x.as_slice().cmp(key)
+            }
+        }) {
+            Ok(idx) => idx,
+            Err(insert_idx) => insert_idx,
+        };
+        self.committed.clear();
+        self.committed_done = false;
+        self.committed_bound = match reverse {
+            false => Some(key.to_vec().max(self.options.prefix.clone())),
+            // 上界不包含,所以从key之后的位置开始
+            true => Some(next_key(key).to_vec()),
+        };
+    }
+
+    pub fn rewind(&mut self) {
+        self.pending_idx = 0;
+        self.committed.clear();
+        self.committed_done = false;
+        self.committed_bound = match self.options.reverse {
+            false => Some(self.options.prefix.clone()),
+            true => prefix_end(&self.options.prefix).map(|end| end.to_vec()),
+        };
+    }
+
+    // 当前这批已提交的数据读完之后,从索引中读取下一批
+    fn fill_committed(&mut self) {
+        if !self.committed.is_empty() || self.committed_done {
+            return;
+        }
+        let prefix = &self.options.prefix;
+        let batch = match self.options.reverse {
+            false => {
+                let start = self.committed_bound.clone().unwrap_or_default();
+                let end = prefix_end(prefix);
+                self.engine
+                    .indexer
+                    .scan(&start, end.as_deref(), ITER_SCAN_BATCH)
+            }
+            true => {
+                let end = match (&self.committed_bound, prefix_end(prefix)) {
+                    (Some(bound), Some(end)) => Some(bound.clone().min(end.to_vec())),
+                    (bound, end) => bound.clone().or(end.map(|end| end.to_vec())),
+                };
+                self.engine
+                    .indexer
+                    .scan_reverse(prefix, end.as_deref(), ITER_SCAN_BATCH)
+            }
+        };
+        if batch.len() < ITER_SCAN_BATCH {
+            self.committed_done = true;
+        }
+        if let Some((last, _)) = batch.last() {
+            self.committed_bound = match self.options.reverse {
+                false => Some(next_key(last).to_vec()),
+                true => Some(last.clone()),
+            };
+        }
+        self.committed.extend(batch);
+    }
+}
+
+// 读取数据文件出错时返回错误,调用方可以选择跳过或者停止迭代
+impl Iterator for WriteBatchIterator<'_> {
+    type Item = Result<(Bytes, Bytes)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.fill_committed();
+            let order = match (self.pending.get(self.pending_idx), self.committed.front()) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((pending, _)), Some((committed, _))) => match self.options.reverse {
+                    false => pending.cmp(committed),
+                    true => pending.cmp(committed).reverse(),
+                },
+            };
+            // 同一个key以暂存的数据为准
+            if order == Ordering::Equal {
+                self.committed.pop_front();
+            }
+            if order != Ordering::Greater {
+                let (key, value) = &self.pending[self.pending_idx];
+                self.pending_idx += 1;
+                match value {
+                    Some(value) => return Some(Ok((Bytes::from(key.clone()), value.clone()))),
+                    // 在batch中删除了
+                    None => continue,
+                }
+            }
+            let (key, pos) = self.committed.pop_front().unwrap();
+            match self.engine.get_value_by_pos(&pos) {
+                Ok(value) => return Some(Ok((Bytes::from(key), value))),
+                // 读取索引之后被并发删除了
+                Err(Errors::KeyNotFound) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod write_batch_test {
     use std::path::PathBuf;
@@ -163,4 +494,291 @@ mod write_batch_test {
         assert_eq!(res4.unwrap(), Bytes::from("value"));
         std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
     }
+
+    #[test]
+    fn test_write_batch_read_your_writes() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/test_write_batch_read");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine.put(Bytes::from("a"), Bytes::from("va")).unwrap();
+        engine.put(Bytes::from("b"), Bytes::from("vb")).unwrap();
+        engine.put(Bytes::from("c"), Bytes::from("vc")).unwrap();
+
+        let write_batch = engine
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        assert!(write_batch.is_empty());
+        // 暂存的数据优先,其次是engine中的数据
+        write_batch
+            .put(Bytes::from("b"), Bytes::from("vb2"))
+            .unwrap();
+        write_batch
+            .put(Bytes::from("d"), Bytes::from("vd"))
+            .unwrap();
+        write_batch.delete(Bytes::from("c")).unwrap();
+        assert_eq!(write_batch.len(), 3);
+        assert_eq!(write_batch.get(Bytes::from("a")).unwrap(), "va");
+        assert_eq!(write_batch.get(Bytes::from("b")).unwrap(), "vb2");
+        assert_eq!(write_batch.get(Bytes::from("d")).unwrap(), "vd");
+        assert_eq!(
+            write_batch.get(Bytes::from("c")).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert_eq!(engine.get(Bytes::from("c")).unwrap(), "vc");
+
+        // 删除一个只在batch中暂存的key
+        write_batch
+            .put(Bytes::from("e"), Bytes::from("ve"))
+            .unwrap();
+        write_batch.delete(Bytes::from("e")).unwrap();
+        assert!(write_batch.get(Bytes::from("e")).is_err());
+        assert_eq!(write_batch.len(), 3);
+
+        // 合并迭代
+        let items: Vec<(Bytes, Bytes)> = write_batch
+            .iter(IndexIteratorOptions::default())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            items,
+            vec![
+                (Bytes::from("a"), Bytes::from("va")),
+                (Bytes::from("b"), Bytes::from("vb2")),
+                (Bytes::from("d"), Bytes::from("vd")),
+            ]
+        );
+        let mut iter = write_batch.iter(IndexIteratorOptions::NewOptions(true, Vec::new()));
+        iter.seek("c".as_bytes());
+        assert_eq!(iter.next().unwrap().unwrap().0, Bytes::from("b"));
+        assert_eq!(iter.next().unwrap().unwrap().0, Bytes::from("a"));
+        assert!(iter.next().is_none());
+
+        // 丢弃后什么都不会提交
+        write_batch.discard();
+        assert!(write_batch.is_empty());
+        write_batch.commit().unwrap();
+        assert_eq!(engine.get(Bytes::from("b")).unwrap(), "vb");
+        assert!(engine.get(Bytes::from("d")).is_err());
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_write_batch_iter_prefix() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/test_write_batch_iter_prefix");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        // 超过一批的已提交数据,以及前缀范围之外的数据
+        for i in 0..2 * ITER_SCAN_BATCH {
+            engine
+                .put(
+                    Bytes::from(format!("p{:04}", i)),
+                    Bytes::from(format!("v{}", i)),
+                )
+                .unwrap();
+        }
+        engine.put(Bytes::from("a"), Bytes::from("va")).unwrap();
+        engine.put(Bytes::from("q"), Bytes::from("vq")).unwrap();
+
+        let write_batch = engine
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        write_batch
+            .put(Bytes::from("p0001"), Bytes::from("new"))
+            .unwrap();
+        write_batch.delete(Bytes::from("p0002")).unwrap();
+        write_batch
+            .put(Bytes::from("p9999"), Bytes::from("v9999"))
+            .unwrap();
+        write_batch
+            .put(Bytes::from("b"), Bytes::from("vb"))
+            .unwrap();
+
+        let keys =
+            |iter: WriteBatchIterator| -> Vec<Bytes> { iter.map(|item| item.unwrap().0).collect() };
+        let mut expected: Vec<Bytes> = (0..2 * ITER_SCAN_BATCH)
+            .filter(|i| *i != 2)
+            .map(|i| Bytes::from(format!("p{:04}", i)))
+            .collect();
+        expected.push(Bytes::from("p9999"));
+        let forward =
+            keys(write_batch.iter(IndexIteratorOptions::NewOptions(false, b"p".to_vec())));
+        assert_eq!(forward, expected);
+        let mut iter = write_batch.iter(IndexIteratorOptions::NewOptions(false, b"p".to_vec()));
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            (Bytes::from("p0000"), Bytes::from("v0"))
+        );
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            (Bytes::from("p0001"), Bytes::from("new"))
+        );
+
+        expected.reverse();
+        let backward =
+            keys(write_batch.iter(IndexIteratorOptions::NewOptions(true, b"p".to_vec())));
+        assert_eq!(backward, expected);
+
+        // seek之后从指定的位置开始
+        let mut iter = write_batch.iter(IndexIteratorOptions::NewOptions(true, b"p".to_vec()));
+        iter.seek(b"p0003");
+        assert_eq!(
+            keys(iter),
+            vec![
+                Bytes::from("p0003"),
+                Bytes::from("p0001"),
+                Bytes::from("p0000")
+            ]
+        );
+        let mut iter = write_batch.iter(IndexIteratorOptions::NewOptions(false, b"p".to_vec()));
+        iter.seek(b"a");
+        assert_eq!(iter.next().unwrap().unwrap().0, Bytes::from("p0000"));
+        iter.seek(b"p0510");
+        assert_eq!(
+            keys(iter),
+            vec![
+                Bytes::from("p0510"),
+                Bytes::from("p0511"),
+                Bytes::from("p9999")
+            ]
+        );
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_write_batch_savepoint() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/test_write_batch_savepoint");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine.put(Bytes::from("a"), Bytes::from("va")).unwrap();
+
+        let write_batch = engine
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        // 没有savepoint时回滚会报错
+        assert_eq!(
+            write_batch.rollback_to_savepoint().err().unwrap(),
+            Errors::NoSavepoint
+        );
+        write_batch
+            .put(Bytes::from("b"), Bytes::from("vb"))
+            .unwrap();
+
+        write_batch.set_savepoint();
+        write_batch
+            .put(Bytes::from("b"), Bytes::from("vb2"))
+            .unwrap();
+        write_batch.delete(Bytes::from("a")).unwrap();
+
+        // 嵌套的savepoint
+        write_batch.set_savepoint();
+        write_batch
+            .put(Bytes::from("c"), Bytes::from("vc"))
+            .unwrap();
+        write_batch.delete(Bytes::from("b")).unwrap();
+        assert!(write_batch.get(Bytes::from("b")).is_err());
+        assert_eq!(write_batch.len(), 2);
+
+        // 回滚内层savepoint
+        write_batch.rollback_to_savepoint().unwrap();
+        assert_eq!(write_batch.get(Bytes::from("b")).unwrap(), "vb2");
+        assert!(write_batch.get(Bytes::from("c")).is_err());
+        assert!(write_batch.get(Bytes::from("a")).is_err());
+
+        // 回滚外层savepoint,之前的值都恢复
+        write_batch.rollback_to_savepoint().unwrap();
+        assert_eq!(write_batch.get(Bytes::from("b")).unwrap(), "vb");
+        assert_eq!(write_batch.get(Bytes::from("a")).unwrap(), "va");
+        assert_eq!(write_batch.len(), 1);
+
+        write_batch.commit().unwrap();
+        assert_eq!(engine.get(Bytes::from("a")).unwrap(), "va");
+        assert_eq!(engine.get(Bytes::from("b")).unwrap(), "vb");
+        assert!(engine.get(Bytes::from("c")).is_err());
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_write_batch_max_bytes() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/test_write_batch_max_bytes");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let mut batch_opts = WriteBatchOptions::default();
+        batch_opts.batch_max_bytes = 16;
+        let write_batch = engine.new_write_batch(batch_opts).unwrap();
+        write_batch
+            .put(Bytes::from("key1"), Bytes::from("value1"))
+            .unwrap();
+        // 超过了大小限制
+        let res = write_batch.put(Bytes::from("key2"), Bytes::from("value2"));
+        assert_eq!(res.err().unwrap(), Errors::ExceedBatchMaxBytes);
+        assert_eq!(write_batch.len(), 1);
+        // 回滚后释放了空间
+        write_batch.set_savepoint();
+        write_batch.put(Bytes::from("k"), Bytes::from("v")).unwrap();
+        write_batch.rollback_to_savepoint().unwrap();
+        write_batch
+            .put(Bytes::from("key3"), Bytes::from("v3"))
+            .unwrap();
+        write_batch.commit().unwrap();
+        assert_eq!(engine.get(Bytes::from("key3")).unwrap(), "v3");
+
+        // 删除的key也计入大小
+        let mut batch_opts = WriteBatchOptions::default();
+        batch_opts.batch_max_bytes = 16;
+        let write_batch = engine.new_write_batch(batch_opts).unwrap();
+        for i in 0..4 {
+            write_batch
+                .delete(Bytes::from(format!("key{}", i)))
+                .unwrap();
+        }
+        let res = write_batch.delete(Bytes::from("key4"));
+        assert_eq!(res.err().unwrap(), Errors::ExceedBatchMaxBytes);
+        let res = write_batch.put(Bytes::from("k"), Bytes::from("v"));
+        assert_eq!(res.err().unwrap(), Errors::ExceedBatchMaxBytes);
+        write_batch.commit().unwrap();
+        assert!(engine.get(Bytes::from("key3")).is_err());
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_write_batch_commit_order() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/test_write_batch_commit_order");
+        opts.file_size_threshlod = 64 * 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let write_batch = engine
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        let keys = ["k5", "k1", "k9", "k3", "k7", "k2"];
+        for key in keys.iter() {
+            write_batch
+                .put(Bytes::from(*key), Bytes::from("value"))
+                .unwrap();
+        }
+        // 重复写入的key按照最后一次写入的位置提交
+        write_batch
+            .put(Bytes::from("k1"), Bytes::from("new value"))
+            .unwrap();
+        write_batch.commit().unwrap();
+
+        // 按照写入顺序检查数据文件中的记录
+        let active_file = engine.data_file.read();
+        let mut offset = 0;
+        let mut logged = Vec::new();
+        while let Ok(read_log_record) = active_file.read_log_record(offset) {
+            offset += read_log_record.size as u64;
+            // 跳过时间标记
+            if read_log_record.logrecord.log_type == TIMESTAMP {
+                continue;
+            }
+            let (key, _) = engine.parse_key(read_log_record.logrecord.key);
+            logged.push(String::from_utf8(key).unwrap());
+        }
+        assert_eq!(logged, vec!["k5", "k9", "k3", "k7", "k2", "k1", "TXN_FIN"]);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
 }